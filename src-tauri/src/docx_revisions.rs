@@ -0,0 +1,308 @@
+use std::io::BufRead;
+
+use quick_xml::events::{BytesEnd, BytesStart, Event};
+
+use crate::options::RevisionAuthor;
+use crate::run_merge::RunMerger;
+
+/// Highest numeric `w:id` in a part. Revisions share their ID space with comments
+/// and bookmarks, so new revisions are numbered above the highest of every story.
+pub fn max_annotation_id<R: BufRead>(source: R) -> u64 {
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut max_id = 0;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                if let Ok(Some(attr)) = e.try_get_attribute("w:id") {
+                    if let Ok(id) = attr.unescape_value().unwrap_or_default().trim().parse::<u64>() {
+                        max_id = max_id.max(id);
+                    }
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    max_id
+}
+
+/// Consecutive runs written as one tracked change: where the first starts and the
+/// last ends, in the source and the output.
+struct RevisionSpan {
+    input: (usize, usize),
+    output: (usize, usize),
+    converted: bool,
+    /// Inside one of the span's runs.
+    in_run: bool,
+    /// The run group ended inside the current run, so the span ends with it.
+    ending: bool,
+}
+
+/// Markup turning a span of runs into a tracked change: what goes before the first
+/// run and after the last, at those output offsets.
+pub struct RevisionWrap {
+    pub start: usize,
+    pub before: Vec<u8>,
+    pub end: usize,
+    pub after: Vec<u8>,
+}
+
+/// Writes converted runs as tracked changes: the original runs deleted, the converted
+/// runs inserted, so reviewers can accept or reject each conversion in Word.
+///
+/// The runs whose text the run merger converts together form one change, as text can
+/// move between them; a change per run would put the pieces of a syllable in separate
+/// insertions. One writer numbers the changes of every story part of a document.
+pub struct RevisionWriter<'o> {
+    author: &'o RevisionAuthor,
+    next_id: u64,
+    span: Option<RevisionSpan>,
+    pub count: usize,
+}
+
+impl<'o> RevisionWriter<'o> {
+    /// `max_id` is the highest annotation ID in the document, from `max_annotation_id`.
+    pub fn new(author: &'o RevisionAuthor, max_id: u64) -> Self {
+        RevisionWriter {
+            author,
+            next_id: max_id + 1,
+            span: None,
+            count: 0,
+        }
+    }
+
+    fn mark(&mut self, name: &str) -> BytesStart<'static> {
+        let mut mark = BytesStart::new(name.to_string());
+        mark.push_attribute(("w:id", self.next_id.to_string().as_str()));
+        mark.push_attribute(("w:author", self.author.author.as_str()));
+        if let Some(date) = self.author.date.as_deref().filter(|date| !date.trim().is_empty()) {
+            mark.push_attribute(("w:date", date));
+        }
+        self.next_id += 1;
+        mark
+    }
+
+    /// A run outside the source's own revisions starts at `input` in the source and
+    /// `output` in the output.
+    pub fn start_run(&mut self, input: usize, output: usize) {
+        let span = self.span.get_or_insert(RevisionSpan {
+            input: (input, input),
+            output: (output, output),
+            converted: false,
+            in_run: false,
+            ending: false,
+        });
+        span.in_run = true;
+    }
+
+    /// Text of the current run is converted.
+    pub fn run_converted(&mut self) {
+        if let Some(span) = self.span.as_mut().filter(|span| span.in_run) {
+            span.converted = true;
+        }
+    }
+
+    /// The current run ends; the tracked change is complete if its run group ended
+    /// inside the run.
+    pub fn end_run(&mut self, source: &[u8], input: usize, output: usize) -> Option<RevisionWrap> {
+        let span = self.span.as_mut().filter(|span| span.in_run)?;
+        span.in_run = false;
+        span.input.1 = input;
+        span.output.1 = output;
+        if span.ending {
+            self.close(source)
+        } else {
+            None
+        }
+    }
+
+    /// The run group ends: the tracked change is complete now between runs, or once
+    /// the current run ends.
+    pub fn end_group(&mut self, source: &[u8]) -> Option<RevisionWrap> {
+        match self.span.as_mut() {
+            Some(span) if span.in_run => {
+                span.ending = true;
+                None
+            }
+            _ => self.close(source),
+        }
+    }
+
+    /// The current run holds a story of its own, such as a text box, and is left out;
+    /// the tracked change ends before it.
+    pub fn leave_run(&mut self, source: &[u8]) -> Option<RevisionWrap> {
+        if let Some(span) = self.span.as_mut() {
+            span.in_run = false;
+        }
+        self.close(source)
+    }
+
+    fn close(&mut self, source: &[u8]) -> Option<RevisionWrap> {
+        use quick_xml::Writer;
+
+        let span = self.span.take().filter(|span| span.converted && span.input.1 > span.input.0)?;
+        let mut writer = Writer::new(Vec::new());
+        let deletion = self.mark("w:del");
+        writer.write_event(Event::Start(deletion)).ok();
+        write_deleted_runs(&mut writer, &source[span.input.0..span.input.1]);
+        writer.write_event(Event::End(BytesEnd::new("w:del"))).ok();
+        let insertion = self.mark("w:ins");
+        writer.write_event(Event::Start(insertion)).ok();
+        self.count += 1;
+        Some(RevisionWrap {
+            start: span.output.0,
+            before: writer.into_inner(),
+            end: span.output.1,
+            after: b"</w:ins>".to_vec(),
+        })
+    }
+}
+
+/// Tracked changes of the source document. Conversions inside them are not written as
+/// revisions of their own, which Word cannot nest.
+const SOURCE_REVISION_MARKS: &[&[u8]] = &[b"w:ins", b"w:del", b"w:moveFrom", b"w:moveTo"];
+
+/// Tracked changes of one story part while it is rewritten: the document's writer, when
+/// the job writes conversions as revisions, and the source's own revisions open around
+/// the current run. Each completed change goes into the run merger's output.
+pub struct PartRevisions<'w, 'o> {
+    writer: Option<&'w mut RevisionWriter<'o>>,
+    source_depth: usize,
+}
+
+impl<'w, 'o> PartRevisions<'w, 'o> {
+    pub fn new(writer: Option<&'w mut RevisionWriter<'o>>) -> Self {
+        PartRevisions { writer, source_depth: 0 }
+    }
+
+    /// Takes note of a start tag; runs inside the source's revisions are left alone.
+    pub fn open(&mut self, name: &[u8]) {
+        if SOURCE_REVISION_MARKS.contains(&name) {
+            self.source_depth += 1;
+        }
+    }
+
+    /// Takes note of an end tag.
+    pub fn close(&mut self, name: &[u8]) {
+        if SOURCE_REVISION_MARKS.contains(&name) {
+            self.source_depth = self.source_depth.saturating_sub(1);
+        }
+    }
+
+    /// A `w:r` starts at `input` in the source and `output` in the output. Runs of a
+    /// story nested in another run, such as a text box, are not tracked.
+    pub fn start_run(&mut self, input: usize, output: usize, nested: bool) {
+        if self.source_depth > 0 || nested {
+            return;
+        }
+        if let Some(writer) = self.writer.as_deref_mut() {
+            writer.start_run(input, output);
+        }
+    }
+
+    /// Text of the current run is converted.
+    pub fn run_converted(&mut self) {
+        if let Some(writer) = self.writer.as_deref_mut() {
+            writer.run_converted();
+        }
+    }
+
+    /// The current `w:r` ends at `input` in the source and `output` in the output.
+    pub fn end_run(&mut self, merger: &mut RunMerger, source: &[u8], input: usize, output: usize) {
+        if let Some(wrap) = self.writer.as_deref_mut().and_then(|writer| writer.end_run(source, input, output)) {
+            // The run group ended in this run; its text is complete before the
+            // tracked change is.
+            merger.flush();
+            wrap_runs(merger, wrap);
+        }
+    }
+
+    /// Ends the run group being merged and the change its runs are written as.
+    pub fn end_group(&mut self, merger: &mut RunMerger, source: &[u8]) {
+        merger.flush();
+        if let Some(wrap) = self.writer.as_deref_mut().and_then(|writer| writer.end_group(source)) {
+            wrap_runs(merger, wrap);
+        }
+    }
+
+    /// The current run holds a story of its own and is left out of the change.
+    pub fn leave_run(&mut self, merger: &mut RunMerger, source: &[u8]) {
+        if let Some(wrap) = self.writer.as_deref_mut().and_then(|writer| writer.leave_run(source)) {
+            wrap_runs(merger, wrap);
+        }
+    }
+}
+
+fn wrap_runs(merger: &mut RunMerger, wrap: RevisionWrap) {
+    merger.insert(wrap.start, wrap.before);
+    merger.insert(wrap.end, wrap.after);
+}
+
+/// Copies the runs of `original` with their text turned into deleted text. Bookmarks
+/// and other marks between the runs stay in the insertion only, so none is doubled.
+fn write_deleted_runs(writer: &mut quick_xml::Writer<Vec<u8>>, original: &[u8]) {
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(original);
+    reader.trim_text(false);
+    let mut buf = Vec::new();
+    let mut run_depth = 0usize;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) if run_depth > 0 || e.name().as_ref() == b"w:r" => {
+                run_depth += 1;
+                let elem = match deleted_name(e.name().as_ref()) {
+                    Some(name) => rename(&e, name),
+                    None => e.into_owned(),
+                };
+                writer.write_event(Event::Start(elem)).ok();
+            }
+            Ok(Event::Empty(e)) if run_depth > 0 || e.name().as_ref() == b"w:r" => {
+                let elem = match deleted_name(e.name().as_ref()) {
+                    Some(name) => rename(&e, name),
+                    None => e.into_owned(),
+                };
+                writer.write_event(Event::Empty(elem)).ok();
+            }
+            Ok(Event::End(e)) if run_depth > 0 => {
+                run_depth -= 1;
+                let end = match deleted_name(e.name().as_ref()) {
+                    Some(name) => BytesEnd::new(name),
+                    None => e.into_owned(),
+                };
+                writer.write_event(Event::End(end)).ok();
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                if run_depth > 0 {
+                    writer.write_event(e.into_owned()).ok();
+                }
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+}
+
+fn deleted_name(name: &[u8]) -> Option<&'static str> {
+    match name {
+        b"w:t" => Some("w:delText"),
+        b"w:instrText" => Some("w:delInstrText"),
+        _ => None,
+    }
+}
+
+fn rename(elem: &BytesStart, name: &'static str) -> BytesStart<'static> {
+    let mut renamed = BytesStart::new(name);
+    renamed.extend_attributes(elem.attributes().flatten());
+    renamed
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use quick_xml::events::BytesStart;
+use zip::ZipArchive;
+
+use crate::font_rules::{FontRule, FontRules};
+use crate::{opc, tag_matches};
+
+/// Guards the `w:basedOn` walk against style cycles in damaged documents.
+const MAX_STYLE_DEPTH: usize = 32;
+
+/// Latin, east-Asian and complex-script typefaces of one theme font collection.
+#[derive(Default)]
+struct ThemeFontCollection {
+    latin: Option<String>,
+    east_asian: Option<String>,
+    complex_script: Option<String>,
+}
+
+/// Major (headings) and minor (body) fonts of a theme part such as
+/// `word/theme/theme1.xml`.
+#[derive(Default)]
+pub struct ThemeFonts {
+    major: ThemeFontCollection,
+    minor: ThemeFontCollection,
+}
+
+impl ThemeFonts {
+    pub fn parse(contents: &[u8]) -> Self {
+        use quick_xml::events::Event;
+        use quick_xml::Reader;
+
+        let mut reader = Reader::from_reader(contents);
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+        let mut theme = ThemeFonts::default();
+        let mut in_major = false;
+        let mut in_minor = false;
+
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    if tag_matches(&name, b"majorFont") {
+                        in_major = true;
+                    } else if tag_matches(&name, b"minorFont") {
+                        in_minor = true;
+                    } else if in_major || in_minor {
+                        let collection = if in_major { &mut theme.major } else { &mut theme.minor };
+                        record_theme_font(&e, collection);
+                    }
+                }
+                Ok(Event::Empty(e)) if in_major || in_minor => {
+                    let collection = if in_major { &mut theme.major } else { &mut theme.minor };
+                    record_theme_font(&e, collection);
+                }
+                Ok(Event::End(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    if tag_matches(&name, b"majorFont") {
+                        in_major = false;
+                    } else if tag_matches(&name, b"minorFont") {
+                        in_minor = false;
+                    }
+                }
+                Ok(Event::Eof) => break,
+                Err(_) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        theme
+    }
+
+    /// Resolves a `w:asciiTheme`-style value such as "minorHAnsi" or "majorBidi".
+    pub fn font(&self, theme_font: &str) -> Option<&str> {
+        let (collection, slot) = if let Some(slot) = theme_font.strip_prefix("major") {
+            (&self.major, slot)
+        } else if let Some(slot) = theme_font.strip_prefix("minor") {
+            (&self.minor, slot)
+        } else {
+            return None;
+        };
+        let font = match slot {
+            "Ascii" | "HAnsi" => &collection.latin,
+            "EastAsia" => &collection.east_asian,
+            "Bidi" => &collection.complex_script,
+            _ => return None,
+        };
+        font.as_deref().filter(|name| !name.is_empty())
+    }
+}
+
+fn record_theme_font(elem: &BytesStart, collection: &mut ThemeFontCollection) {
+    let name = elem.name().as_ref().to_vec();
+    let slot = if tag_matches(&name, b"latin") {
+        &mut collection.latin
+    } else if tag_matches(&name, b"ea") {
+        &mut collection.east_asian
+    } else if tag_matches(&name, b"cs") {
+        &mut collection.complex_script
+    } else {
+        return;
+    };
+    if let Ok(Some(attr)) = elem.try_get_attribute("typeface") {
+        *slot = Some(attr.unescape_value().unwrap_or_default().to_string());
+    }
+}
+
+/// Run font, run size and parent of one `w:style`.
+#[derive(Default)]
+struct StyleDefinition {
+    based_on: Option<String>,
+    font: Option<String>,
+    /// `w:sz` in half-points.
+    size: Option<String>,
+}
+
+/// Style sheet of a WordprocessingML document, used to find the font a run inherits
+/// when it has no `w:rFonts` of its own.
+///
+/// Fonts are resolved the way Word does: direct formatting, then the character style
+/// chain, then the paragraph style chain and finally `w:docDefaults`.
+#[derive(Default)]
+pub struct DocxStyles {
+    theme: ThemeFonts,
+    default_font: Option<String>,
+    default_size: Option<String>,
+    default_paragraph_style: Option<String>,
+    default_character_style: Option<String>,
+    styles: HashMap<String, StyleDefinition>,
+    /// Part names of the style sheet, theme, font table and numbering definitions,
+    /// which hold font definitions themselves.
+    pub styles_part: Option<String>,
+    pub theme_part: Option<String>,
+    pub font_table_part: Option<String>,
+    pub numbering_part: Option<String>,
+}
+
+/// The `w:val` attribute of a WordprocessingML property element.
+pub fn val_attr(elem: &BytesStart) -> Option<String> {
+    elem.try_get_attribute("w:val")
+        .ok()
+        .flatten()
+        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+}
+
+fn is_on(value: &str) -> bool {
+    matches!(value, "1" | "true" | "on")
+}
+
+impl DocxStyles {
+    /// Loads the style sheet and theme linked from `main_part`. Missing parts leave
+    /// the corresponding fonts unset.
+    pub fn load<R: Read + Seek>(archive: &mut ZipArchive<R>, main_part: &str) -> Self {
+        let relationships = opc::read_relationships(archive, main_part);
+        let styles_part = relationships.iter().find(|rel| rel.kind() == "styles").map(|rel| rel.target.clone());
+        let theme_part = relationships.iter().find(|rel| rel.kind() == "theme").map(|rel| rel.target.clone());
+        let font_table_part = relationships.iter().find(|rel| rel.kind() == "fontTable").map(|rel| rel.target.clone());
+        let numbering_part = relationships.iter().find(|rel| rel.kind() == "numbering").map(|rel| rel.target.clone());
+
+        let theme = theme_part
+            .as_deref()
+            .and_then(|part| opc::read_part(archive, part))
+            .map(|contents| ThemeFonts::parse(&contents))
+            .unwrap_or_default();
+
+        let mut styles = DocxStyles {
+            theme,
+            ..DocxStyles::default()
+        };
+        if let Some(contents) = styles_part.as_deref().and_then(|part| opc::read_part(archive, part)) {
+            styles.parse_styles(&contents);
+        }
+        styles.styles_part = styles_part;
+        styles.theme_part = theme_part;
+        styles.font_table_part = font_table_part;
+        styles.numbering_part = numbering_part;
+
+        log::debug!(
+            "DOCX styles: {} definitions, default font {:?}",
+            styles.styles.len(),
+            styles.default_font
+        );
+        styles
+    }
+
+    fn parse_styles(&mut self, contents: &[u8]) {
+        use quick_xml::events::Event;
+        use quick_xml::Reader;
+
+        let mut reader = Reader::from_reader(contents);
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+        let mut in_run_defaults = false;
+        // Conditional table formatting has its own run properties; they are not the
+        // style's font.
+        let mut in_table_condition = false;
+        let mut current: Option<(String, StyleDefinition)> = None;
+
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    match name.as_slice() {
+                        b"w:rPrDefault" => in_run_defaults = true,
+                        b"w:tblStylePr" => in_table_condition = true,
+                        b"w:style" => current = Some(self.start_style(&e)),
+                        b"w:rFonts" => self.record_fonts(&e, in_run_defaults, in_table_condition, &mut current),
+                        _ => {}
+                    }
+                }
+                Ok(Event::Empty(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    match name.as_slice() {
+                        b"w:basedOn" => {
+                            if let Some((_, style)) = current.as_mut() {
+                                style.based_on = val_attr(&e);
+                            }
+                        }
+                        b"w:rFonts" => self.record_fonts(&e, in_run_defaults, in_table_condition, &mut current),
+                        b"w:sz" => {
+                            if in_run_defaults {
+                                self.default_size = val_attr(&e);
+                            } else if let Some((_, style)) = current.as_mut().filter(|_| !in_table_condition) {
+                                style.size = val_attr(&e);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(Event::End(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    match name.as_slice() {
+                        b"w:rPrDefault" => in_run_defaults = false,
+                        b"w:tblStylePr" => in_table_condition = false,
+                        b"w:style" => {
+                            if let Some((id, style)) = current.take() {
+                                self.styles.insert(id, style);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(Event::Eof) => break,
+                Err(_) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+
+    fn start_style(&mut self, elem: &BytesStart) -> (String, StyleDefinition) {
+        let mut id = String::new();
+        let mut style_type = String::new();
+        let mut is_default = false;
+        for attr in elem.attributes().flatten() {
+            let value = attr.unescape_value().unwrap_or_default().to_string();
+            match attr.key.as_ref() {
+                b"w:styleId" => id = value,
+                b"w:type" => style_type = value,
+                b"w:default" => is_default = is_on(&value),
+                _ => {}
+            }
+        }
+        if is_default {
+            match style_type.as_str() {
+                "paragraph" => self.default_paragraph_style = Some(id.clone()),
+                "character" => self.default_character_style = Some(id.clone()),
+                _ => {}
+            }
+        }
+        (id, StyleDefinition::default())
+    }
+
+    fn record_fonts(
+        &mut self,
+        elem: &BytesStart,
+        in_run_defaults: bool,
+        in_table_condition: bool,
+        current: &mut Option<(String, StyleDefinition)>,
+    ) {
+        let font = self.rfonts_font(elem);
+        if in_run_defaults {
+            self.default_font = font;
+        } else if let Some((_, style)) = current.as_mut().filter(|_| !in_table_condition) {
+            style.font = font;
+        }
+    }
+
+    /// Font that a `w:rFonts` element sets for Latin text. Theme references win over
+    /// explicit names, as in Word; `w:hAnsi` covers the upper half of Latin-1 where most
+    /// legacy-encoded text lives.
+    pub fn rfonts_font(&self, elem: &BytesStart) -> Option<String> {
+        let mut fonts: [Option<String>; 4] = Default::default();
+        for attr in elem.attributes().flatten() {
+            let value = attr.unescape_value().unwrap_or_default().to_string();
+            let slot = match attr.key.as_ref() {
+                b"w:asciiTheme" => 0,
+                b"w:ascii" => 1,
+                b"w:hAnsiTheme" => 2,
+                b"w:hAnsi" => 3,
+                _ => continue,
+            };
+            fonts[slot] = Some(value);
+        }
+        let [ascii_theme, ascii, hansi_theme, hansi] = fonts;
+        ascii_theme
+            .and_then(|theme_font| self.theme.font(&theme_font).map(str::to_string))
+            .or(ascii)
+            .or_else(|| hansi_theme.and_then(|theme_font| self.theme.font(&theme_font).map(str::to_string)))
+            .or(hansi)
+    }
+
+    fn style_value(&self, style_id: Option<&str>, field: fn(&StyleDefinition) -> Option<&str>) -> Option<&str> {
+        let mut style_id = style_id?;
+        for _ in 0..MAX_STYLE_DEPTH {
+            let style = self.styles.get(style_id)?;
+            if let Some(value) = field(style) {
+                return Some(value);
+            }
+            style_id = style.based_on.as_deref()?;
+        }
+        None
+    }
+
+    fn inherited_value(
+        &self,
+        run_style: Option<&str>,
+        paragraph_style: Option<&str>,
+        field: fn(&StyleDefinition) -> Option<&str>,
+    ) -> Option<&str> {
+        self.style_value(run_style.or(self.default_character_style.as_deref()), field)
+            .or_else(|| self.style_value(paragraph_style.or(self.default_paragraph_style.as_deref()), field))
+    }
+
+    /// Font a run inherits from its character style, paragraph style and the
+    /// document defaults.
+    pub fn inherited_font(&self, run_style: Option<&str>, paragraph_style: Option<&str>) -> Option<&str> {
+        self.inherited_value(run_style, paragraph_style, |style| style.font.as_deref())
+            .or(self.default_font.as_deref())
+    }
+
+    /// Size in half-points a run inherits, resolved like its font.
+    pub fn inherited_size(&self, run_style: Option<&str>, paragraph_style: Option<&str>) -> Option<&str> {
+        self.inherited_value(run_style, paragraph_style, |style| style.size.as_deref())
+            .or(self.default_size.as_deref())
+    }
+
+    /// Rule for the text of a run, from its own font or the one it inherits.
+    pub fn run_rule<'r>(
+        &self,
+        rules: &'r FontRules,
+        run_font: Option<&str>,
+        run_style: Option<&str>,
+        paragraph_style: Option<&str>,
+    ) -> Option<&'r FontRule> {
+        run_font
+            .or_else(|| self.inherited_font(run_style, paragraph_style))
+            .and_then(|font| rules.find(font))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THEME: &str = r#"<a:theme><a:themeElements><a:fontScheme><a:majorFont><a:latin typeface="Calibri Light"/><a:ea typeface=""/></a:majorFont><a:minorFont><a:latin typeface="Win Innwa"/><a:cs typeface="Arial"/></a:minorFont></a:fontScheme></a:themeElements></a:theme>"#;
+
+    const STYLES: &str = r#"<w:styles><w:docDefaults><w:rPrDefault><w:rPr><w:rFonts w:asciiTheme="minorHAnsi" w:hAnsi="Arial"/><w:sz w:val="22"/></w:rPr></w:rPrDefault></w:docDefaults><w:style w:type="paragraph" w:default="1" w:styleId="Normal"/><w:style w:type="paragraph" w:styleId="Heading"><w:basedOn w:val="Normal"/><w:rPr><w:rFonts w:asciiTheme="majorHAnsi"/><w:sz w:val="32"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Quote"><w:basedOn w:val="Heading"/></w:style><w:style w:type="character" w:styleId="Win"><w:rPr><w:rFonts w:ascii="Win Kalaw"/></w:rPr></w:style><w:style w:type="table" w:styleId="Grid"><w:tblStylePr w:type="firstRow"><w:rPr><w:rFonts w:ascii="Win Kalaw"/></w:rPr></w:tblStylePr></w:style></w:styles>"#;
+
+    fn styles() -> DocxStyles {
+        let mut styles = DocxStyles {
+            theme: ThemeFonts::parse(THEME.as_bytes()),
+            ..DocxStyles::default()
+        };
+        styles.parse_styles(STYLES.as_bytes());
+        styles
+    }
+
+    #[test]
+    fn theme_fonts_resolve_by_slot() {
+        let theme = ThemeFonts::parse(THEME.as_bytes());
+        assert_eq!(theme.font("minorHAnsi"), Some("Win Innwa"));
+        assert_eq!(theme.font("minorBidi"), Some("Arial"));
+        assert_eq!(theme.font("majorAscii"), Some("Calibri Light"));
+        // An empty typeface is no font.
+        assert_eq!(theme.font("majorEastAsia"), None);
+        assert_eq!(theme.font("Win Innwa"), None);
+    }
+
+    #[test]
+    fn runs_inherit_from_character_paragraph_styles_and_defaults() {
+        let styles = styles();
+        // docDefaults name the minor theme font.
+        assert_eq!(styles.inherited_font(None, None), Some("Win Innwa"));
+        assert_eq!(styles.inherited_size(None, None), Some("22"));
+        // Quote is based on Heading, which uses the major theme font.
+        assert_eq!(styles.inherited_font(None, Some("Quote")), Some("Calibri Light"));
+        assert_eq!(styles.inherited_size(None, Some("Quote")), Some("32"));
+        // The character style comes before the paragraph style.
+        assert_eq!(styles.inherited_font(Some("Win"), Some("Heading")), Some("Win Kalaw"));
+        // Conditional table formatting is not the style's font.
+        assert_eq!(styles.inherited_font(None, Some("Grid")), Some("Win Innwa"));
+    }
+
+    #[test]
+    fn run_fonts_prefer_theme_references() {
+        let styles = styles();
+        let rfonts = BytesStart::from_content(r#"w:rFonts w:ascii="Arial" w:asciiTheme="minorHAnsi""#, 8);
+        assert_eq!(styles.rfonts_font(&rfonts).as_deref(), Some("Win Innwa"));
+        let rfonts = BytesStart::from_content(r#"w:rFonts w:hAnsi="Win Kalaw""#, 8);
+        assert_eq!(styles.rfonts_font(&rfonts).as_deref(), Some("Win Kalaw"));
+
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        assert!(styles.run_rule(&rules, None, None, Some("Normal")).is_some());
+        assert!(styles.run_rule(&rules, Some("Arial"), None, Some("Normal")).is_none());
+        assert!(styles.run_rule(&rules, None, None, Some("Heading")).is_none());
+    }
+}
@@ -0,0 +1,332 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::win_to_myanmar3::{review_conversion, win_to_myanmar3, Uncertainty};
+
+pub const DEFAULT_TARGET_FONT: &str = "Myanmar Text";
+
+/// Legacy encoding used to decode text set in a source font.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mapping {
+    /// Win Innwa layout, shared by the rest of the Win font family (Win Kalaw, Win Pyay, ...).
+    #[default]
+    WinInnwa,
+}
+
+impl Mapping {
+    pub fn convert(&self, input: &str) -> String {
+        match self {
+            Mapping::WinInnwa => win_to_myanmar3(input),
+        }
+    }
+
+    /// Whether converting `input` to `output` involved a guess or left text unmapped.
+    pub fn review(&self, input: &str, output: &str) -> Option<Uncertainty> {
+        match self {
+            Mapping::WinInnwa => review_conversion(input, output),
+        }
+    }
+}
+
+/// Font slots of a run. DOCX has all three, PPTX uses latin/cs/ea, XLSX only has one name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FontSlot {
+    Ascii,
+    ComplexScript,
+    EastAsian,
+}
+
+/// Unicode font written in place of a source font. The complex-script and
+/// east-Asian slots fall back to the ASCII font when not set.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetFont {
+    pub ascii: String,
+    #[serde(default)]
+    pub complex_script: Option<String>,
+    #[serde(default)]
+    pub east_asian: Option<String>,
+}
+
+impl Default for TargetFont {
+    fn default() -> Self {
+        TargetFont {
+            ascii: DEFAULT_TARGET_FONT.to_string(),
+            complex_script: None,
+            east_asian: None,
+        }
+    }
+}
+
+impl TargetFont {
+    pub fn slot(&self, slot: FontSlot) -> &str {
+        let font = match slot {
+            FontSlot::Ascii => None,
+            FontSlot::ComplexScript => self.complex_script.as_deref(),
+            FontSlot::EastAsian => self.east_asian.as_deref(),
+        };
+        font.filter(|name| !name.trim().is_empty()).unwrap_or(&self.ascii)
+    }
+}
+
+/// Extra font-name pattern of a rule. Globs support `*` and `?`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum FontPattern {
+    Glob(String),
+    Regex(String),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontRule {
+    pub source_font: String,
+    #[serde(default)]
+    pub mapping: Mapping,
+    /// Overrides the job's target font for this rule.
+    #[serde(default)]
+    pub target_font: Option<TargetFont>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<FontPattern>,
+    /// Compare names exactly as written instead of ignoring case and whitespace.
+    #[serde(default)]
+    pub case_sensitive: bool,
+}
+
+impl FontRule {
+    pub fn target(&self, slot: FontSlot) -> &str {
+        self.target_font
+            .as_ref()
+            .map(|target| target.slot(slot))
+            .unwrap_or(DEFAULT_TARGET_FONT)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchKind {
+    Exact,
+    Normalized,
+    Alias,
+    Pattern,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchedFont {
+    pub font: String,
+    pub rule: String,
+    pub matched_by: MatchKind,
+}
+
+/// "Win Innwa", "WinInnwa" and " WIN  INNWA" all normalise to "wininnwa".
+fn normalize_font_name(name: &str, case_sensitive: bool) -> String {
+    let compact: String = name.chars().filter(|ch| !ch.is_whitespace()).collect();
+    if case_sensitive {
+        compact
+    } else {
+        compact.to_lowercase()
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    for ch in glob.chars() {
+        match ch {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            _ => pattern.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+    pattern.push('$');
+    pattern
+}
+
+struct CompiledRule {
+    rule: FontRule,
+    names: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl CompiledRule {
+    fn compile(rule: FontRule) -> Result<Self, String> {
+        let names = rule
+            .aliases
+            .iter()
+            .map(|alias| normalize_font_name(alias, rule.case_sensitive))
+            .collect();
+
+        let flags = if rule.case_sensitive { "" } else { "(?i)" };
+        let mut patterns = Vec::with_capacity(rule.patterns.len());
+        for pattern in &rule.patterns {
+            let (source, expr) = match pattern {
+                FontPattern::Glob(glob) => (glob, glob_to_regex(glob)),
+                FontPattern::Regex(expr) => (expr, expr.clone()),
+            };
+            let compiled = Regex::new(&format!("{}{}", flags, expr))
+                .map_err(|e| format!("Invalid font pattern \"{}\": {}", source, e))?;
+            patterns.push(compiled);
+        }
+
+        Ok(CompiledRule { rule, names, patterns })
+    }
+
+    fn matches(&self, font: &str) -> Option<MatchKind> {
+        if font == self.rule.source_font {
+            return Some(MatchKind::Exact);
+        }
+        let normalized = normalize_font_name(font, self.rule.case_sensitive);
+        if normalized.is_empty() {
+            return None;
+        }
+        if normalized == normalize_font_name(&self.rule.source_font, self.rule.case_sensitive) {
+            return Some(MatchKind::Normalized);
+        }
+        if self.names.contains(&normalized) {
+            return Some(MatchKind::Alias);
+        }
+        let trimmed = font.split_whitespace().collect::<Vec<_>>().join(" ");
+        if self.patterns.iter().any(|pattern| pattern.is_match(&trimmed)) {
+            return Some(MatchKind::Pattern);
+        }
+        None
+    }
+}
+
+/// The source-font rules of one conversion job, checked in order.
+///
+/// Lookups are cached per font name, which also records which rule matched
+/// each font for the conversion report.
+pub struct FontRules {
+    rules: Vec<CompiledRule>,
+    lookups: RefCell<BTreeMap<String, Option<(usize, MatchKind)>>>,
+}
+
+impl FontRules {
+    /// Compiles `rules`; rules without their own target font use `target_font`.
+    pub fn new(rules: Vec<FontRule>, target_font: &TargetFont) -> Result<Self, String> {
+        let rules = rules
+            .into_iter()
+            .map(|mut rule| {
+                rule.target_font.get_or_insert_with(|| target_font.clone());
+                rule
+            })
+            .map(CompiledRule::compile)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(FontRules {
+            rules,
+            lookups: RefCell::new(BTreeMap::new()),
+        })
+    }
+
+    /// Returns the first rule that matches `font`.
+    pub fn find(&self, font: &str) -> Option<&FontRule> {
+        if let Some(found) = self.lookups.borrow().get(font) {
+            return found.map(|(idx, _)| &self.rules[idx].rule);
+        }
+
+        let found = self
+            .rules
+            .iter()
+            .enumerate()
+            .find_map(|(idx, compiled)| compiled.matches(font).map(|kind| (idx, kind)));
+        if let Some((idx, kind)) = found {
+            log::debug!("Font \"{}\" matched rule \"{}\" ({:?})", font, self.rules[idx].rule.source_font, kind);
+        }
+        self.lookups.borrow_mut().insert(font.to_string(), found);
+        found.map(|(idx, _)| &self.rules[idx].rule)
+    }
+
+    /// Fonts that matched a rule so far, with the rule and how it matched.
+    pub fn matched_fonts(&self) -> Vec<MatchedFont> {
+        self.lookups
+            .borrow()
+            .iter()
+            .filter_map(|(font, found)| {
+                found.map(|(idx, kind)| MatchedFont {
+                    font: font.clone(),
+                    rule: self.rules[idx].rule.source_font.clone(),
+                    matched_by: kind,
+                })
+            })
+            .collect()
+    }
+
+    /// Mapping used where the input carries no font information (plain text).
+    pub fn default_mapping(&self) -> Mapping {
+        self.rules.first().map(|compiled| compiled.rule.mapping).unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FontRule> {
+        self.rules.iter().map(|compiled| &compiled.rule)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(json: &str) -> FontRules {
+        FontRules::new(serde_json::from_str(json).unwrap(), &TargetFont::default()).unwrap()
+    }
+
+    #[test]
+    fn names_match_by_normalized_name_alias_and_pattern() {
+        let rules = rules(
+            r#"[{"sourceFont":"Win Innwa","aliases":["Innwa"],"patterns":[{"kind":"glob","value":"Win Innwa *"}]},
+                {"sourceFont":"Kalaw","patterns":[{"kind":"regex","value":"^win\\s*kalaw"}]}]"#,
+        );
+        assert_eq!(rules.find("Win Innwa").unwrap().source_font, "Win Innwa");
+        assert_eq!(rules.find(" WIN  INNWA").unwrap().source_font, "Win Innwa");
+        assert_eq!(rules.find("innwa").unwrap().source_font, "Win Innwa");
+        assert_eq!(rules.find("Win  Innwa Bold").unwrap().source_font, "Win Innwa");
+        assert_eq!(rules.find("WIN KALAW 2").unwrap().source_font, "Kalaw");
+        assert!(rules.find("Arial").is_none() && rules.find(" ").is_none());
+
+        let kinds: Vec<(String, MatchKind)> = rules.matched_fonts().into_iter().map(|matched| (matched.font, matched.matched_by)).collect();
+        assert!(kinds.contains(&(" WIN  INNWA".to_string(), MatchKind::Normalized)));
+        assert!(kinds.contains(&("innwa".to_string(), MatchKind::Alias)));
+        assert!(kinds.contains(&("Win  Innwa Bold".to_string(), MatchKind::Pattern)));
+    }
+
+    #[test]
+    fn case_sensitive_rules_compare_names_as_written() {
+        let rules = rules(r#"[{"sourceFont":"Win Innwa","caseSensitive":true,"patterns":[{"kind":"glob","value":"Innwa?"}]}]"#);
+        assert!(rules.find("WinInnwa").is_some());
+        assert!(rules.find("win innwa").is_none());
+        assert!(rules.find("Innwa2").is_some() && rules.find("innwa2").is_none());
+    }
+
+    #[test]
+    fn rules_without_a_target_use_the_job_target() {
+        let target: TargetFont = serde_json::from_str(r#"{"ascii":"Pyidaungsu","complexScript":" "}"#).unwrap();
+        let rules = FontRules::new(
+            serde_json::from_str(r#"[{"sourceFont":"Win Innwa"},{"sourceFont":"Win Kalaw","targetFont":{"ascii":"Padauk","eastAsian":"Noto Sans Myanmar"}}]"#).unwrap(),
+            &target,
+        )
+        .unwrap();
+        let innwa = rules.find("Win Innwa").unwrap();
+        assert_eq!(innwa.target(FontSlot::Ascii), "Pyidaungsu");
+        // A blank slot falls back to the ASCII font.
+        assert_eq!(innwa.target(FontSlot::ComplexScript), "Pyidaungsu");
+        let kalaw = rules.find("Win Kalaw").unwrap();
+        assert_eq!(kalaw.target(FontSlot::ComplexScript), "Padauk");
+        assert_eq!(kalaw.target(FontSlot::EastAsian), "Noto Sans Myanmar");
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        let rules = serde_json::from_str(r#"[{"sourceFont":"Win Innwa","patterns":[{"kind":"regex","value":"("}]}]"#).unwrap();
+        assert!(FontRules::new(rules, &TargetFont::default()).is_err());
+    }
+}
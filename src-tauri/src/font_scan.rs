@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::tag_matches;
+
+#[derive(Clone, Serialize)]
+pub struct FontUsage {
+    pub name: String,
+    pub count: usize,
+}
+
+fn record_font(counts: &mut HashMap<String, usize>, value: &str) {
+    // Theme references such as "+mn-lt" are resolved elsewhere, they are not font names.
+    if value.is_empty() || value.starts_with('+') {
+        return;
+    }
+    *counts.entry(value.to_string()).or_insert(0) += 1;
+}
+
+/// Lists the fonts referenced by the runs and styles of an Office document,
+/// most used first. Plain text files carry no font information.
+pub fn list_document_fonts(source: &Path) -> Result<Vec<FontUsage>, Box<dyn std::error::Error>> {
+    use zip::ZipArchive;
+
+    let extension = source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    if !matches!(extension.as_str(), "docx" | "xlsx" | "pptx") {
+        return Ok(Vec::new());
+    }
+
+    log::debug!("Scanning fonts in: {:?}", source);
+    let source_file = File::open(source)?;
+    let mut archive = ZipArchive::new(source_file)?;
+    let mut counts: HashMap<String, usize> = HashMap::new();
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().to_string();
+        if file.is_dir() || !name.ends_with(".xml") {
+            continue;
+        }
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        log::trace!("Scanning fonts in entry: {}", name);
+        collect_fonts(&contents, name.starts_with("xl/"), &mut counts);
+    }
+
+    let mut fonts: Vec<FontUsage> = counts
+        .into_iter()
+        .map(|(name, count)| FontUsage { name, count })
+        .collect();
+    fonts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    log::debug!("Found {} distinct fonts", fonts.len());
+    Ok(fonts)
+}
+
+fn collect_fonts(contents: &[u8], is_spreadsheet: bool, counts: &mut HashMap<String, usize>) {
+    use quick_xml::events::{BytesStart, Event};
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+
+    // SpreadsheetML uses <name> for other things too (defined names, table columns),
+    // so only count it inside a font or run property block.
+    let mut font_depth = 0usize;
+
+    fn inspect(elem: &BytesStart, is_spreadsheet: bool, font_depth: usize, counts: &mut HashMap<String, usize>) {
+        let name = elem.name().as_ref().to_vec();
+        if name.as_slice() == b"w:rFonts" {
+            let mut seen: Vec<String> = Vec::new();
+            for attr in elem.attributes().flatten() {
+                let key = attr.key.as_ref();
+                let is_font_attr = key == b"w:ascii" || key == b"w:hAnsi" || key == b"w:cs" || key == b"w:eastAsia";
+                if is_font_attr {
+                    let value = attr.unescape_value().unwrap_or_default().to_string();
+                    if !seen.contains(&value) {
+                        record_font(counts, &value);
+                        seen.push(value);
+                    }
+                }
+            }
+        } else if name.as_slice() == b"w:sym" {
+            if let Ok(Some(attr)) = elem.try_get_attribute("w:font") {
+                record_font(counts, &attr.unescape_value().unwrap_or_default());
+            }
+        } else if tag_matches(&name, b"latin") || tag_matches(&name, b"ea") || tag_matches(&name, b"cs") {
+            for attr in elem.attributes().flatten() {
+                let key = attr.key.as_ref();
+                if key == b"typeface" || key.ends_with(b":typeface") {
+                    let value = attr.unescape_value().unwrap_or_default().to_string();
+                    record_font(counts, &value);
+                }
+            }
+        } else if is_spreadsheet && font_depth > 0 && (tag_matches(&name, b"name") || tag_matches(&name, b"rFont")) {
+            for attr in elem.attributes().flatten() {
+                let key = attr.key.as_ref();
+                if key == b"val" || key.ends_with(b":val") {
+                    let value = attr.unescape_value().unwrap_or_default().to_string();
+                    record_font(counts, &value);
+                }
+            }
+        }
+    }
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                if is_spreadsheet && (tag_matches(e.name().as_ref(), b"font") || tag_matches(e.name().as_ref(), b"rPr")) {
+                    font_depth += 1;
+                }
+                inspect(&e, is_spreadsheet, font_depth, counts);
+            }
+            Ok(Event::Empty(e)) => {
+                inspect(&e, is_spreadsheet, font_depth, counts);
+            }
+            Ok(Event::End(e)) if is_spreadsheet && (tag_matches(e.name().as_ref(), b"font") || tag_matches(e.name().as_ref(), b"rPr")) => {
+                font_depth = font_depth.saturating_sub(1);
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+}
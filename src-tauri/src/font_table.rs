@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+
+use quick_xml::events::{BytesEnd, BytesStart, Event};
+
+use crate::font_rules::{FontRules, FontSlot};
+use crate::opc;
+
+/// `w:font` children describing the declared font itself: its alternate name, PANOSE
+/// class, character set, family and code-page signature. They are wrong for the
+/// target font, and a symbol character set keeps Word treating the text as symbols.
+const FONT_METRICS: &[&[u8]] = &[b"w:altName", b"w:panose1", b"w:charset", b"w:family", b"w:sig"];
+
+const EMBEDDED_FONTS: &[&[u8]] = &[b"w:embedRegular", b"w:embedBold", b"w:embedItalic", b"w:embedBoldItalic"];
+
+fn font_name(elem: &BytesStart) -> Option<String> {
+    elem.try_get_attribute("w:name")
+        .ok()
+        .flatten()
+        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+}
+
+fn relationship_id(elem: &BytesStart) -> Option<String> {
+    elem.attributes()
+        .flatten()
+        .find(|attr| attr.key.as_ref().ends_with(b":id"))
+        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+}
+
+/// Settings that have Word embed fonts when it saves, which are cleared once no font
+/// is embedded any more.
+const EMBEDDING_SETTINGS: &[&[u8]] = &[b"w:embedTrueTypeFonts", b"w:saveSubsetFonts"];
+
+/// Relationship IDs of the font files embedded for source fonts in `word/fontTable.xml`,
+/// and whether other fonts are embedded as well.
+pub fn embedded_source_fonts(contents: &[u8], rules: &FontRules) -> (HashSet<String>, bool) {
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut ids = HashSet::new();
+    let mut embeds_others = false;
+    let mut in_source_font = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"w:font" => {
+                in_source_font = font_name(&e).is_some_and(|name| rules.find(&name).is_some());
+            }
+            Ok(Event::Empty(e)) if EMBEDDED_FONTS.contains(&e.name().as_ref()) => {
+                if in_source_font {
+                    ids.extend(relationship_id(&e));
+                } else {
+                    embeds_others = true;
+                }
+            }
+            Ok(Event::End(e)) if e.name().as_ref() == b"w:font" => {
+                in_source_font = false;
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    (ids, embeds_others)
+}
+
+/// Names declared in the font table that no rule replaces.
+fn declared_fonts(contents: &[u8], rules: &FontRules) -> HashSet<String> {
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut names = HashSet::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"w:font" => {
+                if let Some(name) = font_name(&e).filter(|name| rules.find(name).is_none()) {
+                    names.insert(name.to_lowercase());
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    names
+}
+
+/// Rewrites `word/fontTable.xml` so that source fonts are declared as their target
+/// fonts, without the metrics of the old font. Entries whose target is declared
+/// already are dropped.
+///
+/// An entry that embeds the source font is kept as it is unless `remove_embedded`
+/// is set; then its embedding goes too and the caller drops the font parts.
+pub fn rewrite_font_table(contents: &[u8], rules: &FontRules, remove_embedded: bool) -> Vec<u8> {
+    use quick_xml::{Reader, Writer};
+
+    let mut declared = declared_fonts(contents, rules);
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+    let mut buf = Vec::new();
+    // Events of the source-font entry being read, written once it is complete.
+    let mut entry: Option<(String, Vec<Event<'static>>)> = None;
+    let mut depth = 0usize;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let elem = e.into_owned();
+                if let Some((_, events)) = entry.as_mut() {
+                    depth += 1;
+                    events.push(Event::Start(elem));
+                } else if let Some(target) = source_font_target(&elem, rules) {
+                    depth = 1;
+                    entry = Some((target, vec![Event::Start(elem)]));
+                } else {
+                    writer.write_event(Event::Start(elem)).ok();
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                let elem = e.into_owned();
+                if let Some((_, events)) = entry.as_mut() {
+                    events.push(Event::Empty(elem));
+                } else if let Some(target) = source_font_target(&elem, rules) {
+                    write_entry(&mut writer, &target, &[Event::Empty(elem)], remove_embedded, &mut declared);
+                } else {
+                    writer.write_event(Event::Empty(elem)).ok();
+                }
+            }
+            Ok(Event::End(e)) => {
+                let end = e.into_owned();
+                if let Some((_, events)) = entry.as_mut() {
+                    events.push(Event::End(end));
+                    depth -= 1;
+                    if depth == 0 {
+                        if let Some((target, events)) = entry.take() {
+                            write_entry(&mut writer, &target, &events, remove_embedded, &mut declared);
+                        }
+                    }
+                } else {
+                    writer.write_event(Event::End(end)).ok();
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                let event = e.into_owned();
+                if let Some((_, events)) = entry.as_mut() {
+                    events.push(event);
+                } else {
+                    writer.write_event(event).ok();
+                }
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
+
+/// Copies `word/settings.xml` without the settings that embed fonts on save.
+pub fn remove_embedding_settings(contents: &[u8]) -> Vec<u8> {
+    opc::remove_elements(contents, |elem| EMBEDDING_SETTINGS.contains(&elem.name().as_ref()))
+}
+
+fn source_font_target(elem: &BytesStart, rules: &FontRules) -> Option<String> {
+    if elem.name().as_ref() != b"w:font" {
+        return None;
+    }
+    let name = font_name(elem)?;
+    rules.find(&name).map(|rule| rule.target(FontSlot::Ascii).to_string())
+}
+
+fn write_entry(
+    writer: &mut quick_xml::Writer<Vec<u8>>,
+    target: &str,
+    events: &[Event<'static>],
+    remove_embedded: bool,
+    declared: &mut HashSet<String>,
+) {
+    let embeds_font = events.iter().any(|event| match event {
+        Event::Start(e) | Event::Empty(e) => EMBEDDED_FONTS.contains(&e.name().as_ref()),
+        _ => false,
+    });
+    if embeds_font && !remove_embedded {
+        for event in events {
+            writer.write_event(event.clone()).ok();
+        }
+        return;
+    }
+    if !declared.insert(target.to_lowercase()) {
+        log::debug!("Dropping font table entry, {} is declared already", target);
+        return;
+    }
+
+    let mut font = BytesStart::new("w:font");
+    font.push_attribute(("w:name", target));
+    let Some((_, children)) = events.split_first() else {
+        return;
+    };
+    if children.is_empty() {
+        writer.write_event(Event::Empty(font)).ok();
+        return;
+    }
+    writer.write_event(Event::Start(font)).ok();
+    // Children of w:font are all empty elements; `skip` covers extension content.
+    let mut skip = 0usize;
+    for event in &children[..children.len() - 1] {
+        match event {
+            Event::Start(e) if skip > 0 || FONT_METRICS.contains(&e.name().as_ref()) || EMBEDDED_FONTS.contains(&e.name().as_ref()) => {
+                skip += 1;
+            }
+            Event::End(_) if skip > 0 => {
+                skip -= 1;
+            }
+            Event::Empty(e) if FONT_METRICS.contains(&e.name().as_ref()) || EMBEDDED_FONTS.contains(&e.name().as_ref()) => {}
+            _ if skip > 0 => {}
+            _ => {
+                writer.write_event(event.clone()).ok();
+            }
+        }
+    }
+    writer.write_event(Event::End(BytesEnd::new("w:font"))).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> FontRules {
+        FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap()
+    }
+
+    #[test]
+    fn embedded_fonts_are_split_by_rule() {
+        let table = r#"<w:fonts><w:font w:name="Win Innwa"><w:embedRegular r:id="rId1"/><w:embedBold r:id="rId2"/></w:font><w:font w:name="Arial"><w:embedRegular r:id="rId3"/></w:font></w:fonts>"#;
+        let (ids, embeds_others) = embedded_source_fonts(table.as_bytes(), &rules());
+        assert_eq!(ids, HashSet::from(["rId1".to_string(), "rId2".to_string()]));
+        assert!(embeds_others);
+
+        let table = r#"<w:fonts><w:font w:name="Win Innwa"><w:embedRegular r:id="rId1"/></w:font><w:font w:name="Arial"/></w:fonts>"#;
+        assert!(!embedded_source_fonts(table.as_bytes(), &rules()).1);
+    }
+
+    #[test]
+    fn embedding_settings_are_removed() {
+        let settings = r#"<w:settings><w:zoom w:percent="100"/><w:embedTrueTypeFonts/><w:saveSubsetFonts/><w:defaultTabStop w:val="720"/></w:settings>"#;
+        let output = remove_embedding_settings(settings.as_bytes());
+        assert_eq!(String::from_utf8(output).unwrap(), r#"<w:settings><w:zoom w:percent="100"/><w:defaultTabStop w:val="720"/></w:settings>"#);
+    }
+}
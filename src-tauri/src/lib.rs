@@ -1,15 +1,43 @@
+mod docx_revisions;
+mod docx_styles;
+mod font_rules;
+mod font_scan;
+mod font_table;
+mod number_formats;
+mod numbering;
+mod opc;
+mod options;
+mod pptx_styles;
+mod run_merge;
+mod settings;
+mod shared_strings;
 mod win_to_myanmar3;
+mod xlsx_links;
+mod xlsx_parts;
+mod xlsx_selection;
+mod xlsx_styles;
 
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{BufRead, Read, Write};
 use std::path::Path;
-use std::collections::HashSet;
-use win_to_myanmar3::win_to_myanmar3;
-use tauri::{AppHandle, Emitter};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use docx_revisions::{PartRevisions, RevisionWriter};
+use docx_styles::{DocxStyles, ThemeFonts};
+use font_rules::{FontRule, FontRules, FontSlot, MatchedFont, TargetFont};
+use font_scan::FontUsage;
+use number_formats::DigitFormats;
+use options::{ConversionOptions, DigitDisplay, ScalingRule};
+use pptx_styles::{PptxStyles, ShapeStyle, SlideStyles};
+use run_merge::{RunMark, RunMerger};
+use shared_strings::{SharedStringPlan, SharedStringUses};
+use quick_xml::events::BytesStart;
+use tauri::{AppHandle, Emitter, Runtime};
+use xlsx_links::{LinkedCells, LinkedRules};
+use xlsx_parts::{References, ValidationFonts};
+use xlsx_selection::{SheetCells, StyleClones, XlsxSelection};
+use xlsx_styles::{ConditionalFonts, XlsxCellStyles};
 use serde::Serialize;
 
-const TARGET_FONT: &str = "Myanmar Text";
-
 #[derive(Clone, Serialize)]
 struct ProgressEvent {
     current: usize,
@@ -18,7 +46,49 @@ struct ProgressEvent {
     message: String,
 }
 
-fn emit_progress(handle: &AppHandle, current: usize, total: usize, message: &str) {
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConversionReport {
+    matched_fonts: Vec<MatchedFont>,
+    containers: Vec<ContainerUsage>,
+    /// Groups of runs highlighted as uncertain conversions.
+    flagged_runs: usize,
+    /// XLSX numeric cells styled in a source font, which showed Myanmar digits.
+    numeric_cells: usize,
+}
+
+#[derive(Clone, Serialize)]
+struct ContainerUsage {
+    name: String,
+    count: usize,
+}
+
+/// Converted text nodes per kind of container (body, header, text box, ...), the
+/// run groups flagged as uncertain, and the numeric cells set in a source font.
+#[derive(Default)]
+struct ContainerReport {
+    counts: BTreeMap<&'static str, usize>,
+    flagged: usize,
+    numeric_cells: usize,
+}
+
+impl ContainerReport {
+    fn record(&mut self, container: &'static str) {
+        *self.counts.entry(container).or_insert(0) += 1;
+    }
+
+    fn usage(&self) -> Vec<ContainerUsage> {
+        self.counts
+            .iter()
+            .map(|(name, count)| ContainerUsage {
+                name: name.to_string(),
+                count: *count,
+            })
+            .collect()
+    }
+}
+
+fn emit_progress<R: Runtime>(handle: &AppHandle<R>, current: usize, total: usize, message: &str) {
     let percentage = if total > 0 {
         (current as f64 / total as f64) * 100.0
     } else {
@@ -42,8 +112,10 @@ fn convert_file(
     handle: AppHandle,
     source_path: String,
     target_path: String,
-    source_font: String,
-) -> Result<(), String> {
+    rules: Vec<FontRule>,
+    target_font: Option<TargetFont>,
+    options: Option<ConversionOptions>,
+) -> Result<ConversionReport, String> {
     let source = Path::new(&source_path);
     let target = Path::new(&target_path);
 
@@ -51,6 +123,13 @@ fn convert_file(
         return Err("Source file not found.".to_string());
     }
 
+    let target_font = target_font.unwrap_or_else(|| settings::load(&handle).target_font);
+    let rules = FontRules::new(rules, &target_font)?;
+    let options = options.unwrap_or_default();
+    if rules.is_empty() {
+        return Err("Please add at least one font to replace.".to_string());
+    }
+
     let extension = source
         .extension()
         .and_then(|ext| ext.to_str())
@@ -59,24 +138,27 @@ fn convert_file(
 
     log::info!("Starting conversion: {} -> {}", source_path, target_path);
     log::info!("File extension: {}", extension);
-    log::info!("Source font: {}", source_font);
+    for rule in rules.iter() {
+        log::info!("Source font: {} -> {} ({:?})", rule.source_font, rule.target(FontSlot::Ascii), rule.mapping);
+    }
 
+    let mut containers = ContainerReport::default();
     let result = match extension.as_str() {
         "txt" => {
             emit_progress(&handle, 1, 50, "Reading text file...");
-            convert_text_file(&handle, source, target).map_err(|e| e.to_string())
+            convert_text_file(&handle, source, target, &rules).map_err(|e| e.to_string())
         }
         "docx" => {
             emit_progress(&handle, 1, 50, "Reading DOCX file...");
-            convert_office_file(&handle, source, target, &source_font, 50).map_err(|e| e.to_string())
+            convert_office_file(&handle, source, target, &rules, &options, &mut containers, 50).map_err(|e| e.to_string())
         }
         "xlsx" => {
             emit_progress(&handle, 1, 50, "Reading XLSX file...");
-            convert_xlsx_file(&handle, source, target, &source_font, 50).map_err(|e| e.to_string())
+            convert_xlsx_file(&handle, source, target, &rules, &options, &mut containers, 50).map_err(|e| e.to_string())
         }
         "pptx" => {
             emit_progress(&handle, 1, 50, "Reading PPTX file...");
-            convert_office_file(&handle, source, target, &source_font, 50).map_err(|e| e.to_string())
+            convert_office_file(&handle, source, target, &rules, &options, &mut containers, 50).map_err(|e| e.to_string())
         }
         _ => Err("Unsupported file type. Please select txt, docx, xlsx, or pptx.".to_string()),
     };
@@ -88,21 +170,54 @@ fn convert_file(
         log::error!("Conversion failed");
     }
 
-    result
+    result.map(|_| ConversionReport {
+        matched_fonts: rules.matched_fonts(),
+        containers: containers.usage(),
+        flagged_runs: containers.flagged,
+        numeric_cells: containers.numeric_cells,
+    })
 }
 
 #[tauri::command]
 fn convert_text(input: String) -> Result<String, String> {
-    Ok(win_to_myanmar3(&input))
+    Ok(win_to_myanmar3::win_to_myanmar3(&input))
+}
+
+#[tauri::command]
+fn get_default_target_font(handle: AppHandle) -> TargetFont {
+    settings::load(&handle).target_font
+}
+
+#[tauri::command]
+fn set_default_target_font(handle: AppHandle, target_font: TargetFont) -> Result<(), String> {
+    if target_font.ascii.trim().is_empty() {
+        return Err("Target font cannot be empty.".to_string());
+    }
+
+    let mut settings = settings::load(&handle);
+    settings.target_font = target_font;
+    settings::save(&handle, &settings).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_document_fonts(source_path: String) -> Result<Vec<FontUsage>, String> {
+    let source = Path::new(&source_path);
+
+    if !source.exists() {
+        return Err("Source file not found.".to_string());
+    }
+
+    font_scan::list_document_fonts(source).map_err(|e| e.to_string())
 }
 
-fn convert_text_file(handle: &AppHandle, source: &Path, target: &Path) -> Result<(), Box<dyn std::error::Error>> {
+fn convert_text_file<R: Runtime>(handle: &AppHandle<R>, source: &Path, target: &Path, rules: &FontRules) -> Result<(), Box<dyn std::error::Error>> {
     log::debug!("Reading text file: {:?}", source);
     let content = std::fs::read_to_string(source)?;
     emit_progress(handle, 25, 50, "Converting text content...");
 
-    log::debug!("Converting content with win_to_myanmar3");
-    let converted = win_to_myanmar3(&content);
+    let mapping = rules.default_mapping();
+    log::debug!("Converting content with {:?} mapping", mapping);
+    let converted = mapping.convert(&content);
 
     emit_progress(handle, 45, 50, "Writing converted file...");
     log::debug!("Writing converted file to: {:?}", target);
@@ -110,7 +225,84 @@ fn convert_text_file(handle: &AppHandle, source: &Path, target: &Path) -> Result
     Ok(())
 }
 
-fn convert_office_file(handle: &AppHandle, source: &Path, target: &Path, source_font: &str, total_steps: usize) -> Result<(), Box<dyn std::error::Error>> {
+/// Relationship kinds of the WordprocessingML parts that hold story text next to
+/// the main document.
+const DOCX_STORY_KINDS: &[&str] = &["header", "footer", "footnotes", "endnotes", "comments", "glossaryDocument"];
+
+/// Relationship kinds of the SmartArt parts linked from a story: the diagram data and
+/// the drawing Word caches for it, both DrawingML text.
+const DOCX_DIAGRAM_KINDS: &[&str] = &["diagramData", "diagramDrawing"];
+
+/// The main document part and every story part it links to.
+fn docx_story_parts<R: Read + std::io::Seek>(archive: &mut zip::ZipArchive<R>, main_part: &str) -> HashSet<String> {
+    let mut parts: HashSet<String> = opc::read_relationships(archive, main_part)
+        .into_iter()
+        .filter(|rel| DOCX_STORY_KINDS.contains(&rel.kind()))
+        .map(|rel| rel.target)
+        .collect();
+    parts.insert(main_part.to_string());
+    parts
+}
+
+/// Font files embedded for source fonts, which `remove_embedded_fonts` drops.
+#[derive(Default)]
+struct EmbeddedSourceFonts {
+    /// Relationship IDs in the font table, and part names of the font files.
+    ids: HashSet<String>,
+    parts: HashSet<String>,
+    /// The settings part, when no other font stays embedded and the settings that
+    /// embed fonts on save go too.
+    settings_part: Option<String>,
+}
+
+fn docx_embedded_source_fonts<R: Read + std::io::Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    main_part: &str,
+    font_table_part: &str,
+    rules: &FontRules,
+) -> EmbeddedSourceFonts {
+    let Some(contents) = opc::read_part(archive, font_table_part) else {
+        return EmbeddedSourceFonts::default();
+    };
+    let (ids, embeds_others) = font_table::embedded_source_fonts(&contents, rules);
+    if ids.is_empty() {
+        return EmbeddedSourceFonts::default();
+    }
+    let parts = opc::read_relationships(archive, font_table_part)
+        .into_iter()
+        .filter(|rel| ids.contains(&rel.id))
+        .map(|rel| rel.target)
+        .collect();
+    let settings_part = opc::read_relationships(archive, main_part)
+        .into_iter()
+        .find(|rel| rel.kind() == "settings" && !embeds_others)
+        .map(|rel| rel.target);
+    EmbeddedSourceFonts { ids, parts, settings_part }
+}
+
+/// SmartArt parts linked from any of `story_parts`.
+fn docx_diagram_parts<R: Read + std::io::Seek>(archive: &mut zip::ZipArchive<R>, story_parts: &HashSet<String>) -> HashSet<String> {
+    let mut parts = HashSet::new();
+    for story in story_parts {
+        parts.extend(
+            opc::read_relationships(archive, story)
+                .into_iter()
+                .filter(|rel| DOCX_DIAGRAM_KINDS.contains(&rel.kind()))
+                .map(|rel| rel.target),
+        );
+    }
+    parts
+}
+
+fn convert_office_file<R: Runtime>(
+    handle: &AppHandle<R>,
+    source: &Path,
+    target: &Path,
+    rules: &FontRules,
+    options: &ConversionOptions,
+    report: &mut ContainerReport,
+    total_steps: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
     use zip::write::FileOptions;
     use zip::{CompressionMethod, ZipArchive, ZipWriter};
 
@@ -121,9 +313,50 @@ fn convert_office_file(handle: &AppHandle, source: &Path, target: &Path, source_
     let total_files = archive.len();
     log::debug!("Total files in archive: {}", total_files);
 
+    let is_docx = source
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("docx"));
+    let docx_main_part = is_docx.then(|| opc::main_document_part(&mut archive).unwrap_or_else(|| "word/document.xml".to_string()));
+    let (story_parts, docx_styles) = match docx_main_part.as_deref() {
+        Some(main_part) => (docx_story_parts(&mut archive, main_part), DocxStyles::load(&mut archive, main_part)),
+        None => (HashSet::new(), DocxStyles::default()),
+    };
+    let is_pptx = source
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pptx"));
+    let pptx_styles = if is_pptx {
+        let main_part = opc::main_document_part(&mut archive).unwrap_or_else(|| "ppt/presentation.xml".to_string());
+        PptxStyles::load(&mut archive, &main_part)
+    } else {
+        PptxStyles::default()
+    };
+    let diagram_parts = docx_diagram_parts(&mut archive, &story_parts);
+    log::debug!("DOCX story parts: {:?}, SmartArt parts: {:?}", story_parts, diagram_parts);
+    let embedded_fonts = match (docx_main_part.as_deref(), docx_styles.font_table_part.as_deref()) {
+        (Some(main_part), Some(font_table_part)) if options.remove_embedded_fonts => {
+            docx_embedded_source_fonts(&mut archive, main_part, font_table_part, rules)
+        }
+        _ => EmbeddedSourceFonts::default(),
+    };
+    let font_table_rels = docx_styles.font_table_part.as_deref().map(opc::rels_part_name);
+    // Tracked changes share one ID space with the comments, bookmarks and other
+    // annotations of every story part, so one writer numbers them all.
+    let mut revisions = match options.tracked_revisions.as_ref() {
+        Some(author) if is_docx => {
+            let mut max_id = 0;
+            for part in &story_parts {
+                if let Ok(file) = archive.by_name(part) {
+                    max_id = max_id.max(docx_revisions::max_annotation_id(std::io::BufReader::new(file)));
+                }
+            }
+            Some(RevisionWriter::new(author, max_id))
+        }
+        _ => None,
+    };
+
     let target_file = File::create(target)?;
     let mut writer = ZipWriter::new(target_file);
-    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let file_options = FileOptions::default().compression_method(CompressionMethod::Deflated);
 
     for i in 0..archive.len() {
         let progress = 3 + (i * 40 / total_files.max(1));
@@ -133,28 +366,67 @@ fn convert_office_file(handle: &AppHandle, source: &Path, target: &Path, source_
         log::trace!("Processing archive entry: {}", name);
 
         if file.is_dir() {
-            writer.add_directory(name, options)?;
+            writer.add_directory(name, file_options)?;
+            continue;
+        }
+
+        if embedded_fonts.parts.contains(&name) {
+            log::debug!("Removing embedded source font: {}", name);
             continue;
         }
 
         let mut contents = Vec::new();
         file.read_to_end(&mut contents)?;
 
-        let updated = if name == "word/document.xml" {
-            log::debug!("Processing DOCX document.xml");
-            Some(process_docx_xml(handle, &contents, source_font, total_steps))
-        } else if name.starts_with("ppt/slides/") && name.ends_with(".xml") {
-            log::debug!("Processing PPTX slide: {}", name);
-            Some(process_pptx_slide(handle, &contents, source_font, total_steps))
+        let updated = if story_parts.contains(&name) {
+            log::debug!("Processing DOCX part: {}", name);
+            Some(process_docx_xml(handle, &contents, rules, &docx_styles, options, report, revisions.as_mut()))
+        } else if diagram_parts.contains(&name) {
+            log::debug!("Processing DOCX SmartArt: {}", name);
+            Some(process_pptx_slide(handle, &contents, rules, &SlideStyles::default(), options, report, total_steps))
+        } else if docx_styles.styles_part.as_ref() == Some(&name) {
+            log::debug!("Processing DOCX styles: {}", name);
+            Some(rewrite_font_definitions(&contents, &|elem| {
+                (elem.name().as_ref() == b"w:rFonts").then(|| rewrite_font_attrs(elem, rules, &docx_font_slot).0)
+            }))
+        } else if docx_styles.numbering_part.as_ref() == Some(&name) {
+            log::debug!("Processing DOCX numbering: {}", name);
+            Some(numbering::process_numbering_xml(&contents, rules, &docx_styles, options, report))
+        } else if docx_styles.font_table_part.as_ref() == Some(&name) {
+            log::debug!("Processing DOCX font table: {}", name);
+            Some(font_table::rewrite_font_table(&contents, rules, options.remove_embedded_fonts))
+        } else if font_table_rels.as_ref() == Some(&name) && !embedded_fonts.ids.is_empty() {
+            Some(opc::remove_relationships(&contents, &embedded_fonts.ids))
+        } else if name == "[Content_Types].xml" && !embedded_fonts.parts.is_empty() {
+            Some(opc::remove_content_type_overrides(&contents, &embedded_fonts.parts))
+        } else if embedded_fonts.settings_part.as_ref() == Some(&name) {
+            log::debug!("Removing font embedding settings: {}", name);
+            Some(font_table::remove_embedding_settings(&contents))
+        } else if docx_styles.theme_part.as_ref() == Some(&name) {
+            log::debug!("Processing DOCX theme: {}", name);
+            Some(rewrite_theme_fonts(&contents, rules))
+        } else if let Some(slide_styles) = pptx_styles.slide_styles(&name) {
+            log::debug!("Processing PPTX part: {}", name);
+            Some(process_pptx_slide(handle, &contents, rules, &slide_styles, options, report, total_steps))
+        } else if is_pptx && pptx_styles::is_legacy_comments(&name) {
+            log::debug!("Processing PPTX comments: {}", name);
+            let rule = pptx_styles.presentation_styles().inherited_rule(rules, &ShapeStyle::default());
+            Some(process_pptx_comments(&contents, rule, report))
+        } else if pptx_styles.theme_parts.contains(&name) || pptx_styles.presentation_part.as_ref() == Some(&name) {
+            log::debug!("Processing PPTX fonts: {}", name);
+            Some(rewrite_theme_fonts(&contents, rules))
         } else {
             None
         };
 
         let output_bytes = updated.unwrap_or(contents);
-        writer.start_file(name, options)?;
+        writer.start_file(name, file_options)?;
         writer.write_all(&output_bytes)?;
     }
 
+    if let Some(revisions) = revisions.as_ref() {
+        log::debug!("Wrote {} tracked changes", revisions.count);
+    }
     emit_progress(handle, 48, 50, "Finalizing office file...");
     log::debug!("Writing final office file");
     writer.finish()?;
@@ -162,7 +434,73 @@ fn convert_office_file(handle: &AppHandle, source: &Path, target: &Path, source_
     Ok(())
 }
 
-fn convert_xlsx_file(handle: &AppHandle, source: &Path, target: &Path, source_font: &str, _total_steps: usize) -> Result<(), Box<dyn std::error::Error>> {
+/// Parts of a workbook, other than worksheets, that conversion rewrites.
+enum XlsxPart {
+    SharedStrings,
+    Styles,
+    Theme,
+    Workbook,
+    Comments,
+    Table,
+    Chart,
+    PivotCache,
+    AppProperties,
+}
+
+impl XlsxPart {
+    fn of(name: &str, theme_part: Option<&str>, selection: &XlsxSelection, refs: &References) -> Option<Self> {
+        let part = if name == "xl/sharedStrings.xml" {
+            XlsxPart::SharedStrings
+        } else if name == "xl/styles.xml" {
+            XlsxPart::Styles
+        } else if theme_part == Some(name) {
+            XlsxPart::Theme
+        } else if name == "xl/workbook.xml" {
+            XlsxPart::Workbook
+        } else if name.starts_with("xl/comments") && name.ends_with(".xml") && selection.includes_part(name) {
+            XlsxPart::Comments
+        } else if name.starts_with("xl/tables/") && name.ends_with(".xml") {
+            XlsxPart::Table
+        } else if is_xlsx_chart(name) && selection.includes_part(name) {
+            XlsxPart::Chart
+        } else if name.starts_with("xl/pivotCache/pivotCacheDefinition") && !refs.is_empty() {
+            XlsxPart::PivotCache
+        } else if name == "docProps/app.xml" && !refs.is_empty() {
+            XlsxPart::AppProperties
+        } else {
+            return None;
+        };
+        Some(part)
+    }
+}
+
+fn is_xlsx_worksheet(name: &str) -> bool {
+    name.starts_with("xl/worksheets/") && name.ends_with(".xml")
+}
+
+fn is_xlsx_chart(name: &str) -> bool {
+    name.starts_with("xl/charts/chart") && name.ends_with(".xml")
+}
+
+/// Converts a workbook in two passes over the archive. The first reads the small
+/// parts conversion depends on and streams each worksheet to collect the shared
+/// strings, conditional formats and data validations it uses, and the rules of the
+/// cells that table headers and chart series repeat; the second streams
+/// the worksheets through `process_xlsx_worksheet` into the output, rewrites the
+/// other affected parts, and copies every other entry without decompressing it.
+/// Worksheets are never held in memory whole, whatever their size. The other
+/// rewritten parts, sharedStrings.xml among them, are buffered while they are
+/// rewritten, one at a time; the first pass only counts the shared strings.
+fn convert_xlsx_file<R: Runtime>(
+    handle: &AppHandle<R>,
+    source: &Path,
+    target: &Path,
+    rules: &FontRules,
+    options: &ConversionOptions,
+    report: &mut ContainerReport,
+    _total_steps: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::BufReader;
     use zip::write::FileOptions;
     use zip::{CompressionMethod, ZipArchive, ZipWriter};
 
@@ -170,75 +508,169 @@ fn convert_xlsx_file(handle: &AppHandle, source: &Path, target: &Path, source_fo
     let source_file = File::open(source)?;
     let mut archive = ZipArchive::new(source_file)?;
 
-    let mut entries: Vec<(String, Vec<u8>, bool)> = Vec::with_capacity(archive.len());
-
     emit_progress(handle, 3, 50, "Reading XLSX structure...");
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let name = file.name().to_string();
-        let is_dir = file.is_dir();
-        if is_dir {
-            entries.push((name, Vec::new(), true));
-            continue;
-        }
-        let mut contents = Vec::new();
-        file.read_to_end(&mut contents)?;
-        entries.push((name, contents, false));
-    }
+    let mut part_names: Vec<String> = archive.file_names().map(str::to_string).collect();
+    part_names.sort();
 
     emit_progress(handle, 10, 50, "Parsing XLSX styles...");
-    let styles_xml = entries
-        .iter()
-        .find(|(name, _, is_dir)| !is_dir && name == "xl/styles.xml")
-        .map(|(_, data, _)| data.clone())
+    let styles_xml = opc::read_part(&mut archive, "xl/styles.xml").unwrap_or_default();
+
+    // Fonts with a `scheme` follow the workbook theme's major and minor fonts.
+    let theme_part = opc::read_relationships(&mut archive, "xl/workbook.xml")
+        .into_iter()
+        .find(|rel| rel.kind() == "theme")
+        .map(|rel| rel.target);
+    let theme = theme_part
+        .as_deref()
+        .and_then(|part| opc::read_part(&mut archive, part))
+        .map(|contents| ThemeFonts::parse(&contents))
         .unwrap_or_default();
 
-    let (source_font_ids, xf_font_ids) = parse_xlsx_styles(&styles_xml, source_font);
-    log::debug!("Found {} source font IDs, {} XF font IDs", source_font_ids.len(), xf_font_ids.len());
+    let cell_styles = XlsxCellStyles::parse(&styles_xml, rules, &theme);
+    log::debug!(
+        "Found {} source font IDs, {} XF font IDs, {} differential formats",
+        cell_styles.source_font_ids.len(),
+        cell_styles.xf_font_ids.len(),
+        cell_styles.dxf_rules.len()
+    );
+    // With `DigitDisplay::Text`, cells in date and other number formats keep their
+    // values and get a tagged format like with `DigitDisplay::NumberFormat`.
+    let digit_formats = match options.myanmar_digits {
+        Some(display) => DigitFormats::plan(&styles_xml, &cell_styles, display),
+        None => DigitFormats::default(),
+    };
 
-    emit_progress(handle, 15, 50, "Analyzing worksheet data...");
-    let mut shared_indices: HashSet<usize> = HashSet::new();
+    // With a selection, styles.xml is still converted as a whole; cells outside the
+    // selection move to copies of their formats that keep the source font.
+    let selection = XlsxSelection::load(&mut archive, &options.xlsx_selection);
+    let style_clones = if selection.is_active() {
+        StyleClones::plan(&styles_xml, &cell_styles)
+    } else {
+        StyleClones::default()
+    };
+    drop(styles_xml);
 
-    for (name, data, is_dir) in &entries {
-        if *is_dir {
-            continue;
-        }
-        if name.starts_with("xl/worksheets/") && name.ends_with(".xml") {
-            log::trace!("Analyzing worksheet: {}", name);
-            collect_shared_string_indices(data, &source_font_ids, &xf_font_ids, &mut shared_indices);
-        }
+    // Table headers and the cells chart series read, whose text tables and chart
+    // caches repeat.
+    let charts: Vec<&str> = part_names
+        .iter()
+        .map(String::as_str)
+        .filter(|name| is_xlsx_chart(name) && selection.includes_part(name))
+        .collect();
+    let mut linked = LinkedCells::load(&mut archive, &charts);
+
+    emit_progress(handle, 15, 50, "Analyzing worksheet data...");
+    let mut shared_uses = SharedStringUses::default();
+    let mut sheet_plans: HashMap<String, WorksheetPlan> = HashMap::new();
+
+    for name in part_names.iter().filter(|name| is_xlsx_worksheet(name)) {
+        log::trace!("Analyzing worksheet: {}", name);
+        // Conditional formats and data validations follow the cells they apply to,
+        // so each is read in a pass of its own before the cells.
+        let conditional_fonts = ConditionalFonts::scan(BufReader::new(archive.by_name(name)?), &cell_styles);
+        let validation_fonts = ValidationFonts::scan(BufReader::new(archive.by_name(name)?));
+        let cells = selection.cells(name);
+        let source = BufReader::new(archive.by_name(name)?);
+        collect_shared_string_uses(source, &cell_styles, &conditional_fonts, cells, &mut shared_uses, linked.sheet_cells(name));
+        sheet_plans.insert(
+            name.clone(),
+            WorksheetPlan {
+                cells,
+                conditional_fonts,
+                validation_fonts,
+            },
+        );
+    }
+    let shared_count = match archive.by_name("xl/sharedStrings.xml") {
+        Ok(file) => shared_strings::count_shared_strings(BufReader::new(file)),
+        Err(_) => 0,
+    };
+    let shared_plan = shared_uses.plan(shared_count);
+    log::debug!(
+        "Found {} shared string indices to convert, {} to split",
+        shared_plan.convert.len(),
+        shared_plan.splits.len()
+    );
+
+    let mut refs = References::default();
+    if let Some(workbook) = opc::read_part(&mut archive, "xl/workbook.xml") {
+        refs.sheets = xlsx_parts::sheet_renames(&workbook, cell_styles.default_rule());
+        refs.sheets.retain(|(name, _)| selection.includes_sheet(name));
     }
-    log::debug!("Found {} shared string indices to convert", shared_indices.len());
+    refs.columns = linked.column_renames();
+    log::debug!("Renaming sheets {:?} and table columns {:?}", refs.sheets, refs.columns);
 
     emit_progress(handle, 20, 50, "Processing shared strings and styles...");
     let target_file = File::create(target)?;
     let mut writer = ZipWriter::new(target_file);
-    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
-
-    let entry_count = entries.len();
-    for (idx, (name, data, is_dir)) in entries.into_iter().enumerate() {
-        if is_dir {
-            writer.add_directory(&name, options)?;
-            continue;
-        }
+    let file_options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let plans = XlsxPlans {
+        cell_styles: &cell_styles,
+        shared_plan: &shared_plan,
+        refs: &refs,
+        style_clones: &style_clones,
+        digit_formats: &digit_formats,
+    };
+    let entry_count = archive.len();
+    for idx in 0..entry_count {
+        let name = archive.by_index_raw(idx)?.name().to_string();
 
         if idx % 3 == 0 {
             let progress = 20 + ((idx * 25) / entry_count.max(1));
             emit_progress(handle, progress, 50, &format!("Writing entry {}/{}", idx + 1, entry_count));
         }
 
-        let updated = if name == "xl/sharedStrings.xml" {
-            log::debug!("Processing shared strings XML");
-            Some(process_shared_strings(&data, source_font, &shared_indices))
-        } else if name == "xl/styles.xml" {
-            log::debug!("Processing styles XML");
-            Some(process_xlsx_styles(&data, source_font))
-        } else {
-            None
+        if let Some(sheet) = sheet_plans.remove(&name) {
+            log::trace!("Processing worksheet: {}", name);
+            writer.start_file(&name, file_options)?;
+            let source = BufReader::new(archive.by_index(idx)?);
+            process_xlsx_worksheet(source, &mut writer, rules, &plans, sheet, options, report)?;
+            continue;
+        }
+
+        let Some(part) = XlsxPart::of(&name, theme_part.as_deref(), &selection, &refs) else {
+            writer.raw_copy_file(archive.by_index_raw(idx)?)?;
+            continue;
         };
 
-        let output_bytes = updated.unwrap_or(data);
-        writer.start_file(&name, options)?;
+        let mut data = Vec::new();
+        archive.by_index(idx)?.read_to_end(&mut data)?;
+        let output_bytes = match part {
+            XlsxPart::SharedStrings => {
+                log::debug!("Processing shared strings XML");
+                process_shared_strings(&data, rules, &shared_plan)
+            }
+            XlsxPart::Styles => {
+                log::debug!("Processing styles XML");
+                process_xlsx_styles(&data, rules, options, &digit_formats, &style_clones)
+            }
+            XlsxPart::Theme => {
+                log::debug!("Processing XLSX theme: {}", name);
+                rewrite_theme_fonts(&data, rules)
+            }
+            XlsxPart::Workbook => {
+                log::debug!("Processing workbook XML");
+                xlsx_parts::process_workbook(&data, &refs, report)
+            }
+            XlsxPart::Comments => {
+                // Notes hold rich text runs like shared strings; runs in a source font convert.
+                log::trace!("Processing comments: {}", name);
+                process_shared_strings(&data, rules, &SharedStringPlan::default())
+            }
+            XlsxPart::Table => {
+                log::trace!("Processing table: {}", name);
+                xlsx_parts::process_table(&data, &refs, report)
+            }
+            XlsxPart::Chart => {
+                log::trace!("Processing chart: {}", name);
+                let converted = process_pptx_slide(handle, &data, rules, &SlideStyles::default(), options, report, 50);
+                xlsx_parts::process_chart(&converted, &refs, &linked, report)
+            }
+            XlsxPart::PivotCache => xlsx_parts::process_pivot_cache(&data, &refs),
+            XlsxPart::AppProperties => xlsx_parts::process_app_properties(&data, &refs),
+        };
+        writer.start_file(&name, file_options)?;
         writer.write_all(&output_bytes)?;
     }
 
@@ -249,205 +681,1298 @@ fn convert_xlsx_file(handle: &AppHandle, source: &Path, target: &Path, source_fo
     Ok(())
 }
 
-fn process_docx_xml(handle: &AppHandle, contents: &[u8], source_font: &str, _total_steps: usize) -> Vec<u8> {
-    use quick_xml::events::{BytesStart, BytesText, Event};
+fn docx_font_slot(key: &[u8]) -> Option<FontSlot> {
+    match key {
+        b"w:ascii" | b"w:hAnsi" => Some(FontSlot::Ascii),
+        b"w:cs" => Some(FontSlot::ComplexScript),
+        b"w:eastAsia" => Some(FontSlot::EastAsian),
+        _ => None,
+    }
+}
+
+fn is_val_attr(key: &[u8]) -> bool {
+    key == b"val" || key.ends_with(b":val")
+}
+
+fn xlsx_font_slot(key: &[u8]) -> Option<FontSlot> {
+    is_val_attr(key).then_some(FontSlot::Ascii)
+}
+
+fn is_typeface_attr(key: &[u8]) -> bool {
+    key == b"typeface" || key.ends_with(b":typeface")
+}
+
+/// DrawingML names the run font slot by element: `a:latin`, `a:cs` or `a:ea`.
+fn pptx_font_slot(name: &[u8]) -> Option<FontSlot> {
+    if tag_matches(name, b"latin") {
+        Some(FontSlot::Ascii)
+    } else if tag_matches(name, b"cs") {
+        Some(FontSlot::ComplexScript)
+    } else if tag_matches(name, b"ea") {
+        Some(FontSlot::EastAsian)
+    } else {
+        None
+    }
+}
+
+fn tag_matches(name: &[u8], local: &[u8]) -> bool {
+    if name == local {
+        return true;
+    }
+    if name.ends_with(local) {
+        let idx = name.len() - local.len();
+        return idx > 0 && name[idx - 1] == b':';
+    }
+    false
+}
+
+/// Copies `elem`, replacing every font attribute that names a source font with the
+/// target font of its rule for that slot. Returns the copy and the rule matched by
+/// the ASCII slot, which is the font the legacy-encoded text was displayed in.
+fn rewrite_font_attrs<'r>(
+    elem: &BytesStart,
+    rules: &'r FontRules,
+    font_slot: &dyn Fn(&[u8]) -> Option<FontSlot>,
+) -> (BytesStart<'static>, Option<&'r FontRule>) {
+    let tag = String::from_utf8_lossy(elem.name().as_ref()).to_string();
+    let mut new_elem = BytesStart::new(tag);
+    let mut matched = None;
+    for attr in elem.attributes().flatten() {
+        let key = attr.key.as_ref();
+        let value = attr.unescape_value().unwrap_or_default().to_string();
+        let slot = font_slot(key);
+        match slot.and_then(|slot| rules.find(&value).map(|rule| (slot, rule))) {
+            Some((slot, rule)) => {
+                if slot == FontSlot::Ascii {
+                    matched = matched.or(Some(rule));
+                }
+                new_elem.push_attribute((key, rule.target(slot).as_bytes()));
+            }
+            None => new_elem.push_attribute((key, value.as_bytes())),
+        }
+    }
+    (new_elem, matched)
+}
+
+/// Copies a part that holds font definitions but no text, such as a style sheet or
+/// theme, passing every element through `rewrite`.
+fn rewrite_font_definitions(contents: &[u8], rewrite: &dyn Fn(&BytesStart) -> Option<BytesStart<'static>>) -> Vec<u8> {
+    use quick_xml::events::Event;
     use quick_xml::{Reader, Writer};
 
-    log::debug!("Starting DOCX XML processing, size: {} bytes", contents.len());
     let mut reader = Reader::from_reader(contents);
     reader.trim_text(false);
     let mut writer = Writer::new(Vec::with_capacity(contents.len()));
-
     let mut buf = Vec::new();
-    let mut in_run = false;
-    let mut run_has_font = false;
-    let mut event_count = 0usize;
-    let mut last_progress = 0usize;
 
     loop {
-        event_count += 1;
-
-        // Emit progress every 200 events processed (for 2% increments)
-        if event_count % 200 == 0 {
-            let progress = 5 + ((event_count / 200) * 40 / 100);
-            if progress != last_progress && progress <= 45 {
-                emit_progress(handle, progress, 50, &format!("Converting content... ({} nodes processed)", event_count));
-                last_progress = progress;
-            }
-
-            // Allow other events to process
-            std::thread::yield_now();
-        }
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
                 let elem = e.into_owned();
-                let name = elem.name().as_ref().to_vec();
-
-                if name.as_slice() == b"w:r" {
-                    in_run = true;
-                    run_has_font = false;
-                }
-
-                if name.as_slice() == b"w:rFonts" && in_run {
-                    let mut new_elem = BytesStart::new("w:rFonts");
-                    for attr in elem.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_font_attr = key == b"w:hAnsi" || key == b"w:ascii";
-                        if is_font_attr && value == source_font {
-                            run_has_font = true;
-                            new_elem.push_attribute((key, TARGET_FONT.as_bytes()));
-                        } else {
-                            new_elem.push_attribute((key, value.as_bytes()));
-                        }
-                    }
-                    writer.write_event(Event::Start(new_elem)).ok();
-                } else {
-                    writer.write_event(Event::Start(elem)).ok();
-                }
+                let new_elem = rewrite(&elem).unwrap_or(elem);
+                writer.write_event(Event::Start(new_elem)).ok();
             }
             Ok(Event::Empty(e)) => {
                 let elem = e.into_owned();
-                let name = elem.name().as_ref().to_vec();
-
-                if name.as_slice() == b"w:rFonts" && in_run {
-                    let mut new_elem = BytesStart::new("w:rFonts");
-                    for attr in elem.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_font_attr = key == b"w:hAnsi" || key == b"w:ascii";
-                        if is_font_attr && value == source_font {
-                            run_has_font = true;
-                            new_elem.push_attribute((key, TARGET_FONT.as_bytes()));
-                        } else {
-                            new_elem.push_attribute((key, value.as_bytes()));
-                        }
-                    }
-                    writer.write_event(Event::Empty(new_elem)).ok();
-                } else {
-                    writer.write_event(Event::Empty(elem)).ok();
-                }
-            }
-            Ok(Event::Text(e)) => {
-                if in_run && run_has_font {
-                    let text = e.unescape().unwrap_or_default().to_string();
-                    let converted = win_to_myanmar3(&text);
-                    let new_text = BytesText::new(&converted);
-                    writer.write_event(Event::Text(new_text)).ok();
-                } else {
-                    writer.write_event(Event::Text(e.into_owned())).ok();
-                }
-            }
-            Ok(Event::End(e)) => {
-                if e.name().as_ref() == b"w:r" {
-                    in_run = false;
-                    run_has_font = false;
-                }
-                writer.write_event(Event::End(e.into_owned())).ok();
-            }
-            Ok(Event::CData(e)) => {
-                writer.write_event(Event::CData(e.into_owned())).ok();
-            }
-            Ok(Event::Decl(e)) => {
-                writer.write_event(Event::Decl(e.into_owned())).ok();
-            }
-            Ok(Event::PI(e)) => {
-                writer.write_event(Event::PI(e.into_owned())).ok();
-            }
-            Ok(Event::Comment(e)) => {
-                writer.write_event(Event::Comment(e.into_owned())).ok();
-            }
-            Ok(Event::DocType(e)) => {
-                writer.write_event(Event::DocType(e.into_owned())).ok();
+                let new_elem = rewrite(&elem).unwrap_or(elem);
+                writer.write_event(Event::Empty(new_elem)).ok();
             }
             Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
             Err(_) => break,
         }
         buf.clear();
     }
 
-    log::debug!("DOCX XML processing completed, {} events processed", event_count);
-    emit_progress(handle, 46, 50, "Document content processed");
-
     writer.into_inner()
 }
 
-fn process_shared_strings(contents: &[u8], source_font: &str, indices_to_convert: &HashSet<usize>) -> Vec<u8> {
-    use quick_xml::events::{BytesStart, BytesText, Event};
-    use quick_xml::{Reader, Writer};
+/// Swaps source fonts in a theme's major and minor font lists, which cells and
+/// paragraphs that use a theme font follow.
+fn rewrite_theme_fonts(contents: &[u8], rules: &FontRules) -> Vec<u8> {
+    rewrite_font_definitions(contents, &|elem| {
+        pptx_font_slot(elem.name().as_ref())
+            .map(|slot| rewrite_font_attrs(elem, rules, &|key| is_typeface_attr(key).then_some(slot)).0)
+    })
+}
 
-    let mut reader = Reader::from_reader(contents);
-    reader.trim_text(false);
-    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+/// Copies `elem` with the text attribute `key` passed through `convert`.
+fn convert_attr(elem: &BytesStart, key: &[u8], convert: impl Fn(&str) -> String) -> BytesStart<'static> {
+    let tag = String::from_utf8_lossy(elem.name().as_ref()).to_string();
+    let mut new_elem = BytesStart::new(tag);
+    for attr in elem.attributes().flatten() {
+        let value = attr.unescape_value().unwrap_or_default().to_string();
+        let converted = if attr.key.as_ref() == key { convert(&value) } else { value };
+        new_elem.push_attribute((attr.key.as_ref(), converted.as_bytes()));
+    }
+    new_elem
+}
+
+/// Copies `elem` with the numeric attribute `key` passed through `scale`.
+fn scale_attr(elem: &BytesStart, key: &[u8], scale: impl Fn(&str) -> Option<String>) -> BytesStart<'static> {
+    let tag = String::from_utf8_lossy(elem.name().as_ref()).to_string();
+    let mut new_elem = BytesStart::new(tag);
+    for attr in elem.attributes().flatten() {
+        let value = attr.unescape_value().unwrap_or_default().to_string();
+        let scaled = if attr.key.as_ref() == key { scale(&value) } else { None };
+        new_elem.push_attribute((attr.key.as_ref(), scaled.unwrap_or(value).as_bytes()));
+    }
+    new_elem
+}
+
+/// Runs and paragraphs holding text in a source font with a scaling rule, keyed by
+/// their position in document order. Elements are numbered by their start tag, so
+/// nested text-box paragraphs get their own entry.
+#[derive(Default)]
+struct ScaledText<'o> {
+    runs: HashMap<usize, &'o ScalingRule>,
+    /// Only paragraphs whose rule also scales line spacing.
+    paragraphs: HashMap<usize, &'o ScalingRule>,
+    /// Runs that name a font of their own, source font or not; the others inherit one.
+    named_runs: HashSet<usize>,
+}
 
+/// Pre-pass for `process_docx_xml` and `process_pptx_slide`: properties such as the
+/// paragraph spacing or the DrawingML `sz` attribute are written before the run font
+/// that decides whether they need scaling. `run_font` gives the font a run-property
+/// element names, resolved the way the conversion itself resolves it.
+fn scan_scaled_text<'o>(
+    contents: &[u8],
+    rules: &FontRules,
+    options: &'o ConversionOptions,
+    run_tag: &[u8],
+    paragraph_tag: &[u8],
+    run_font: &dyn Fn(&BytesStart) -> Option<String>,
+) -> ScaledText<'o> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut scaled = ScaledText::default();
+    if options.scaling.is_empty() {
+        return scaled;
+    }
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(true);
     let mut buf = Vec::new();
-    let mut in_run = false;
-    let mut run_has_font = false;
-    let mut in_si = false;
-    let mut si_index: usize = 0;
-    let mut convert_si = false;
+    let mut paragraphs: Vec<usize> = Vec::new();
+    let mut runs: Vec<usize> = Vec::new();
+    let mut paragraph_count = 0usize;
+    let mut run_count = 0usize;
 
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(Event::Start(e)) => {
-                let elem = e.into_owned();
-                let name = elem.name().as_ref().to_vec();
-
-                if name.as_slice() == b"si" {
-                    in_si = true;
-                    convert_si = indices_to_convert.contains(&si_index);
-                    si_index += 1;
-                }
-
-                if name.as_slice() == b"r" {
-                    in_run = true;
-                    run_has_font = false;
-                }
-
-                if name.as_slice() == b"rFont" && in_run {
-                    let mut new_elem = BytesStart::new("rFont");
-                    for attr in elem.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_val_attr = key == b"val" || key.ends_with(b":val");
-                        if is_val_attr && value == source_font {
-                            run_has_font = true;
-                            new_elem.push_attribute((key, TARGET_FONT.as_bytes()));
-                        } else {
-                            new_elem.push_attribute((key, value.as_bytes()));
-                        }
-                    }
-                    writer.write_event(Event::Start(new_elem)).ok();
-                } else {
-                    writer.write_event(Event::Start(elem)).ok();
+                let name = e.name().as_ref().to_vec();
+                if name.as_slice() == paragraph_tag {
+                    paragraphs.push(paragraph_count);
+                    paragraph_count += 1;
+                } else if name.as_slice() == run_tag {
+                    runs.push(run_count);
+                    run_count += 1;
+                } else if let Some(font) = run_font(&e) {
+                    record_scaled_text(&font, rules, options, &paragraphs, &runs, &mut scaled);
                 }
             }
             Ok(Event::Empty(e)) => {
+                let name = e.name().as_ref().to_vec();
+                if name.as_slice() == paragraph_tag {
+                    paragraph_count += 1;
+                } else if name.as_slice() == run_tag {
+                    run_count += 1;
+                } else if let Some(font) = run_font(&e) {
+                    record_scaled_text(&font, rules, options, &paragraphs, &runs, &mut scaled);
+                }
+            }
+            Ok(Event::End(e)) => {
+                let name = e.name().as_ref().to_vec();
+                if name.as_slice() == paragraph_tag {
+                    paragraphs.pop();
+                } else if name.as_slice() == run_tag {
+                    runs.pop();
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    scaled
+}
+
+fn record_scaled_text<'o>(
+    font: &str,
+    rules: &FontRules,
+    options: &'o ConversionOptions,
+    paragraphs: &[usize],
+    runs: &[usize],
+    scaled: &mut ScaledText<'o>,
+) {
+    let Some(&run) = runs.last() else {
+        return;
+    };
+    scaled.named_runs.insert(run);
+    let Some(scaling) = rules.find(font).and_then(|rule| options.scaling_for(rule)) else {
+        return;
+    };
+    scaled.runs.entry(run).or_insert(scaling);
+    if let Some(&paragraph) = paragraphs.last().filter(|_| scaling.line_spacing_factor.is_some()) {
+        scaled.paragraphs.entry(paragraph).or_insert(scaling);
+    }
+}
+
+/// Single line spacing in WordprocessingML, in 240ths of a line.
+const DOCX_SINGLE_LINE: f64 = 240.0;
+
+/// `w:pPr` children that come after `w:spacing` in the schema sequence.
+const DOCX_AFTER_SPACING: &[&[u8]] = &[
+    b"w:ind", b"w:contextualSpacing", b"w:mirrorIndents", b"w:suppressOverlap", b"w:jc",
+    b"w:textDirection", b"w:textAlignment", b"w:textboxTightWrap", b"w:outlineLvl",
+    b"w:divId", b"w:cnfStyle", b"w:rPr", b"w:sectPr", b"w:pPrChange",
+];
+
+/// `w:spacing` for a paragraph, scaling the existing line value or single spacing.
+fn docx_line_spacing(existing: Option<&BytesStart>, scaling: &ScalingRule) -> BytesStart<'static> {
+    let mut new_elem = BytesStart::new("w:spacing");
+    let mut line = None;
+    if let Some(elem) = existing {
+        for attr in elem.attributes().flatten() {
+            let value = attr.unescape_value().unwrap_or_default().to_string();
+            match attr.key.as_ref() {
+                b"w:line" => line = value.parse::<f64>().ok(),
+                b"w:lineRule" => {}
+                key => new_elem.push_attribute((key, value.as_bytes())),
+            }
+        }
+    }
+    let rule = existing
+        .and_then(|elem| elem.try_get_attribute("w:lineRule").ok().flatten())
+        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+        .filter(|_| line.is_some())
+        .unwrap_or_else(|| "auto".to_string());
+    let value = line.unwrap_or(DOCX_SINGLE_LINE);
+    let scaled = scaling.scale_line(value).unwrap_or(value as i64);
+    new_elem.push_attribute(("w:line", scaled.to_string().as_str()));
+    new_elem.push_attribute(("w:lineRule", rule.as_str()));
+    new_elem
+}
+
+/// Run children holding the run's text; deleted text is merged and converted like
+/// the rest so that rejecting a revision gives Unicode too.
+const DOCX_TEXT_ELEMENTS: &[&[u8]] = &[b"w:t", b"w:delText", b"m:t"];
+
+/// Paragraph content that may sit between two runs without ending a run group.
+/// Revision marks (`w:ins`, `w:del`) are not among them: text is never merged across
+/// a revision boundary, so accepting or rejecting leaves each side converted on its own.
+const DOCX_RUN_JOINERS: &[&[u8]] = &[
+    b"w:r", b"w:t", b"w:delText", b"w:rPr", b"w:proofErr", b"w:bookmarkStart", b"w:bookmarkEnd",
+    b"w:lastRenderedPageBreak", b"w:permStart", b"w:permEnd", b"m:r", b"m:t", b"m:rPr",
+];
+
+/// Kind of text container opened by `name`, as listed in the conversion report: the
+/// root of each story part and the containers nested inside stories.
+fn text_container(name: &[u8]) -> Option<&'static str> {
+    match name {
+        b"w:document" => Some("body"),
+        b"w:hdr" => Some("header"),
+        b"w:ftr" => Some("footer"),
+        b"w:footnotes" => Some("footnotes"),
+        b"w:endnotes" => Some("endnotes"),
+        b"w:comments" => Some("comments"),
+        b"w:glossaryDocument" => Some("glossary"),
+        b"w:txbxContent" => Some("text box"),
+        b"w:sdtContent" => Some("content control"),
+        b"m:oMath" => Some("equation"),
+        b"w:fldSimple" => Some("field result"),
+        b"w:ins" | b"w:moveTo" => Some("inserted text"),
+        b"w:del" | b"w:moveFrom" => Some("deleted text"),
+        b"p:sld" => Some("slide"),
+        b"p:sldLayout" => Some("slide layout"),
+        b"p:sldMaster" => Some("slide master"),
+        b"p:notes" => Some("notes"),
+        b"p:notesMaster" => Some("notes master"),
+        b"p:handoutMaster" => Some("handout master"),
+        b"p188:cmLst" => Some("comments"),
+        b"dgm:dataModel" | b"dsp:drawing" => Some("SmartArt"),
+        _ => None,
+    }
+}
+
+/// Rule and character of a `w:sym` set in a source font. `w:char` is a hex code,
+/// usually in the private-use range that the mapping folds back to Latin-1.
+fn docx_symbol<'r>(elem: &BytesStart, rules: &'r FontRules) -> Option<(&'r FontRule, String)> {
+    let font = elem.try_get_attribute("w:font").ok().flatten()?;
+    let rule = rules.find(&font.unescape_value().unwrap_or_default())?;
+    let code = elem.try_get_attribute("w:char").ok().flatten()?;
+    let code = u32::from_str_radix(code.unescape_value().unwrap_or_default().trim(), 16).ok()?;
+    char::from_u32(code).map(|ch| (rule, ch.to_string()))
+}
+
+/// Tracks a complex field through its `w:fldChar` marks. The cached result between
+/// "separate" and "end" is listed as a field result in the report.
+fn docx_field_char(elem: &BytesStart, fields: &mut Vec<bool>, containers: &mut Vec<&'static str>) {
+    let kind = elem.try_get_attribute("w:fldCharType").ok().flatten();
+    match kind.as_ref().map(|attr| attr.value.as_ref()) {
+        Some(b"begin") => fields.push(false),
+        Some(b"separate") => {
+            if let Some(in_result) = fields.last_mut().filter(|in_result| !**in_result) {
+                *in_result = true;
+                containers.push("field result");
+            }
+        }
+        Some(b"end") => {
+            let in_result = fields.pop();
+            if in_result == Some(true) {
+                containers.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Language tag written on converted runs by the `set_run_language` option.
+const MYANMAR_LANGUAGE: &str = "my-MM";
+
+/// `w:rPr` children that come after `w:lang` in the schema sequence.
+const DOCX_AFTER_LANG: &[&[u8]] = &[b"w:eastAsianLayout", b"w:specVanish", b"w:oMath", b"w:rPrChange"];
+
+/// `w:rFonts` naming the rule's target font in every slot, with a complex-script hint
+/// so Word shapes the Myanmar text with the `w:cs` font.
+fn docx_target_fonts(rule: &FontRule) -> BytesStart<'static> {
+    let mut fonts = BytesStart::new("w:rFonts");
+    fonts.push_attribute(("w:ascii", rule.target(FontSlot::Ascii)));
+    fonts.push_attribute(("w:hAnsi", rule.target(FontSlot::Ascii)));
+    fonts.push_attribute(("w:eastAsia", rule.target(FontSlot::EastAsian)));
+    fonts.push_attribute(("w:cs", rule.target(FontSlot::ComplexScript)));
+    fonts.push_attribute(("w:hint", "cs"));
+    fonts
+}
+
+/// `w:lang` tagging the run as Myanmar, keeping the east-Asian language of `existing`.
+fn docx_myanmar_lang(existing: Option<&BytesStart>) -> BytesStart<'static> {
+    let mut lang = BytesStart::new("w:lang");
+    lang.push_attribute(("w:val", MYANMAR_LANGUAGE));
+    if let Some(east_asian) = existing.and_then(|elem| elem.try_get_attribute("w:eastAsia").ok().flatten()) {
+        lang.push_attribute(("w:eastAsia", east_asian.unescape_value().unwrap_or_default().as_ref()));
+    }
+    lang.push_attribute(("w:bidi", MYANMAR_LANGUAGE));
+    lang
+}
+
+/// `w:rPr` children that come after `w:highlight` in the schema sequence.
+const DOCX_AFTER_HIGHLIGHT: &[&[u8]] = &[
+    b"w:u", b"w:effect", b"w:bdr", b"w:shd", b"w:fitText", b"w:vertAlign", b"w:rtl", b"w:cs", b"w:em",
+    b"w:lang", b"w:eastAsianLayout", b"w:specVanish", b"w:oMath", b"w:rPrChange",
+];
+
+/// Highlight flagging an uncertain conversion, inside and without a `w:rPr`.
+const DOCX_HIGHLIGHT: &[u8] = br#"<w:highlight w:val="yellow"/>"#;
+const DOCX_HIGHLIGHT_PROPS: &[u8] = br#"<w:rPr><w:highlight w:val="yellow"/></w:rPr>"#;
+
+/// Run properties the options add to the current `w:r`. They go where the schema
+/// puts them: fonts right after `w:rStyle`, a scaled size before `w:highlight`, the
+/// language before the few elements that follow it. The highlight of `flag_uncertain` is only known once the run's
+/// text is converted, so its place is recorded as a mark for the run merger.
+#[derive(Default)]
+struct RunProperties {
+    set_language: bool,
+    flag_uncertain: bool,
+    /// Write the target fonts even without `set_language`, for a symbol's run.
+    target_fonts: bool,
+    /// The first child of the run has been reached.
+    props_seen: bool,
+    /// Inside the run's own `w:rPr`.
+    in_props: bool,
+    fonts_written: bool,
+    lang_written: bool,
+    /// Scaled `w:sz` for a converted run that inherits its size, and whether the run
+    /// has a size of its own or the scaled one is written.
+    scaled_size: Option<String>,
+    size_written: bool,
+    /// The run is highlighted already and is not flagged.
+    highlighted: bool,
+    mark: Option<RunMark>,
+    /// Output range of the run's own `w:rPr`, copied when a symbol is split off the run.
+    props_range: Option<(usize, usize)>,
+    /// Properties of the run that continues after a split-off symbol, opened once the
+    /// run has more content.
+    rest_props: Option<Vec<u8>>,
+}
+
+impl RunProperties {
+    fn new(options: &ConversionOptions) -> Self {
+        RunProperties {
+            set_language: options.set_run_language,
+            flag_uncertain: options.flag_uncertain,
+            ..RunProperties::default()
+        }
+    }
+
+    /// Before the first child of the run; a run without `w:rPr` gets one here.
+    fn first_child(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, rule: Option<&FontRule>, name: &[u8]) {
+        use quick_xml::events::{BytesEnd, Event};
+
+        self.props_seen = true;
+        if name == b"w:rPr" {
+            let start = writer.get_ref().len();
+            self.props_range = Some((start, start));
+            return;
+        }
+        if let Some(rule) = rule.filter(|_| self.set_language || self.target_fonts || self.scaled_size.is_some()) {
+            writer.write_event(Event::Start(BytesStart::new("w:rPr"))).ok();
+            self.finish_props(writer, Some(rule));
+            writer.write_event(Event::End(BytesEnd::new("w:rPr"))).ok();
+        } else if self.flag_uncertain {
+            self.mark = Some((writer.get_ref().len(), DOCX_HIGHLIGHT_PROPS));
+        }
+    }
+
+    /// Before a direct child of the run's `w:rPr`.
+    fn before_props_child(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, rule: Option<&FontRule>, name: &[u8]) {
+        use quick_xml::events::Event;
+
+        let converted = rule.is_some();
+        let font_rule = rule.filter(|_| self.set_language || self.target_fonts);
+        let rule = rule.filter(|_| self.set_language);
+        if let Some(rule) = font_rule.filter(|_| !self.fonts_written && name != b"w:rStyle" && name != b"w:rFonts") {
+            self.fonts_written = true;
+            writer.write_event(Event::Empty(docx_target_fonts(rule))).ok();
+        }
+        if name == b"w:sz" || name == b"w:szCs" {
+            self.size_written = true;
+        } else if !self.size_written && (name == b"w:highlight" || DOCX_AFTER_HIGHLIGHT.contains(&name)) {
+            self.size_written = true;
+            if let Some(size) = self.scaled_size.as_deref().filter(|_| converted) {
+                for tag in ["w:sz", "w:szCs"] {
+                    let mut elem = BytesStart::new(tag);
+                    elem.push_attribute(("w:val", size));
+                    writer.write_event(Event::Empty(elem)).ok();
+                }
+            }
+        }
+        if name == b"w:highlight" {
+            self.highlighted = true;
+        } else if self.flag_uncertain && !self.highlighted && self.mark.is_none() && DOCX_AFTER_HIGHLIGHT.contains(&name) {
+            self.mark = Some((writer.get_ref().len(), DOCX_HIGHLIGHT));
+        }
+        if rule.is_some() && !self.lang_written && DOCX_AFTER_LANG.contains(&name) {
+            self.lang_written = true;
+            writer.write_event(Event::Empty(docx_myanmar_lang(None))).ok();
+        }
+    }
+
+    /// Before the end of the run's `w:rPr`.
+    fn finish_props(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, rule: Option<&FontRule>) {
+        self.before_props_child(writer, rule, b"w:rPrChange");
+        self.in_props = false;
+    }
+
+    /// After the run's own `w:rPr`, ending at output offset `end`.
+    fn props_written(&mut self, end: usize) {
+        if let Some(range) = self.props_range.as_mut() {
+            range.1 = end;
+        }
+    }
+
+    /// Before the next child of a run a symbol was split off: the rest of the run
+    /// goes in a run of its own with a copy of the run's properties.
+    fn reopen_after_symbol(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>) {
+        use quick_xml::events::{BytesEnd, BytesStart, Event};
+
+        let Some(source) = self.rest_props.take() else {
+            return;
+        };
+        writer.write_event(Event::End(BytesEnd::new("w:r"))).ok();
+        writer.write_event(Event::Start(BytesStart::new("w:r"))).ok();
+        let start = writer.get_ref().len();
+        writer.get_mut().extend_from_slice(&source);
+        self.props_range = Some((start, writer.get_ref().len()));
+    }
+
+    /// Whether an empty `w:rPr` has to be written out in full to hold added properties.
+    fn expands_empty_props(&self, rule: Option<&FontRule>) -> bool {
+        ((self.set_language || self.target_fonts || self.scaled_size.is_some()) && rule.is_some()) || self.flag_uncertain
+    }
+
+    /// Replacement for the run's own `w:rFonts` or `w:lang`, once the run's rule is known.
+    fn replace(&mut self, elem: &BytesStart, rule: Option<&FontRule>) -> Option<BytesStart<'static>> {
+        let rule = rule.filter(|_| (self.set_language || self.target_fonts) && self.in_props)?;
+        match elem.name().as_ref() {
+            b"w:rFonts" => {
+                self.fonts_written = true;
+                Some(docx_target_fonts(rule))
+            }
+            b"w:lang" if self.set_language => {
+                self.lang_written = true;
+                Some(docx_myanmar_lang(Some(elem)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Puts a symbol set in a source font in a run of its own, inside a run in another
+/// font whose properties are written already, and returns the symbol run's flag mark.
+/// When the symbol is the run's first content the run's properties give way to the
+/// symbol's; otherwise the run is closed before it. The rest of the run is reopened
+/// by `reopen_after_symbol` only if it has content.
+fn split_symbol_run(
+    writer: &mut quick_xml::Writer<Vec<u8>>,
+    props: &mut RunProperties,
+    options: &ConversionOptions,
+    rule: &FontRule,
+) -> Option<RunMark> {
+    use quick_xml::events::{BytesEnd, BytesStart, Event};
+
+    let source = props
+        .props_range
+        .map(|(start, end)| writer.get_ref()[start..end].to_vec())
+        .unwrap_or_default();
+    match props.props_range {
+        Some((start, end)) if end == writer.get_ref().len() => writer.get_mut().truncate(start),
+        _ => {
+            writer.write_event(Event::End(BytesEnd::new("w:r"))).ok();
+            writer.write_event(Event::Start(BytesStart::new("w:r"))).ok();
+        }
+    }
+    let mut symbol_props = RunProperties::new(options);
+    symbol_props.target_fonts = true;
+    write_symbol_props(writer, &source, &mut symbol_props, rule);
+    props.rest_props = Some(source);
+    symbol_props.mark
+}
+
+/// Writes the `w:rPr` of a run split off for a symbol: a copy of the properties of the
+/// run it was in, `source`, with the symbol rule's target fonts.
+fn write_symbol_props(writer: &mut quick_xml::Writer<Vec<u8>>, source: &[u8], props: &mut RunProperties, rule: &FontRule) {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(false);
+    let mut buf = Vec::new();
+    let mut depth = 0usize;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let elem = e.into_owned();
+                depth += 1;
+                if depth == 1 {
+                    props.first_child(writer, Some(rule), elem.name().as_ref());
+                    props.in_props = true;
+                } else if depth == 2 {
+                    props.before_props_child(writer, Some(rule), elem.name().as_ref());
+                }
+                let elem = props.replace(&elem, Some(rule)).filter(|_| depth == 2).unwrap_or(elem);
+                writer.write_event(Event::Start(elem)).ok();
+            }
+            Ok(Event::Empty(e)) => {
+                let elem = e.into_owned();
+                if depth == 0 {
+                    props.first_child(writer, Some(rule), elem.name().as_ref());
+                    writer.write_event(Event::Start(elem.clone())).ok();
+                    props.finish_props(writer, Some(rule));
+                    writer.write_event(Event::End(elem.to_end().into_owned())).ok();
+                } else {
+                    if depth == 1 {
+                        props.before_props_child(writer, Some(rule), elem.name().as_ref());
+                    }
+                    let elem = props.replace(&elem, Some(rule)).filter(|_| depth == 1).unwrap_or(elem);
+                    writer.write_event(Event::Empty(elem)).ok();
+                }
+            }
+            Ok(Event::End(e)) => {
+                if depth == 1 {
+                    props.finish_props(writer, Some(rule));
+                }
+                depth = depth.saturating_sub(1);
+                writer.write_event(Event::End(e.into_owned())).ok();
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+    if !props.props_seen {
+        // The run had no properties of its own.
+        props.first_child(writer, Some(rule), b"w:t");
+    }
+}
+
+/// Word's run size, in half-points, when neither the styles nor the document
+/// defaults set one.
+const DOCX_DEFAULT_SIZE: &str = "20";
+
+/// Scaled size for a converted run without a `w:sz` of its own, from the size it
+/// inherits; `None` when its font pair is not scaled.
+fn docx_scaled_size(
+    styles: &DocxStyles,
+    options: &ConversionOptions,
+    rule: Option<&FontRule>,
+    run_style: Option<&str>,
+    paragraph_style: Option<&str>,
+) -> Option<String> {
+    let scaling = options.scaling_for(rule?)?;
+    scaling.scale_size(styles.inherited_size(run_style, paragraph_style).unwrap_or(DOCX_DEFAULT_SIZE))
+}
+
+/// Inputs shared by every element of a DOCX part being rewritten.
+struct DocxContext<'a> {
+    rules: &'a FontRules,
+    styles: &'a DocxStyles,
+    options: &'a ConversionOptions,
+}
+
+/// Writes a `w:pPr` holding only the line spacing of a scaled paragraph.
+fn write_docx_ppr_spacing(writer: &mut quick_xml::Writer<Vec<u8>>, scaling: &ScalingRule) {
+    use quick_xml::events::{BytesEnd, Event};
+
+    writer.write_event(Event::Start(BytesStart::new("w:pPr"))).ok();
+    writer.write_event(Event::Empty(docx_line_spacing(None, scaling))).ok();
+    writer.write_event(Event::End(BytesEnd::new("w:pPr"))).ok();
+}
+
+/// Line spacing of the paragraphs with scaled runs, written into each paragraph's
+/// `w:pPr` where the schema puts `w:spacing`.
+#[derive(Default)]
+struct DocxParagraphSpacing<'o> {
+    /// Line spacing still to write for the paragraph just opened, before its first child.
+    pending_paragraph: Option<&'o ScalingRule>,
+    /// Line spacing to apply inside the current w:pPr, until its w:spacing is written.
+    pending_spacing: Option<&'o ScalingRule>,
+    ppr_depth: usize,
+}
+
+impl<'o> DocxParagraphSpacing<'o> {
+    /// A `w:p` starts; `scaling` is set when the paragraph has scaled runs.
+    fn open_paragraph(&mut self, scaling: Option<&'o ScalingRule>) {
+        self.pending_paragraph = scaling;
+    }
+
+    /// Before the start tag `name` is written.
+    fn start(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, name: &[u8]) {
+        use quick_xml::events::Event;
+
+        if let Some(scaling) = self.pending_paragraph.take() {
+            if name == b"w:pPr" {
+                self.pending_spacing = Some(scaling);
+                self.ppr_depth = 1;
+            } else {
+                write_docx_ppr_spacing(writer, scaling);
+            }
+        } else if self.ppr_depth > 0 {
+            self.ppr_depth += 1;
+            if self.ppr_depth == 2 && DOCX_AFTER_SPACING.contains(&name) {
+                if let Some(scaling) = self.pending_spacing.take() {
+                    writer.write_event(Event::Empty(docx_line_spacing(None, scaling))).ok();
+                }
+            }
+        }
+    }
+
+    /// Before the empty element `elem` is written. An empty `w:pPr` that gets the
+    /// spacing is written here in full; returns whether it was.
+    fn empty(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, elem: &BytesStart) -> bool {
+        use quick_xml::events::Event;
+
+        let name = elem.name();
+        if let Some(scaling) = self.pending_paragraph.take() {
+            if name.as_ref() == b"w:pPr" {
+                writer.write_event(Event::Start(elem.clone())).ok();
+                writer.write_event(Event::Empty(docx_line_spacing(None, scaling))).ok();
+                writer.write_event(Event::End(elem.to_end().into_owned())).ok();
+                return true;
+            }
+            write_docx_ppr_spacing(writer, scaling);
+        }
+        if self.ppr_depth == 1 && DOCX_AFTER_SPACING.contains(&name.as_ref()) {
+            if let Some(scaling) = self.pending_spacing.take() {
+                writer.write_event(Event::Empty(docx_line_spacing(None, scaling))).ok();
+            }
+        }
+        false
+    }
+
+    /// Before an end tag is written.
+    fn end(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>) {
+        use quick_xml::events::Event;
+
+        if let Some(scaling) = self.pending_paragraph.take() {
+            write_docx_ppr_spacing(writer, scaling);
+        }
+        if self.ppr_depth > 0 {
+            self.ppr_depth -= 1;
+            if self.ppr_depth == 0 {
+                if let Some(scaling) = self.pending_spacing.take() {
+                    writer.write_event(Event::Empty(docx_line_spacing(None, scaling))).ok();
+                }
+            }
+        }
+    }
+
+    /// Replacement for the paragraph's own `w:spacing`, an empty element or a start tag.
+    fn rewrite(&mut self, elem: &BytesStart, empty: bool) -> Option<BytesStart<'static>> {
+        let depth = if empty { 1 } else { 2 };
+        if elem.name().as_ref() != b"w:spacing" || self.ppr_depth != depth {
+            return None;
+        }
+        let scaling = self.pending_spacing.take()?;
+        Some(docx_line_spacing(Some(elem), scaling))
+    }
+}
+
+/// State of the run around a text box, restored when the text box ends: text box
+/// content is a story of its own inside a run of the paragraph.
+struct OuterRun<'r> {
+    rule: Option<&'r FontRule>,
+    font: Option<String>,
+    style: Option<String>,
+    format: Vec<u8>,
+}
+
+/// The `w:r` or `m:r` being rewritten and the rule its text converts with.
+#[derive(Default)]
+struct DocxRun<'r> {
+    active: bool,
+    rule: Option<&'r FontRule>,
+    /// Font set directly on the run and its character style; runs without a font of
+    /// their own inherit one through the styles.
+    font: Option<String>,
+    style: Option<String>,
+    /// The run's properties, which key the group of runs its text is converted with.
+    format: Vec<u8>,
+    rpr_depth: usize,
+    in_text: bool,
+    /// End of the current text element's start tag when it lacks xml:space="preserve".
+    text_space_at: Option<usize>,
+    props: RunProperties,
+    /// Inside a w:rPrChange, whose fonts are the run's formatting before the revision.
+    in_rpr_change: bool,
+}
+
+impl<'r> DocxRun<'r> {
+    /// A run starts with the properties the options add to it; its rule is resolved
+    /// by `resolve`.
+    fn start(&mut self, props: RunProperties) {
+        self.active = true;
+        self.rule = None;
+        self.font = None;
+        self.style = None;
+        self.format.clear();
+        self.props = props;
+    }
+
+    /// Resolves the rule and scaled size of the run from its font, its character
+    /// style and the paragraph style.
+    fn resolve(&mut self, context: &DocxContext<'r>, paragraph_style: Option<&str>) {
+        let (styles, options) = (context.styles, context.options);
+        self.rule = styles.run_rule(context.rules, self.font.as_deref(), self.style.as_deref(), paragraph_style);
+        self.props.scaled_size = docx_scaled_size(styles, options, self.rule, self.style.as_deref(), paragraph_style);
+    }
+
+    fn end(&mut self) {
+        self.active = false;
+        self.rule = None;
+        // A symbol ended the run; nothing of the run is left to reopen.
+        self.props.rest_props = None;
+    }
+
+    fn enter_text_box(&mut self) -> OuterRun<'r> {
+        self.active = false;
+        OuterRun {
+            rule: self.rule.take(),
+            font: self.font.take(),
+            style: self.style.take(),
+            format: std::mem::take(&mut self.format),
+        }
+    }
+
+    fn leave_text_box(&mut self, outer: OuterRun<'r>) {
+        self.active = true;
+        self.rule = outer.rule;
+        self.font = outer.font;
+        self.style = outer.style;
+        self.format = outer.format;
+        self.props = RunProperties::default();
+    }
+
+    /// Replacement for a font, language or size element of the run, which updates the
+    /// run's rule. `direct_child` is set for the children of the run's own `w:rPr`.
+    fn rewrite_prop(
+        &mut self,
+        elem: &BytesStart,
+        direct_child: bool,
+        context: &DocxContext<'r>,
+        paragraph_style: Option<&str>,
+    ) -> Option<BytesStart<'static>> {
+        let name = elem.name();
+        let name = name.as_ref();
+        if name == b"w:rFonts" && self.in_rpr_change {
+            // Fonts from before a formatting revision; rejecting it must not put
+            // the legacy font back on converted text.
+            return Some(rewrite_font_attrs(elem, context.rules, &docx_font_slot).0);
+        }
+        if name == b"w:rFonts" && self.active {
+            let (new_elem, _) = rewrite_font_attrs(elem, context.rules, &docx_font_slot);
+            self.font = context.styles.rfonts_font(elem).or(self.font.take());
+            self.resolve(context, paragraph_style);
+            return Some(self.props.replace(elem, self.rule).filter(|_| direct_child).unwrap_or(new_elem));
+        }
+        if let Some(new_elem) = self.props.replace(elem, self.rule).filter(|_| direct_child) {
+            return Some(new_elem);
+        }
+        if (name == b"w:sz" || name == b"w:szCs") && self.active && direct_child && !self.in_rpr_change {
+            // Sizes inside a formatting revision are the old formatting and stay as they were.
+            let scaling = self.rule.and_then(|rule| context.options.scaling_for(rule))?;
+            return Some(scale_attr(elem, b"w:val", |value| scaling.scale_size(value)));
+        }
+        None
+    }
+}
+
+/// Font of the content control whose properties are being read, for its list items.
+#[derive(Default)]
+struct ContentControl<'r> {
+    in_props: bool,
+    rule: Option<&'r FontRule>,
+}
+
+impl<'r> ContentControl<'r> {
+    /// Replacement for the font or a list item of the content control's properties.
+    fn rewrite(&mut self, elem: &BytesStart, context: &DocxContext<'r>, report: &mut ContainerReport) -> Option<BytesStart<'static>> {
+        if !self.in_props {
+            return None;
+        }
+        match elem.name().as_ref() {
+            b"w:rFonts" => {
+                self.rule = context.styles.rfonts_font(elem).and_then(|font| context.rules.find(&font));
+                Some(rewrite_font_attrs(elem, context.rules, &docx_font_slot).0)
+            }
+            b"w:listItem" => {
+                let rule = self.rule?;
+                report.record("content control");
+                Some(convert_attr(elem, b"w:displayText", |value| rule.mapping.convert(value)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Writes a `w:sym` set in a source font as run text, converted along with the text
+/// around it. A run in another font whose properties are written already is split
+/// around the symbol.
+fn write_docx_symbol<'r>(
+    writer: &mut quick_xml::Writer<Vec<u8>>,
+    run: &mut DocxRun<'r>,
+    merger: &mut RunMerger<'r>,
+    options: &ConversionOptions,
+    rule: &'r FontRule,
+    symbol: String,
+) {
+    use quick_xml::events::{BytesEnd, Event};
+
+    let mut mark = run.props.mark;
+    if !run.props.props_seen && run.rule.is_none() {
+        run.props.target_fonts = true;
+        run.props.first_child(writer, Some(rule), b"w:sym");
+        mark = run.props.mark;
+    } else if !run.props.props_seen {
+        run.props.first_child(writer, run.rule, b"w:sym");
+        mark = run.props.mark;
+    } else if run.rule.is_none() {
+        mark = split_symbol_run(writer, &mut run.props, options, rule);
+    } else if !run.rule.is_some_and(|run_rule| std::ptr::eq(run_rule, rule)) {
+        log::debug!("Symbol in {} converted inside a run set in another font", rule.source_font);
+    }
+    let mut text_elem = BytesStart::new("w:t");
+    text_elem.push_attribute(("xml:space", "preserve"));
+    writer.write_event(Event::Start(text_elem)).ok();
+    merger.push(rule, &run.format, writer.get_ref().len(), symbol, mark, None);
+    writer.write_event(Event::End(BytesEnd::new("w:t"))).ok();
+}
+
+fn process_docx_xml<R: Runtime>(
+    handle: &AppHandle<R>,
+    contents: &[u8],
+    rules: &FontRules,
+    styles: &DocxStyles,
+    options: &ConversionOptions,
+    report: &mut ContainerReport,
+    revisions: Option<&mut RevisionWriter>,
+) -> Vec<u8> {
+    use quick_xml::events::{BytesText, Event};
+    use quick_xml::{Reader, Writer};
+
+    log::debug!("Starting DOCX XML processing, size: {} bytes", contents.len());
+    let run_font = |elem: &BytesStart| (elem.name().as_ref() == b"w:rFonts").then(|| styles.rfonts_font(elem)).flatten();
+    let line_spacing = scan_scaled_text(contents, rules, options, b"w:r", b"w:p", &run_font).paragraphs;
+    let context = DocxContext { rules, styles, options };
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+
+    let mut buf = Vec::new();
+    let mut run = DocxRun::default();
+    // Paragraph style of each open paragraph; text boxes nest paragraphs.
+    let mut paragraph_styles: Vec<Option<String>> = Vec::new();
+    // Run text is converted per group of runs with the same formatting, keyed by
+    // the run properties.
+    let mut merger = RunMerger::default();
+    let mut revisions = PartRevisions::new(revisions);
+    // Open complex fields, true once past the field code into the cached result. Field
+    // codes are left as they are; results are converted like other runs.
+    let mut fields: Vec<bool> = Vec::new();
+    let mut in_field_code = false;
+    let mut outer_runs: Vec<OuterRun> = Vec::new();
+    let mut containers: Vec<&'static str> = Vec::new();
+    let mut content_control = ContentControl::default();
+    let mut spacing = DocxParagraphSpacing::default();
+    let mut event_count = 0usize;
+    let mut last_progress = 0usize;
+    let mut paragraph_count = 0usize;
+
+    loop {
+        event_count += 1;
+
+        // Emit progress every 200 events processed (for 2% increments)
+        if event_count % 200 == 0 {
+            let progress = 5 + ((event_count / 200) * 40 / 100);
+            if progress != last_progress && progress <= 45 {
+                emit_progress(handle, progress, 50, &format!("Converting content... ({} nodes processed)", event_count));
+                last_progress = progress;
+            }
+
+            // Allow other events to process
+            std::thread::yield_now();
+        }
+        let event_start = reader.buffer_position();
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
                 let elem = e.into_owned();
                 let name = elem.name().as_ref().to_vec();
+                run.props.reopen_after_symbol(&mut writer);
+
+                if run.rpr_depth > 0 {
+                    run.rpr_depth += 1;
+                    run_merge::record_format(&mut run.format, &elem, &[]);
+                } else if (name.as_slice() == b"w:rPr" || name.as_slice() == b"m:rPr") && run.active {
+                    run.rpr_depth = 1;
+                    run_merge::record_format(&mut run.format, &elem, &[]);
+                } else if DOCX_TEXT_ELEMENTS.contains(&name.as_slice()) && run.active {
+                    run.in_text = true;
+                } else if !DOCX_RUN_JOINERS.contains(&name.as_slice()) {
+                    revisions.end_group(&mut merger, contents);
+                }
+                let space_preserved = elem
+                    .try_get_attribute("xml:space")
+                    .ok()
+                    .flatten()
+                    .is_some_and(|attr| attr.value.as_ref() == b"preserve");
+
+                if let Some(container) = text_container(&name) {
+                    containers.push(container);
+                }
+                revisions.open(&name);
+                if name.as_slice() == b"w:txbxContent" {
+                    outer_runs.push(run.enter_text_box());
+                    revisions.leave_run(&mut merger, contents);
+                } else if name.as_slice() == b"w:instrText" || name.as_slice() == b"w:delInstrText" {
+                    in_field_code = true;
+                } else if name.as_slice() == b"w:fldChar" {
+                    docx_field_char(&elem, &mut fields, &mut containers);
+                } else if name.as_slice() == b"w:sdtPr" {
+                    content_control = ContentControl {
+                        in_props: true,
+                        rule: None,
+                    };
+                } else if name.as_slice() == b"w:rPrChange" {
+                    run.in_rpr_change = true;
+                }
 
-                if name.as_slice() == b"rFont" && in_run {
-                    let mut new_elem = BytesStart::new("rFont");
-                    for attr in elem.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_val_attr = key == b"val" || key.ends_with(b":val");
-                        if is_val_attr && value == source_font {
-                            run_has_font = true;
-                            new_elem.push_attribute((key, TARGET_FONT.as_bytes()));
-                        } else {
-                            new_elem.push_attribute((key, value.as_bytes()));
+                spacing.start(&mut writer, &name);
+                if name.as_slice() == b"w:p" {
+                    spacing.open_paragraph(line_spacing.get(&paragraph_count).copied());
+                    paragraph_count += 1;
+                    paragraph_styles.push(None);
+                }
+                let paragraph_style = paragraph_styles.last().and_then(|style| style.as_deref());
+
+                if name.as_slice() == b"w:r" {
+                    run.start(RunProperties::new(options));
+                    run.resolve(&context, paragraph_style);
+                    revisions.start_run(event_start, writer.get_ref().len(), !outer_runs.is_empty());
+                } else if name.as_slice() == b"m:r" {
+                    // Math runs are set in the math font unless they name a font themselves.
+                    run.start(RunProperties::default());
+                } else if run.active && !run.props.props_seen {
+                    run.props.first_child(&mut writer, run.rule, &name);
+                    run.props.in_props = name.as_slice() == b"w:rPr";
+                } else if run.props.in_props && run.rpr_depth == 2 {
+                    run.props.before_props_child(&mut writer, run.rule, &name);
+                }
+
+                let new_elem = run
+                    .rewrite_prop(&elem, run.rpr_depth == 2, &context, paragraph_style)
+                    .or_else(|| content_control.rewrite(&elem, &context, report))
+                    .or_else(|| spacing.rewrite(&elem, false))
+                    .unwrap_or(elem);
+                writer.write_event(Event::Start(new_elem)).ok();
+                if run.in_text && run.rpr_depth == 0 {
+                    run.text_space_at = (!space_preserved).then(|| writer.get_ref().len() - 1);
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                let elem = e.into_owned();
+                let name = elem.name().as_ref().to_vec();
+                run.props.reopen_after_symbol(&mut writer);
+
+                if let Some((rule, symbol)) = docx_symbol(&elem, rules).filter(|_| name.as_slice() == b"w:sym" && run.active) {
+                    report.record(containers.last().copied().unwrap_or("body"));
+                    revisions.run_converted();
+                    write_docx_symbol(&mut writer, &mut run, &mut merger, options, rule, symbol);
+                    buf.clear();
+                    continue;
+                }
+
+                if run.rpr_depth > 0 || ((name.as_slice() == b"w:rPr" || name.as_slice() == b"m:rPr") && run.active) {
+                    run_merge::record_format(&mut run.format, &elem, &[]);
+                } else if !DOCX_RUN_JOINERS.contains(&name.as_slice()) {
+                    revisions.end_group(&mut merger, contents);
+                }
+
+                if spacing.empty(&mut writer, &elem) {
+                    buf.clear();
+                    continue;
+                }
+
+                if name.as_slice() == b"w:p" {
+                    paragraph_count += 1;
+                } else if name.as_slice() == b"w:fldChar" {
+                    docx_field_char(&elem, &mut fields, &mut containers);
+                }
+
+                if name.as_slice() == b"w:pStyle" {
+                    if let Some(style) = paragraph_styles.last_mut() {
+                        *style = docx_styles::val_attr(&elem);
+                    }
+                }
+                let paragraph_style = paragraph_styles.last().and_then(|style| style.as_deref());
+                if name.as_slice() == b"w:rStyle" && run.active {
+                    run.style = docx_styles::val_attr(&elem);
+                    run.resolve(&context, paragraph_style);
+                }
+
+                if run.active && !run.props.props_seen {
+                    run.props.first_child(&mut writer, run.rule, &name);
+                    if name.as_slice() == b"w:rPr" && run.props.expands_empty_props(run.rule) {
+                        // An empty w:rPr is written out in full to hold the added properties.
+                        writer.write_event(Event::Start(elem.clone())).ok();
+                        run.props.finish_props(&mut writer, run.rule);
+                        writer.write_event(Event::End(elem.to_end().into_owned())).ok();
+                        run.props.props_written(writer.get_ref().len());
+                        buf.clear();
+                        continue;
+                    }
+                } else if run.props.in_props && run.rpr_depth == 1 {
+                    run.props.before_props_child(&mut writer, run.rule, &name);
+                }
+
+                let new_elem = run
+                    .rewrite_prop(&elem, run.rpr_depth == 1, &context, paragraph_style)
+                    .or_else(|| content_control.rewrite(&elem, &context, report))
+                    .or_else(|| spacing.rewrite(&elem, true))
+                    .unwrap_or(elem);
+                writer.write_event(Event::Empty(new_elem)).ok();
+                if name.as_slice() == b"w:rPr" && run.active && run.rpr_depth == 0 {
+                    run.props.props_written(writer.get_ref().len());
+                }
+            }
+            Ok(Event::Text(e)) => {
+                match run.rule.filter(|_| run.active && !in_field_code) {
+                    Some(rule) if run.in_text => {
+                        // Written by the merger once the run group is complete.
+                        let text = e.unescape().unwrap_or_default().to_string();
+                        report.record(containers.last().copied().unwrap_or("body"));
+                        revisions.run_converted();
+                        merger.push(rule, &run.format, writer.get_ref().len(), text, run.props.mark, run.text_space_at.take());
+                    }
+                    Some(rule) => {
+                        report.record(containers.last().copied().unwrap_or("body"));
+                        revisions.run_converted();
+                        let text = e.unescape().unwrap_or_default().to_string();
+                        let converted = rule.mapping.convert(&text);
+                        let new_text = BytesText::new(&converted);
+                        writer.write_event(Event::Text(new_text)).ok();
+                    }
+                    None => {
+                        if run.in_text {
+                            revisions.end_group(&mut merger, contents);
                         }
+                        writer.write_event(Event::Text(e.into_owned())).ok();
+                    }
+                }
+            }
+            Ok(Event::End(e)) => {
+                let name = e.name().as_ref().to_vec();
+                if run.props.in_props && run.rpr_depth == 1 {
+                    run.props.finish_props(&mut writer, run.rule);
+                }
+                if run.rpr_depth > 0 {
+                    run_merge::record_format_end(&mut run.format, &name);
+                    run.rpr_depth -= 1;
+                } else if DOCX_TEXT_ELEMENTS.contains(&name.as_slice()) {
+                    run.in_text = false;
+                } else if !DOCX_RUN_JOINERS.contains(&name.as_slice()) {
+                    revisions.end_group(&mut merger, contents);
+                }
+
+                spacing.end(&mut writer);
+                if text_container(&name).is_some() {
+                    containers.pop();
+                }
+                revisions.close(&name);
+                if name.as_slice() == b"w:r" || name.as_slice() == b"m:r" {
+                    run.end();
+                } else if name.as_slice() == b"w:p" {
+                    paragraph_styles.pop();
+                } else if name.as_slice() == b"w:txbxContent" {
+                    if let Some(outer) = outer_runs.pop() {
+                        run.leave_text_box(outer);
                     }
+                } else if name.as_slice() == b"w:sdtPr" {
+                    content_control.in_props = false;
+                } else if name.as_slice() == b"w:rPrChange" {
+                    run.in_rpr_change = false;
+                } else if name.as_slice() == b"w:instrText" || name.as_slice() == b"w:delInstrText" {
+                    in_field_code = false;
+                }
+                writer.write_event(Event::End(e.into_owned())).ok();
+
+                if name.as_slice() == b"w:rPr" && run.rpr_depth == 0 && run.active {
+                    run.props.props_written(writer.get_ref().len());
+                }
+                if name.as_slice() == b"w:r" {
+                    revisions.end_run(&mut merger, contents, reader.buffer_position(), writer.get_ref().len());
+                }
+            }
+            Ok(Event::CData(e)) => {
+                writer.write_event(Event::CData(e.into_owned())).ok();
+            }
+            Ok(Event::Decl(e)) => {
+                writer.write_event(Event::Decl(e.into_owned())).ok();
+            }
+            Ok(Event::PI(e)) => {
+                writer.write_event(Event::PI(e.into_owned())).ok();
+            }
+            Ok(Event::Comment(e)) => {
+                writer.write_event(Event::Comment(e.into_owned())).ok();
+            }
+            Ok(Event::DocType(e)) => {
+                writer.write_event(Event::DocType(e.into_owned())).ok();
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    log::debug!("DOCX XML processing completed, {} events processed", event_count);
+    emit_progress(handle, 46, 50, "Document content processed");
+
+    revisions.end_group(&mut merger, contents);
+    report.flagged += merger.flagged;
+    merger.finish(writer.into_inner())
+}
+
+/// Converts the strings `plan` assigns a source font and appends the converted
+/// copies of split strings to the end of the table. Rich text runs in a source
+/// font convert too, unless only cells outside the selection use the string.
+fn process_shared_strings(contents: &[u8], rules: &FontRules, plan: &SharedStringPlan) -> Vec<u8> {
+    use quick_xml::events::{BytesText, Event};
+    use quick_xml::{Reader, Writer};
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+
+    let mut buf = Vec::new();
+    let mut in_run = false;
+    let mut run_rule: Option<&FontRule> = None;
+    let mut in_si = false;
+    let mut si_index: usize = 0;
+    let mut si_rule: Option<&FontRule> = None;
+    let mut si_kept = false;
+    // Source XML of the split strings, copied again for each of their new indices.
+    let mut si_start = 0usize;
+    let mut split_sources: HashMap<usize, &[u8]> = HashMap::new();
+
+    loop {
+        let event_start = reader.buffer_position();
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let elem = e.into_owned();
+                let name = elem.name().as_ref().to_vec();
+
+                if name.as_slice() == b"sst" && !plan.splits.is_empty() {
+                    let unique_count = (plan.unique_count + plan.splits.len()).to_string();
+                    let new_elem = convert_attr(&elem, b"uniqueCount", |_| unique_count.clone());
+                    writer.write_event(Event::Start(new_elem)).ok();
+                    buf.clear();
+                    continue;
+                }
+
+                if name.as_slice() == b"si" {
+                    in_si = true;
+                    si_rule = plan.convert.get(&si_index).copied();
+                    si_kept = plan.kept.contains(&si_index);
+                    si_start = event_start;
+                }
+
+                if name.as_slice() == b"r" {
+                    in_run = true;
+                    run_rule = None;
+                }
+
+                if name.as_slice() == b"rFont" && in_run && !si_kept {
+                    let (new_elem, matched) = rewrite_font_attrs(&elem, rules, &xlsx_font_slot);
+                    run_rule = matched.or(run_rule);
+                    writer.write_event(Event::Start(new_elem)).ok();
+                } else {
+                    writer.write_event(Event::Start(elem)).ok();
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                let elem = e.into_owned();
+                let name = elem.name().as_ref().to_vec();
+
+                if name.as_slice() == b"si" {
+                    if plan.is_split(si_index) {
+                        split_sources.insert(si_index, &contents[event_start..reader.buffer_position()]);
+                    }
+                    si_index += 1;
+                }
+
+                if name.as_slice() == b"rFont" && in_run && !si_kept {
+                    let (new_elem, matched) = rewrite_font_attrs(&elem, rules, &xlsx_font_slot);
+                    run_rule = matched.or(run_rule);
                     writer.write_event(Event::Empty(new_elem)).ok();
                 } else {
                     writer.write_event(Event::Empty(elem)).ok();
                 }
             }
             Ok(Event::Text(e)) => {
-                if (in_run && run_has_font) || (in_si && convert_si) {
+                let rule = run_rule.filter(|_| in_run).or(si_rule.filter(|_| in_si));
+                if let Some(rule) = rule {
                     let text = e.unescape().unwrap_or_default().to_string();
-                    let converted = win_to_myanmar3(&text);
+                    let converted = rule.mapping.convert(&text);
                     let new_text = BytesText::new(&converted);
                     writer.write_event(Event::Text(new_text)).ok();
                 } else {
@@ -457,11 +1982,25 @@ fn process_shared_strings(contents: &[u8], source_font: &str, indices_to_convert
             Ok(Event::End(e)) => {
                 if e.name().as_ref() == b"r" {
                     in_run = false;
-                    run_has_font = false;
+                    run_rule = None;
                 }
                 if e.name().as_ref() == b"si" {
                     in_si = false;
-                    convert_si = false;
+                    si_rule = None;
+                    si_kept = false;
+                    if plan.is_split(si_index) {
+                        split_sources.insert(si_index, &contents[si_start..reader.buffer_position()]);
+                    }
+                    si_index += 1;
+                }
+                if e.name().as_ref() == b"sst" {
+                    for (original, rule) in &plan.splits {
+                        if let Some(source) = split_sources.get(original) {
+                            let mut copy = SharedStringPlan::default();
+                            copy.convert.insert(0, *rule);
+                            writer.get_mut().extend_from_slice(&process_shared_strings(source, rules, &copy));
+                        }
+                    }
                 }
                 writer.write_event(Event::End(e.into_owned())).ok();
             }
@@ -489,26 +2028,78 @@ fn process_shared_strings(contents: &[u8], source_font: &str, indices_to_convert
     writer.into_inner()
 }
 
-fn process_xlsx_styles(contents: &[u8], source_font: &str) -> Vec<u8> {
-    use quick_xml::events::{BytesStart, Event};
+/// Scaling rule for each `font` element of styles.xml, in document order, whose
+/// name is a source font. `sz` comes before `name` inside a font.
+fn scan_xlsx_font_scaling<'o>(contents: &[u8], rules: &FontRules, options: &'o ConversionOptions) -> HashMap<usize, &'o ScalingRule> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut scaled = HashMap::new();
+    if options.scaling.is_empty() {
+        return scaled;
+    }
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut font_count = 0usize;
+    let mut current_font: Option<usize> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) if tag_matches(e.name().as_ref(), b"font") => {
+                current_font = Some(font_count);
+                font_count += 1;
+            }
+            Ok(Event::Empty(e)) => {
+                let name = e.name().as_ref().to_vec();
+                if tag_matches(&name, b"font") {
+                    font_count += 1;
+                } else if let Some(font) = current_font.filter(|_| tag_matches(&name, b"name")) {
+                    for attr in e.attributes().flatten() {
+                        if !is_val_attr(attr.key.as_ref()) {
+                            continue;
+                        }
+                        let value = attr.unescape_value().unwrap_or_default().to_string();
+                        if let Some(scaling) = rules.find(&value).and_then(|rule| options.scaling_for(rule)) {
+                            scaled.insert(font, scaling);
+                        }
+                    }
+                }
+            }
+            Ok(Event::End(e)) if tag_matches(e.name().as_ref(), b"font") => {
+                current_font = None;
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    scaled
+}
+
+fn process_xlsx_styles(
+    contents: &[u8],
+    rules: &FontRules,
+    options: &ConversionOptions,
+    digit_formats: &DigitFormats,
+    style_clones: &StyleClones,
+) -> Vec<u8> {
+    use quick_xml::events::{BytesEnd, Event};
     use quick_xml::{Reader, Writer};
 
+    let scaled_fonts = scan_xlsx_font_scaling(contents, rules, options);
     let mut reader = Reader::from_reader(contents);
     reader.trim_text(false);
     let mut writer = Writer::new(Vec::with_capacity(contents.len()));
 
     let mut buf = Vec::new();
-
-    fn tag_matches(name: &[u8], local: &[u8]) -> bool {
-        if name == local {
-            return true;
-        }
-        if name.ends_with(local) {
-            let idx = name.len() - local.len();
-            return idx > 0 && name[idx - 1] == b':';
-        }
-        false
-    }
+    let mut font_count = 0usize;
+    let mut font_scaling: Option<&ScalingRule> = None;
+    let mut in_cell_xfs = false;
+    let mut xf_count = 0usize;
 
     loop {
         match reader.read_event_into(&mut buf) {
@@ -516,19 +2107,45 @@ fn process_xlsx_styles(contents: &[u8], source_font: &str) -> Vec<u8> {
                 let elem = e.into_owned();
                 let name = elem.name().as_ref().to_vec();
 
-                if tag_matches(&name, b"name") || tag_matches(&name, b"rFont") {
-                    let tag = String::from_utf8_lossy(&name).to_string();
-                    let mut new_elem = BytesStart::new(tag.as_str());
-                    for attr in elem.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_val_attr = key == b"val" || key.ends_with(b":val");
-                        if is_val_attr && value == source_font {
-                            new_elem.push_attribute((key, TARGET_FONT.as_bytes()));
-                        } else {
-                            new_elem.push_attribute((key, value.as_bytes()));
-                        }
-                    }
+                if tag_matches(&name, b"font") {
+                    font_scaling = scaled_fonts.get(&font_count).copied();
+                    font_count += 1;
+                }
+
+                if name.as_slice() == b"styleSheet" && !digit_formats.is_empty() && !digit_formats.has_num_fmts {
+                    // numFmts is the first child of styleSheet.
+                    writer.write_event(Event::Start(elem)).ok();
+                    let count = digit_formats.formats.len().to_string();
+                    let mut num_fmts = BytesStart::new("numFmts");
+                    num_fmts.push_attribute(("count", count.as_str()));
+                    writer.write_event(Event::Start(num_fmts)).ok();
+                    digit_formats.write_formats(&mut writer);
+                    writer.write_event(Event::End(BytesEnd::new("numFmts"))).ok();
+                } else if name.as_slice() == b"numFmts" && !digit_formats.is_empty() {
+                    let new_elem = convert_attr(&elem, b"count", |count| {
+                        let count = count.trim().parse::<usize>().unwrap_or_default();
+                        (count + digit_formats.formats.len()).to_string()
+                    });
+                    writer.write_event(Event::Start(new_elem)).ok();
+                } else if name.as_slice() == b"cellXfs" {
+                    in_cell_xfs = true;
+                    let new_elem = convert_attr(&elem, b"count", |count| {
+                        let count = count.trim().parse::<usize>().unwrap_or_default();
+                        (count + style_clones.xf_count()).to_string()
+                    });
+                    writer.write_event(Event::Start(new_elem)).ok();
+                } else if name.as_slice() == b"fonts" {
+                    let new_elem = convert_attr(&elem, b"count", |count| {
+                        let count = count.trim().parse::<usize>().unwrap_or_default();
+                        (count + style_clones.font_count()).to_string()
+                    });
+                    writer.write_event(Event::Start(new_elem)).ok();
+                } else if in_cell_xfs && name.as_slice() == b"xf" {
+                    let new_elem = digit_formats.rewrite_xf(&elem, xf_count).unwrap_or(elem);
+                    xf_count += 1;
+                    writer.write_event(Event::Start(new_elem)).ok();
+                } else if tag_matches(&name, b"name") || tag_matches(&name, b"rFont") {
+                    let (new_elem, _) = rewrite_font_attrs(&elem, rules, &xlsx_font_slot);
                     writer.write_event(Event::Start(new_elem)).ok();
                 } else {
                     writer.write_event(Event::Start(elem)).ok();
@@ -538,19 +2155,25 @@ fn process_xlsx_styles(contents: &[u8], source_font: &str) -> Vec<u8> {
                 let elem = e.into_owned();
                 let name = elem.name().as_ref().to_vec();
 
-                if tag_matches(&name, b"name") || tag_matches(&name, b"rFont") {
-                    let tag = String::from_utf8_lossy(&name).to_string();
-                    let mut new_elem = BytesStart::new(tag.as_str());
-                    for attr in elem.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_val_attr = key == b"val" || key.ends_with(b":val");
-                        if is_val_attr && value == source_font {
-                            new_elem.push_attribute((key, TARGET_FONT.as_bytes()));
-                        } else {
-                            new_elem.push_attribute((key, value.as_bytes()));
-                        }
-                    }
+                if tag_matches(&name, b"font") {
+                    font_count += 1;
+                }
+
+                if in_cell_xfs && name.as_slice() == b"xf" {
+                    let new_elem = digit_formats.rewrite_xf(&elem, xf_count).unwrap_or(elem);
+                    xf_count += 1;
+                    writer.write_event(Event::Empty(new_elem)).ok();
+                } else if name.as_slice() == b"numFmts" && !digit_formats.is_empty() {
+                    let count = digit_formats.formats.len().to_string();
+                    let new_elem = convert_attr(&elem, b"count", |_| count.clone());
+                    writer.write_event(Event::Start(new_elem)).ok();
+                    digit_formats.write_formats(&mut writer);
+                    writer.write_event(Event::End(BytesEnd::new("numFmts"))).ok();
+                } else if tag_matches(&name, b"name") || tag_matches(&name, b"rFont") {
+                    let (new_elem, _) = rewrite_font_attrs(&elem, rules, &xlsx_font_slot);
+                    writer.write_event(Event::Empty(new_elem)).ok();
+                } else if let Some(scaling) = font_scaling.filter(|_| tag_matches(&name, b"sz")) {
+                    let new_elem = scale_attr(&elem, b"val", |value| scaling.scale_points(value));
                     writer.write_event(Event::Empty(new_elem)).ok();
                 } else {
                     writer.write_event(Event::Empty(elem)).ok();
@@ -560,6 +2183,16 @@ fn process_xlsx_styles(contents: &[u8], source_font: &str) -> Vec<u8> {
                 writer.write_event(Event::Text(e.into_owned())).ok();
             }
             Ok(Event::End(e)) => {
+                if tag_matches(e.name().as_ref(), b"font") {
+                    font_scaling = None;
+                } else if e.name().as_ref() == b"cellXfs" {
+                    in_cell_xfs = false;
+                    style_clones.write_xfs(&mut writer);
+                } else if e.name().as_ref() == b"fonts" {
+                    style_clones.write_fonts(&mut writer);
+                } else if e.name().as_ref() == b"numFmts" {
+                    digit_formats.write_formats(&mut writer);
+                }
                 writer.write_event(Event::End(e.into_owned())).ok();
             }
             Ok(Event::CData(e)) => {
@@ -586,194 +2219,711 @@ fn process_xlsx_styles(contents: &[u8], source_font: &str) -> Vec<u8> {
     writer.into_inner()
 }
 
-fn parse_xlsx_styles(contents: &[u8], source_font: &str) -> (HashSet<usize>, Vec<usize>) {
+/// Records the rule of each shared string a worksheet's cells use, and the rule of
+/// each `linked` cell holding text, which its table header or chart cache copies.
+fn collect_shared_string_uses<'r, R: BufRead>(
+    source: R,
+    cell_styles: &XlsxCellStyles<'r>,
+    conditional_fonts: &ConditionalFonts<'r>,
+    cells: &SheetCells,
+    out: &mut SharedStringUses<'r>,
+    mut linked: Option<&mut LinkedRules<'r>>,
+) {
     use quick_xml::events::Event;
     use quick_xml::Reader;
 
-    let mut reader = Reader::from_reader(contents);
+    let mut reader = Reader::from_reader(source);
     reader.trim_text(true);
     let mut buf = Vec::new();
 
-    let mut in_fonts = false;
-    let mut in_cell_xfs = false;
-    let mut current_font_id: usize = 0;
-    let mut source_font_ids: HashSet<usize> = HashSet::new();
-    let mut xf_font_ids: Vec<usize> = Vec::new();
+    let mut current_cell_style: Option<usize> = None;
+    let mut current_cell_ref: Option<String> = None;
+    let mut current_cell_type: Option<String> = None;
 
-    fn tag_matches(name: &[u8], local: &[u8]) -> bool {
-        if name == local {
-            return true;
-        }
-        if name.ends_with(local) {
-            let idx = name.len() - local.len();
-            return idx > 0 && name[idx - 1] == b':';
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let name = e.name().as_ref().to_vec();
+                if name.as_slice() == b"c" {
+                    current_cell_style = None;
+                    current_cell_ref = None;
+                    current_cell_type = None;
+                    for attr in e.attributes().flatten() {
+                        let key = attr.key.as_ref();
+                        let value = attr.unescape_value().unwrap_or_default().to_string();
+                        if key == b"s" {
+                            current_cell_style = value.parse::<usize>().ok();
+                        } else if key == b"r" {
+                            current_cell_ref = Some(value);
+                        } else if key == b"t" {
+                            current_cell_type = Some(value);
+                        }
+                    }
+                    let is_text = matches!(current_cell_type.as_deref(), Some("s" | "inlineStr" | "str"));
+                    let position = current_cell_ref.as_deref().and_then(xlsx_styles::cell_position);
+                    if let (Some(linked), Some(position), true) = (linked.as_deref_mut(), position, is_text) {
+                        if let Some(rule) = linked.get_mut(&position) {
+                            *rule = cell_styles
+                                .rule(current_cell_style.unwrap_or(0))
+                                .or_else(|| conditional_fonts.rule_at(current_cell_ref.as_deref()?))
+                                .filter(|_| cells.contains(current_cell_ref.as_deref()));
+                        }
+                    }
+                }
+            }
+            Ok(Event::Text(e)) => {
+                if let Some(ref cell_type) = current_cell_type {
+                    if cell_type == "s" {
+                        // Cells without a style index use the first cell style.
+                        let rule = cell_styles
+                            .rule(current_cell_style.unwrap_or(0))
+                            .or_else(|| conditional_fonts.rule_at(current_cell_ref.as_deref()?));
+                        let text = e.unescape().unwrap_or_default().to_string();
+                        if let Ok(idx) = text.trim().parse::<usize>() {
+                            if cells.contains(current_cell_ref.as_deref()) {
+                                out.record(idx, rule);
+                            } else {
+                                out.record_unpicked(idx);
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(Event::End(e)) => {
+                if e.name().as_ref() == b"c" {
+                    current_cell_style = None;
+                    current_cell_ref = None;
+                    current_cell_type = None;
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
         }
-        false
+        buf.clear();
     }
+}
+
+/// Workbook-wide plans every worksheet is rewritten with.
+struct XlsxPlans<'a> {
+    cell_styles: &'a XlsxCellStyles<'a>,
+    shared_plan: &'a SharedStringPlan<'a>,
+    refs: &'a References,
+    style_clones: &'a StyleClones,
+    digit_formats: &'a DigitFormats,
+}
+
+/// What the first pass over one worksheet found: the cells picked for conversion,
+/// and the conditional formats and data validations that follow its cells.
+struct WorksheetPlan<'a> {
+    cells: &'a SheetCells,
+    conditional_fonts: ConditionalFonts<'a>,
+    validation_fonts: ValidationFonts<'a>,
+}
+
+/// `r` reference of a worksheet cell.
+fn elem_cell_ref(elem: &BytesStart) -> Option<String> {
+    elem.try_get_attribute("r")
+        .ok()
+        .flatten()
+        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+}
+
+/// Rewrites a worksheet's own cell text: inline strings (`t="inlineStr"`) and
+/// formula string results (`t="str"`) in a cell shown in a source font, by its
+/// style or by a conditional format, plus rich text runs whose `rFont` is a source
+/// font. Shared string cells move to the converted copy `shared_plan` made for
+/// their font, and numeric cells in a source font are counted and, with
+/// `DigitDisplay::Text`, turned into Myanmar digit text when in the General
+/// format. Formulas and hyperlinks
+/// follow renamed sheets and table columns, string literals in the formulas of
+/// such cells and of data validations over them are converted, and print headers
+/// and footers are converted. The sheet is streamed from `source` to `output`,
+/// which is handed back; a malformed sheet or a failed write is an error.
+fn process_xlsx_worksheet<R: BufRead, W: Write>(
+    source: R,
+    output: W,
+    rules: &FontRules,
+    plans: &XlsxPlans,
+    sheet: WorksheetPlan,
+    options: &ConversionOptions,
+    report: &mut ContainerReport,
+) -> Result<W, Box<dyn std::error::Error>> {
+    use quick_xml::events::{BytesEnd, BytesText, Event};
+    use quick_xml::{Reader, Writer};
+
+    let XlsxPlans {
+        cell_styles,
+        shared_plan,
+        refs,
+        style_clones,
+        digit_formats,
+    } = plans;
+    let WorksheetPlan {
+        cells,
+        conditional_fonts,
+        mut validation_fonts,
+    } = sheet;
+    let digits_as_text = options.myanmar_digits == Some(DigitDisplay::Text);
+
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(false);
+    let mut writer = Writer::new(output);
+
+    let mut buf = Vec::new();
+    let mut cell_rule: Option<&FontRule> = None;
+    let mut cell_type: Option<String> = None;
+    let mut cell_style = 0usize;
+    let mut in_inline_string = false;
+    let mut in_text = false;
+    let mut in_shared_index = false;
+    let mut in_run = false;
+    let mut run_rule: Option<&FontRule> = None;
+    // Whether the rich-text run names a font; runs that don't are in the cell's font.
+    let mut run_font_named = false;
+    // Numeric cell held back until its first child shows whether it has a formula.
+    let mut pending_cell: Option<BytesStart<'static>> = None;
+    let mut number_as_text = false;
+    let mut in_formula = false;
+    let mut formula_rule: Option<&FontRule> = None;
+    let mut validation: Option<usize> = None;
+    let mut validation_count = 0usize;
+    let mut in_header_footer = false;
+    let mut cell_picked = true;
 
     loop {
         match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"v" && pending_cell.is_some() => {
+                report.numeric_cells += 1;
+                if let Some(cell) = pending_cell.take() {
+                    let mut text_cell = BytesStart::new("c");
+                    text_cell.extend_attributes(cell.attributes().flatten().filter(|attr| attr.key.as_ref() != b"t"));
+                    text_cell.push_attribute(("t", "inlineStr"));
+                    writer.write_event(Event::Start(text_cell))?;
+                }
+                writer.write_event(Event::Start(BytesStart::new("is")))?;
+                writer.write_event(Event::Start(BytesStart::new("t")))?;
+                number_as_text = true;
+            }
             Ok(Event::Start(e)) => {
-                let name = e.name().as_ref().to_vec();
-                if name.as_slice() == b"fonts" {
-                    in_fonts = true;
-                } else if name.as_slice() == b"cellXfs" {
-                    in_cell_xfs = true;
+                let elem = e.into_owned();
+                let name = elem.name().as_ref().to_vec();
+                if let Some(cell) = pending_cell.take() {
+                    writer.write_event(Event::Start(cell))?;
+                }
+
+                match name.as_slice() {
+                    b"c" => {
+                        cell_rule = cell_styles.default_rule();
+                        cell_type = None;
+                        cell_style = 0;
+                        let mut cell_ref: Option<String> = None;
+                        for attr in elem.attributes().flatten() {
+                            let key = attr.key.as_ref();
+                            let value = attr.unescape_value().unwrap_or_default().to_string();
+                            if key == b"s" {
+                                let style_idx = value.parse::<usize>().ok();
+                                cell_style = style_idx.unwrap_or(0);
+                                cell_rule = style_idx.and_then(|style_idx| cell_styles.rule(style_idx));
+                            } else if key == b"r" {
+                                cell_ref = Some(value);
+                            } else if key == b"t" {
+                                cell_type = Some(value);
+                            }
+                        }
+                        // A conditional format can show the cell in a source font.
+                        cell_rule = cell_rule.or_else(|| conditional_fonts.rule_at(cell_ref.as_deref()?));
+                        cell_picked = cells.contains(cell_ref.as_deref());
+                        if !cell_picked {
+                            cell_rule = None;
+                        }
+                        if let Some(cell_ref) = cell_ref.as_deref() {
+                            validation_fonts.record(cell_ref, cell_rule);
+                        }
+                    }
+                    b"is" => in_inline_string = cell_picked && cell_type.as_deref() == Some("inlineStr"),
+                    b"t" => in_text = in_inline_string,
+                    b"v" => {
+                        in_text = cell_type.as_deref() == Some("str");
+                        in_shared_index = cell_type.as_deref() == Some("s");
+                        if cell_rule.is_some() && matches!(cell_type.as_deref(), None | Some("n")) {
+                            report.numeric_cells += 1;
+                        }
+                    }
+                    b"r" if in_inline_string => {
+                        in_run = true;
+                        run_rule = None;
+                        run_font_named = false;
+                    }
+                    b"f" => {
+                        in_formula = true;
+                        formula_rule = cell_rule;
+                    }
+                    b"formula1" | b"formula2" => {
+                        in_formula = true;
+                        formula_rule = validation.and_then(|index| validation_fonts.rule(index, cell_styles.default_rule()));
+                    }
+                    b"formula" | b"xm:f" => in_formula = true,
+                    b"dataValidation" => {
+                        validation = Some(validation_count);
+                        validation_count += 1;
+                    }
+                    name if xlsx_parts::is_header_footer(name) => in_header_footer = cells.is_all(),
+                    _ => {}
+                }
+
+                // Cells outside the selection move to copies of their source font formats.
+                let elem = match name.as_slice() {
+                    b"c" if !cell_picked => style_clones.restyle(&elem, b"s").unwrap_or(elem),
+                    b"row" if matches!(cells, SheetCells::Nothing) => style_clones.restyle(&elem, b"s").unwrap_or(elem),
+                    _ => elem,
+                };
+                let numeric = cell_rule.is_some() && matches!(cell_type.as_deref(), None | Some("n"));
+                // Only General cells become text; a date or number format needs the value.
+                if name.as_slice() == b"c" && digits_as_text && numeric && digit_formats.general_xfs.contains(&cell_style) {
+                    pending_cell = Some(elem);
+                } else if name.as_slice() == b"rFont" && in_run {
+                    let (new_elem, matched) = rewrite_font_attrs(&elem, rules, &xlsx_font_slot);
+                    run_rule = matched.or(run_rule);
+                    run_font_named = true;
+                    writer.write_event(Event::Start(new_elem))?;
+                } else {
+                    writer.write_event(Event::Start(elem))?;
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                let elem = e.into_owned();
+                let name = elem.name().as_ref().to_vec();
+                if let Some(cell) = pending_cell.take() {
+                    writer.write_event(Event::Start(cell))?;
                 }
 
-                if in_fonts && (tag_matches(&name, b"name") || tag_matches(&name, b"rFont")) {
-                    for attr in e.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_val_attr = key == b"val" || key.ends_with(b":val");
-                        if is_val_attr && value == source_font {
-                            source_font_ids.insert(current_font_id);
+                let mut empty_cell_picked = true;
+                match name.as_slice() {
+                    b"c" => {
+                        // An empty cell still sets the font its data validation list shows in.
+                        let mut empty_rule = cell_styles.default_rule();
+                        let cell_ref = elem_cell_ref(&elem);
+                        if let Some(style) = elem.try_get_attribute("s").ok().flatten() {
+                            let style_idx = style.unescape_value().unwrap_or_default().parse::<usize>().ok();
+                            empty_rule = style_idx.and_then(|style_idx| cell_styles.rule(style_idx));
+                        }
+                        empty_cell_picked = cells.contains(cell_ref.as_deref());
+                        if let Some(cell_ref) = cell_ref.as_deref() {
+                            let rule = empty_rule.or_else(|| conditional_fonts.rule_at(cell_ref));
+                            validation_fonts.record(cell_ref, rule.filter(|_| empty_cell_picked));
                         }
                     }
-                } else if in_cell_xfs && name.as_slice() == b"xf" {
-                    let mut font_id = 0usize;
-                    for attr in e.attributes().flatten() {
-                        if attr.key.as_ref() == b"fontId" {
-                            if let Ok(val) = attr.unescape_value() {
-                                font_id = val.parse::<usize>().unwrap_or(0);
+                    b"dataValidation" => validation_count += 1,
+                    _ => {}
+                }
+                let elem = match name.as_slice() {
+                    b"c" if !empty_cell_picked => style_clones.restyle(&elem, b"s").unwrap_or(elem),
+                    b"row" if matches!(cells, SheetCells::Nothing) => style_clones.restyle(&elem, b"s").unwrap_or(elem),
+                    b"col" if matches!(cells, SheetCells::Nothing) => style_clones.restyle(&elem, b"style").unwrap_or(elem),
+                    _ => elem,
+                };
+
+                let location = (name.as_slice() == b"hyperlink")
+                    .then(|| elem.try_get_attribute("location").ok().flatten())
+                    .flatten()
+                    .map(|attr| attr.unescape_value().unwrap_or_default().to_string());
+                if name.as_slice() == b"rFont" && in_run {
+                    let (new_elem, matched) = rewrite_font_attrs(&elem, rules, &xlsx_font_slot);
+                    run_rule = matched.or(run_rule);
+                    run_font_named = true;
+                    writer.write_event(Event::Empty(new_elem))?;
+                } else if let Some(location) = location.filter(|location| refs.rename_formula(location) != *location) {
+                    let new_elem = convert_attr(&elem, b"location", |_| refs.rename_formula(&location));
+                    writer.write_event(Event::Empty(new_elem))?;
+                } else {
+                    writer.write_event(Event::Empty(elem))?;
+                }
+            }
+            Ok(Event::Text(e)) if in_formula || in_header_footer => {
+                let text = e.unescape().unwrap_or_default().to_string();
+                let new_text = if in_formula {
+                    let renamed = refs.rename_formula(&text);
+                    match formula_rule {
+                        Some(rule) => {
+                            let converted = if validation.is_some() {
+                                xlsx_parts::convert_validation_formula(&renamed, rule)
+                            } else {
+                                xlsx_parts::convert_formula_strings(&renamed, rule)
+                            };
+                            if converted != renamed {
+                                report.record(if validation.is_some() { "data validation" } else { "formula" });
                             }
+                            converted
                         }
+                        None => renamed,
                     }
-                    xf_font_ids.push(font_id);
+                } else {
+                    xlsx_parts::convert_header_footer(&text, rules, cell_styles.default_rule())
+                };
+                if new_text == text {
+                    writer.write_event(Event::Text(e.into_owned()))?;
+                } else {
+                    if in_header_footer {
+                        report.record("header/footer");
+                    }
+                    writer.write_event(Event::Text(BytesText::new(&new_text)))?;
                 }
             }
-            Ok(Event::Empty(e)) => {
-                let name = e.name().as_ref().to_vec();
-                if in_fonts && (tag_matches(&name, b"name") || tag_matches(&name, b"rFont")) {
-                    for attr in e.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_val_attr = key == b"val" || key.ends_with(b":val");
-                        if is_val_attr && value == source_font {
-                            source_font_ids.insert(current_font_id);
-                        }
+            Ok(Event::Text(e)) if number_as_text => {
+                let text = e.unescape().unwrap_or_default().to_string();
+                let digits = number_formats::myanmar_digits(text.trim());
+                writer.write_event(Event::Text(BytesText::new(&digits)))?;
+            }
+            Ok(Event::Text(e)) if in_shared_index => {
+                let split = cell_rule.and_then(|rule| {
+                    let text = e.unescape().unwrap_or_default().to_string();
+                    let index = text.trim().parse::<usize>().ok()?;
+                    shared_plan.split_index(index, rule)
+                });
+                match split {
+                    Some(new_index) => {
+                        writer.write_event(Event::Text(BytesText::new(&new_index.to_string())))?;
                     }
-                } else if in_cell_xfs && name.as_slice() == b"xf" {
-                    let mut font_id = 0usize;
-                    for attr in e.attributes().flatten() {
-                        if attr.key.as_ref() == b"fontId" {
-                            if let Ok(val) = attr.unescape_value() {
-                                font_id = val.parse::<usize>().unwrap_or(0);
-                            }
-                        }
+                    None => {
+                        writer.write_event(Event::Text(e.into_owned()))?;
+                    }
+                }
+            }
+            Ok(Event::Text(e)) => {
+                let rule = if in_run && run_font_named { run_rule } else { cell_rule };
+                match rule.filter(|_| in_text) {
+                    Some(rule) => {
+                        let text = e.unescape().unwrap_or_default().to_string();
+                        let converted = rule.mapping.convert(&text);
+                        writer.write_event(Event::Text(BytesText::new(&converted)))?;
+                    }
+                    None => {
+                        writer.write_event(Event::Text(e.into_owned()))?;
                     }
-                    xf_font_ids.push(font_id);
                 }
             }
+            Ok(Event::End(e)) if e.name().as_ref() == b"v" && number_as_text => {
+                writer.write_event(Event::End(BytesEnd::new("t")))?;
+                writer.write_event(Event::End(BytesEnd::new("is")))?;
+                number_as_text = false;
+            }
             Ok(Event::End(e)) => {
-                let name = e.name().as_ref().to_vec();
-                if name.as_slice() == b"font" && in_fonts {
-                    current_font_id += 1;
-                } else if name.as_slice() == b"fonts" {
-                    in_fonts = false;
-                } else if name.as_slice() == b"cellXfs" {
-                    in_cell_xfs = false;
+                if let Some(cell) = pending_cell.take() {
+                    writer.write_event(Event::Start(cell))?;
+                }
+                match e.name().as_ref() {
+                    b"c" => {
+                        cell_rule = None;
+                        cell_type = None;
+                        cell_picked = true;
+                    }
+                    b"is" => in_inline_string = false,
+                    b"t" => in_text = false,
+                    b"v" => {
+                        in_text = false;
+                        in_shared_index = false;
+                    }
+                    b"r" => {
+                        in_run = false;
+                        run_rule = None;
+                    }
+                    b"f" | b"formula" | b"formula1" | b"formula2" | b"xm:f" => {
+                        in_formula = false;
+                        formula_rule = None;
+                    }
+                    b"dataValidation" => validation = None,
+                    name if xlsx_parts::is_header_footer(name) => in_header_footer = false,
+                    _ => {}
                 }
+                writer.write_event(Event::End(e.into_owned()))?;
             }
             Ok(Event::Eof) => break,
-            Err(_) => break,
-            _ => {}
+            Ok(e) => {
+                writer.write_event(e.into_owned())?;
+            }
+            Err(e) => return Err(e.into()),
         }
         buf.clear();
     }
 
-    (source_font_ids, xf_font_ids)
+    Ok(writer.into_inner())
 }
 
-fn collect_shared_string_indices(
-    contents: &[u8],
-    source_font_ids: &HashSet<usize>,
-    xf_font_ids: &[usize],
-    out: &mut HashSet<usize>,
-) {
-    use quick_xml::events::Event;
-    use quick_xml::Reader;
+/// 100% line spacing in DrawingML `a:spcPct`, in thousandths of a percent.
+const PPTX_SINGLE_LINE: f64 = 100000.0;
 
-    let mut reader = Reader::from_reader(contents);
-    reader.trim_text(true);
-    let mut buf = Vec::new();
+/// Paragraph content that may sit between two runs without ending a run group.
+const PPTX_RUN_JOINERS: &[&[u8]] = &[b"a:r", b"a:t", b"a:rPr"];
 
-    let mut current_cell_style: Option<usize> = None;
-    let mut current_cell_type: Option<String> = None;
+/// `a:rPr` attributes that PowerPoint sets per run for proofing and editing state.
+const PPTX_IGNORED_RUN_ATTRS: &[&[u8]] = &[b"err", b"dirty", b"smtClean"];
 
-    loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                let name = e.name().as_ref().to_vec();
-                if name.as_slice() == b"c" {
-                    current_cell_style = None;
-                    current_cell_type = None;
-                    for attr in e.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        if key == b"s" {
-                            current_cell_style = value.parse::<usize>().ok();
-                        } else if key == b"t" {
-                            current_cell_type = Some(value);
-                        }
-                    }
-                }
+fn write_pptx_line_spacing(writer: &mut quick_xml::Writer<Vec<u8>>, scaling: &ScalingRule) {
+    use quick_xml::events::{BytesEnd, Event};
+
+    let value = scaling.scale_line(PPTX_SINGLE_LINE).unwrap_or(PPTX_SINGLE_LINE as i64);
+    let mut spacing = BytesStart::new("a:spcPct");
+    spacing.push_attribute(("val", value.to_string().as_str()));
+    writer.write_event(Event::Start(BytesStart::new("a:lnSpc"))).ok();
+    writer.write_event(Event::Empty(spacing)).ok();
+    writer.write_event(Event::End(BytesEnd::new("a:lnSpc"))).ok();
+}
+
+/// `a:rPr` children that come after `a:highlight` in the schema sequence.
+const PPTX_AFTER_HIGHLIGHT: &[&[u8]] = &[
+    b"a:uLnTx", b"a:uLn", b"a:uFillTx", b"a:uFill", b"a:latin", b"a:ea", b"a:cs", b"a:sym",
+    b"a:hlinkClick", b"a:hlinkMouseOver", b"a:rtl", b"a:extLst",
+];
+
+/// Highlight flagging an uncertain conversion, inside and without an `a:rPr`.
+const PPTX_HIGHLIGHT: &[u8] = br#"<a:highlight><a:srgbClr val="FFFF00"/></a:highlight>"#;
+const PPTX_HIGHLIGHT_PROPS: &[u8] = br#"<a:rPr><a:highlight><a:srgbClr val="FFFF00"/></a:highlight></a:rPr>"#;
+
+/// PowerPoint's run size, in hundredths of a point, when no list style sets one.
+const PPTX_DEFAULT_SIZE: &str = "1800";
+
+/// `a:rPr` of a scaled run: its own `sz` scaled, or the size it inherits scaled and
+/// written out when it has none.
+fn pptx_scaled_props(elem: &BytesStart, scaling: &ScalingRule, inherited_size: Option<&str>) -> BytesStart<'static> {
+    if elem.try_get_attribute("sz").ok().flatten().is_some() {
+        return scale_attr(elem, b"sz", |value| scaling.scale_size(value));
+    }
+    let mut new_elem = elem.clone().into_owned();
+    if let Some(size) = scaling.scale_size(inherited_size.unwrap_or(PPTX_DEFAULT_SIZE)) {
+        new_elem.push_attribute(("sz", size.as_str()));
+    }
+    new_elem
+}
+
+/// Before the first child of a run without `a:rPr`: writes one holding the scaled
+/// size the run inherits, if it is scaled. Returns where a highlight would go.
+fn pptx_add_props(
+    writer: &mut quick_xml::Writer<Vec<u8>>,
+    scaling: Option<&ScalingRule>,
+    inherited_size: Option<&str>,
+    flag_uncertain: bool,
+) -> Option<RunMark> {
+    use quick_xml::events::{BytesEnd, Event};
+
+    let Some(scaling) = scaling else {
+        return flag_uncertain.then(|| (writer.get_ref().len(), PPTX_HIGHLIGHT_PROPS));
+    };
+    writer
+        .write_event(Event::Start(pptx_scaled_props(&BytesStart::new("a:rPr"), scaling, inherited_size)))
+        .ok();
+    let mark = flag_uncertain.then(|| (writer.get_ref().len(), PPTX_HIGHLIGHT));
+    writer.write_event(Event::End(BytesEnd::new("a:rPr"))).ok();
+    mark
+}
+
+/// Writes an `a:pPr` holding only the line spacing of a scaled paragraph.
+fn write_pptx_ppr_spacing(writer: &mut quick_xml::Writer<Vec<u8>>, ppr: BytesStart, scaling: &ScalingRule) {
+    use quick_xml::events::{BytesEnd, Event};
+
+    writer.write_event(Event::Start(ppr)).ok();
+    write_pptx_line_spacing(writer, scaling);
+    writer.write_event(Event::End(BytesEnd::new("a:pPr"))).ok();
+}
+
+/// Line spacing of the paragraphs with scaled runs: an `a:lnSpc` at the start of each
+/// paragraph's `a:pPr`, or the paragraph's own spacing scaled.
+#[derive(Default)]
+struct PptxParagraphSpacing<'o> {
+    /// Line spacing still to write for the paragraph just opened, before its first child.
+    pending_paragraph: Option<&'o ScalingRule>,
+    /// Line spacing for the a:pPr just opened, unless its first child is an a:lnSpc.
+    pending_ppr: Option<&'o ScalingRule>,
+    /// Inside the a:lnSpc of a scaled paragraph.
+    scale_spacing: Option<&'o ScalingRule>,
+}
+
+impl<'o> PptxParagraphSpacing<'o> {
+    /// An `a:p` starts; `scaling` is set when the paragraph has scaled runs.
+    fn open_paragraph(&mut self, scaling: Option<&'o ScalingRule>) {
+        self.pending_paragraph = scaling;
+    }
+
+    /// Before the start tag `elem` is written. The paragraph's `a:pPr` is written here;
+    /// returns whether it was.
+    fn start(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, elem: &BytesStart) -> bool {
+        use quick_xml::events::Event;
+
+        let name = elem.name();
+        if let Some(scaling) = self.pending_paragraph.take() {
+            if name.as_ref() == b"a:pPr" {
+                self.pending_ppr = Some(scaling);
+                writer.write_event(Event::Start(elem.clone().into_owned())).ok();
+                return true;
             }
-            Ok(Event::Text(e)) => {
-                if let Some(ref cell_type) = current_cell_type {
-                    if cell_type == "s" {
-                        if let Some(style_idx) = current_cell_style {
-                            if let Some(font_id) = xf_font_ids.get(style_idx) {
-                                if source_font_ids.contains(font_id) {
-                                    let text = e.unescape().unwrap_or_default().to_string();
-                                    if let Ok(idx) = text.parse::<usize>() {
-                                        out.insert(idx);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+            write_pptx_ppr_spacing(writer, BytesStart::new("a:pPr"), scaling);
+        } else if let Some(scaling) = self.pending_ppr.take() {
+            if name.as_ref() == b"a:lnSpc" {
+                self.scale_spacing = Some(scaling);
+            } else {
+                write_pptx_line_spacing(writer, scaling);
             }
-            Ok(Event::End(e)) => {
-                if e.name().as_ref() == b"c" {
-                    current_cell_style = None;
-                    current_cell_type = None;
-                }
+        }
+        false
+    }
+
+    /// Before the empty element `elem` is written. An empty `a:pPr` that gets the
+    /// spacing is written here in full; returns whether it was.
+    fn empty(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, elem: &BytesStart) -> bool {
+        if let Some(scaling) = self.pending_paragraph.take() {
+            let is_ppr = elem.name().as_ref() == b"a:pPr";
+            let ppr = if is_ppr { elem.clone().into_owned() } else { BytesStart::new("a:pPr") };
+            write_pptx_ppr_spacing(writer, ppr, scaling);
+            return is_ppr;
+        }
+        if let Some(scaling) = self.pending_ppr.take() {
+            write_pptx_line_spacing(writer, scaling);
+        }
+        false
+    }
+
+    /// Before the end tag `name` is written.
+    fn end(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, name: &[u8]) {
+        if let Some(scaling) = self.pending_paragraph.take() {
+            write_pptx_ppr_spacing(writer, BytesStart::new("a:pPr"), scaling);
+        } else if let Some(scaling) = self.pending_ppr.take() {
+            write_pptx_line_spacing(writer, scaling);
+        }
+        if name == b"a:lnSpc" {
+            self.scale_spacing = None;
+        }
+    }
+
+    /// Replacement for the value of the paragraph's own line spacing.
+    fn rewrite(&self, elem: &BytesStart) -> Option<BytesStart<'static>> {
+        let scaling = self.scale_spacing.filter(|_| matches!(elem.name().as_ref(), b"a:spcPct" | b"a:spcPts"))?;
+        Some(scale_attr(elem, b"val", |value| {
+            value.parse::<f64>().ok().and_then(|value| scaling.scale_line(value)).map(|value| value.to_string())
+        }))
+    }
+}
+
+/// The DrawingML run being rewritten and the rule its text converts with.
+#[derive(Default)]
+struct PptxRun<'r, 'o> {
+    active: bool,
+    rule: Option<&'r FontRule>,
+    /// The run names a Latin font; runs without one take theirs from the shape, layout
+    /// and master.
+    has_latin: bool,
+    scaling: Option<&'o ScalingRule>,
+    /// The run's properties, which key the group of runs its text is converted with.
+    format: Vec<u8>,
+    rpr_depth: usize,
+    in_text: bool,
+    /// Where the run is highlighted if its conversion is uncertain; while
+    /// `mark_pending`, the place inside its a:rPr is still to be found.
+    props_seen: bool,
+    mark_pending: bool,
+    mark: Option<RunMark>,
+}
+
+impl<'r, 'o> PptxRun<'r, 'o> {
+    /// A run starts; an `a:r` also gets the scaling of its font pair.
+    fn start(&mut self) {
+        self.active = true;
+        self.rule = None;
+        self.has_latin = false;
+        self.format.clear();
+    }
+
+    fn start_text_run(&mut self, scaling: Option<&'o ScalingRule>) {
+        self.scaling = scaling;
+        self.props_seen = false;
+        self.mark = None;
+    }
+
+    fn end(&mut self) {
+        self.active = false;
+        self.rule = None;
+        self.scaling = None;
+    }
+
+    /// Before the first child `name` of the run; a run without `a:rPr` gets one here.
+    fn first_child(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, name: &[u8], inherited_size: Option<&str>, flag_uncertain: bool) {
+        self.props_seen = true;
+        if name != b"a:rPr" {
+            self.mark = pptx_add_props(writer, self.scaling, inherited_size, flag_uncertain);
+        }
+    }
+
+    /// Writes an empty `a:rPr` of the run in full, to hold the highlight.
+    fn expand_empty_props(&mut self, writer: &mut quick_xml::Writer<Vec<u8>>, elem: &BytesStart, inherited_size: Option<&str>) {
+        use quick_xml::events::{BytesEnd, Event};
+
+        self.props_seen = true;
+        let new_elem = match self.scaling {
+            Some(scaling) => pptx_scaled_props(elem, scaling, inherited_size),
+            None => elem.clone().into_owned(),
+        };
+        writer.write_event(Event::Start(new_elem)).ok();
+        self.mark = Some((writer.get_ref().len(), PPTX_HIGHLIGHT));
+        writer.write_event(Event::End(BytesEnd::new("a:rPr"))).ok();
+    }
+
+    /// Records where the highlight goes when `name`, a child of the run's `a:rPr`, is
+    /// about to be written. A run with a highlight of its own is not flagged.
+    fn place_mark(&mut self, writer: &quick_xml::Writer<Vec<u8>>, name: &[u8]) {
+        if name == b"a:highlight" {
+            self.mark_pending = false;
+        } else if PPTX_AFTER_HIGHLIGHT.contains(&name) {
+            self.mark_pending = false;
+            self.mark = Some((writer.get_ref().len(), PPTX_HIGHLIGHT));
+        }
+    }
+
+    /// Replacement for a font element, which updates the run's rule, or for the run's
+    /// `a:rPr`, which gets the scaled size.
+    fn rewrite_prop(&mut self, elem: &BytesStart, rules: &'r FontRules, styles: &SlideStyles, shape: &ShapeStyle) -> Option<BytesStart<'static>> {
+        let name = elem.name();
+        if let Some(slot) = pptx_font_slot(name.as_ref()) {
+            let (new_elem, matched) = rewrite_font_attrs(elem, rules, &|key| is_typeface_attr(key).then_some(slot));
+            if self.active && slot == FontSlot::Ascii {
+                self.has_latin = true;
+                self.rule = matched.or_else(|| styles.latin_rule(rules, elem)).or(self.rule);
             }
-            Ok(Event::Eof) => break,
-            Err(_) => break,
-            _ => {}
+            return Some(new_elem);
         }
-        buf.clear();
+        let scaling = self.scaling.filter(|_| self.active && name.as_ref() == b"a:rPr")?;
+        Some(pptx_scaled_props(elem, scaling, styles.inherited_size(shape)))
     }
 }
 
-fn process_pptx_slide(handle: &AppHandle, contents: &[u8], source_font: &str, _total_steps: usize) -> Vec<u8> {
-    use quick_xml::events::{BytesStart, BytesText, Event};
+fn process_pptx_slide<R: Runtime>(
+    handle: &AppHandle<R>,
+    contents: &[u8],
+    rules: &FontRules,
+    styles: &SlideStyles,
+    options: &ConversionOptions,
+    report: &mut ContainerReport,
+    _total_steps: usize,
+) -> Vec<u8> {
+    use quick_xml::events::{BytesText, Event};
     use quick_xml::{Reader, Writer};
 
     log::debug!("Starting PPTX slide XML processing, size: {} bytes", contents.len());
+    let run_font = |elem: &BytesStart| (elem.name().as_ref() == b"a:latin").then(|| styles.latin_typeface(elem)).flatten();
+    let scaled = scan_scaled_text(contents, rules, options, b"a:r", b"a:p", &run_font);
     let mut reader = Reader::from_reader(contents);
     reader.trim_text(false);
     let mut writer = Writer::new(Vec::with_capacity(contents.len()));
 
     let mut buf = Vec::new();
-    let mut in_run = false;
-    let mut run_has_font = false;
+    let mut run = PptxRun::default();
+    // Runs without an a:latin take their font from the shape, layout and master.
+    let mut shape = ShapeStyle::default();
     let mut event_count = 0usize;
     let mut last_progress = 0usize;
-
-    fn tag_matches(name: &[u8], local: &[u8]) -> bool {
-        if name == local {
-            return true;
-        }
-        if name.ends_with(local) {
-            let idx = name.len() - local.len();
-            return idx > 0 && name[idx - 1] == b':';
-        }
-        false
-    }
+    let mut run_count = 0usize;
+    let mut paragraph_count = 0usize;
+    let mut spacing = PptxParagraphSpacing::default();
+    let mut merger = RunMerger::default();
+    let mut containers: Vec<&'static str> = Vec::new();
 
     loop {
         event_count += 1;
@@ -793,83 +2943,138 @@ fn process_pptx_slide(handle: &AppHandle, contents: &[u8], source_font: &str, _t
             Ok(Event::Start(e)) => {
                 let elem = e.into_owned();
                 let name = elem.name().as_ref().to_vec();
+                shape.open(&elem);
+
+                if run.rpr_depth > 0 {
+                    run.rpr_depth += 1;
+                    run_merge::record_format(&mut run.format, &elem, PPTX_IGNORED_RUN_ATTRS);
+                } else if name.as_slice() == b"a:rPr" && run.active {
+                    run.rpr_depth = 1;
+                    run_merge::record_format(&mut run.format, &elem, PPTX_IGNORED_RUN_ATTRS);
+                } else if name.as_slice() == b"a:t" && run.active {
+                    run.in_text = true;
+                    if run.rule.is_none() && !run.has_latin {
+                        run.rule = styles.inherited_rule(rules, &shape);
+                    }
+                } else if !PPTX_RUN_JOINERS.contains(&name.as_slice()) {
+                    merger.flush();
+                }
+                if let Some(container) = text_container(&name) {
+                    containers.push(container);
+                }
+
+                if spacing.start(&mut writer, &elem) {
+                    buf.clear();
+                    continue;
+                }
+                if name.as_slice() == b"a:p" {
+                    spacing.open_paragraph(scaled.paragraphs.get(&paragraph_count).copied());
+                    paragraph_count += 1;
+                }
 
                 if tag_matches(&name, b"r") {
-                    in_run = true;
-                    run_has_font = false;
+                    run.start();
+                }
+                if name.as_slice() == b"a:r" {
+                    let scaling = scaled.runs.get(&run_count).copied().or_else(|| {
+                        let rule = styles.inherited_rule(rules, &shape).filter(|_| !scaled.named_runs.contains(&run_count))?;
+                        options.scaling_for(rule)
+                    });
+                    run.start_text_run(scaling);
+                    run_count += 1;
+                } else if run.active && !run.props_seen {
+                    run.first_child(&mut writer, &name, styles.inherited_size(&shape), options.flag_uncertain);
+                } else if run.mark_pending && run.rpr_depth == 2 {
+                    run.place_mark(&writer, &name);
                 }
 
-                if tag_matches(&name, b"rPr") && in_run {
-                    let tag = String::from_utf8_lossy(&name).to_string();
-                    let mut new_elem = BytesStart::new(tag.as_str());
-                    for attr in elem.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_typeface = key == b"typeface" || key.ends_with(b":typeface");
-                        if is_typeface && value == source_font {
-                            run_has_font = true;
-                            new_elem.push_attribute((key, TARGET_FONT.as_bytes()));
-                        } else {
-                            new_elem.push_attribute((key, value.as_bytes()));
-                        }
-                    }
-                    writer.write_event(Event::Start(new_elem)).ok();
-                } else if tag_matches(&name, b"latin") && in_run {
-                    let tag = String::from_utf8_lossy(&name).to_string();
-                    let mut new_elem = BytesStart::new(tag.as_str());
-                    for attr in elem.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_typeface = key == b"typeface" || key.ends_with(b":typeface");
-                        if is_typeface && value == source_font {
-                            run_has_font = true;
-                            new_elem.push_attribute((key, TARGET_FONT.as_bytes()));
-                        } else {
-                            new_elem.push_attribute((key, value.as_bytes()));
-                        }
-                    }
-                    writer.write_event(Event::Start(new_elem)).ok();
-                } else {
-                    writer.write_event(Event::Start(elem)).ok();
+                let new_elem = run.rewrite_prop(&elem, rules, styles, &shape).unwrap_or(elem);
+                writer.write_event(Event::Start(new_elem)).ok();
+                if name.as_slice() == b"a:rPr" && run.active && run.rpr_depth == 1 {
+                    run.mark_pending = options.flag_uncertain;
                 }
             }
             Ok(Event::Empty(e)) => {
                 let elem = e.into_owned();
                 let name = elem.name().as_ref().to_vec();
+                shape.empty(&elem);
 
-                if (tag_matches(&name, b"rPr") || tag_matches(&name, b"latin")) && in_run {
-                    let tag = String::from_utf8_lossy(&name).to_string();
-                    let mut new_elem = BytesStart::new(tag.as_str());
-                    for attr in elem.attributes().flatten() {
-                        let key = attr.key.as_ref();
-                        let value = attr.unescape_value().unwrap_or_default().to_string();
-                        let is_typeface = key == b"typeface" || key.ends_with(b":typeface");
-                        if is_typeface && value == source_font {
-                            run_has_font = true;
-                            new_elem.push_attribute((key, TARGET_FONT.as_bytes()));
-                        } else {
-                            new_elem.push_attribute((key, value.as_bytes()));
-                        }
+                if run.rpr_depth > 0 || (name.as_slice() == b"a:rPr" && run.active) {
+                    run_merge::record_format(&mut run.format, &elem, PPTX_IGNORED_RUN_ATTRS);
+                } else if !PPTX_RUN_JOINERS.contains(&name.as_slice()) {
+                    merger.flush();
+                }
+
+                if spacing.empty(&mut writer, &elem) {
+                    buf.clear();
+                    continue;
+                }
+                if name.as_slice() == b"a:p" {
+                    paragraph_count += 1;
+                }
+                if name.as_slice() == b"a:r" {
+                    run_count += 1;
+                } else if run.active && !run.props_seen {
+                    if name.as_slice() == b"a:rPr" && options.flag_uncertain {
+                        run.expand_empty_props(&mut writer, &elem, styles.inherited_size(&shape));
+                        buf.clear();
+                        continue;
                     }
-                    writer.write_event(Event::Empty(new_elem)).ok();
-                } else {
-                    writer.write_event(Event::Empty(elem)).ok();
+                    run.first_child(&mut writer, &name, styles.inherited_size(&shape), options.flag_uncertain);
+                } else if run.mark_pending && run.rpr_depth == 1 {
+                    run.place_mark(&writer, &name);
                 }
+
+                let new_elem = run
+                    .rewrite_prop(&elem, rules, styles, &shape)
+                    .or_else(|| spacing.rewrite(&elem))
+                    .unwrap_or(elem);
+                writer.write_event(Event::Empty(new_elem)).ok();
             }
             Ok(Event::Text(e)) => {
-                if in_run && run_has_font {
-                    let text = e.unescape().unwrap_or_default().to_string();
-                    let converted = win_to_myanmar3(&text);
-                    let new_text = BytesText::new(&converted);
-                    writer.write_event(Event::Text(new_text)).ok();
-                } else {
-                    writer.write_event(Event::Text(e.into_owned())).ok();
+                match run.rule.filter(|_| run.active) {
+                    Some(rule) if run.in_text => {
+                        // Written by the merger once the run group is complete.
+                        let text = e.unescape().unwrap_or_default().to_string();
+                        report.record(containers.last().copied().unwrap_or("slide"));
+                        merger.push(rule, &run.format, writer.get_ref().len(), text, run.mark, None);
+                    }
+                    Some(rule) => {
+                        report.record(containers.last().copied().unwrap_or("slide"));
+                        let text = e.unescape().unwrap_or_default().to_string();
+                        let converted = rule.mapping.convert(&text);
+                        let new_text = BytesText::new(&converted);
+                        writer.write_event(Event::Text(new_text)).ok();
+                    }
+                    None => {
+                        if run.in_text {
+                            merger.flush();
+                        }
+                        writer.write_event(Event::Text(e.into_owned())).ok();
+                    }
                 }
             }
             Ok(Event::End(e)) => {
-                if tag_matches(e.name().as_ref(), b"r") {
-                    in_run = false;
-                    run_has_font = false;
+                let name = e.name().as_ref().to_vec();
+                shape.close(&name);
+                if run.mark_pending && run.rpr_depth == 1 {
+                    run.place_mark(&writer, b"a:extLst");
+                }
+                if run.rpr_depth > 0 {
+                    run_merge::record_format_end(&mut run.format, &name);
+                    run.rpr_depth -= 1;
+                } else if name.as_slice() == b"a:t" {
+                    run.in_text = false;
+                } else if !PPTX_RUN_JOINERS.contains(&name.as_slice()) {
+                    merger.flush();
+                }
+                if text_container(&name).is_some() {
+                    containers.pop();
+                }
+
+                spacing.end(&mut writer, &name);
+                if tag_matches(&name, b"r") {
+                    run.end();
                 }
                 writer.write_event(Event::End(e.into_owned())).ok();
             }
@@ -897,9 +3102,53 @@ fn process_pptx_slide(handle: &AppHandle, contents: &[u8], source_font: &str, _t
     log::debug!("PPTX slide XML processing completed, {} events processed", event_count);
     emit_progress(handle, 46, 50, "Slide content processed");
 
-    writer.into_inner()
+    merger.flush();
+    report.flagged += merger.flagged;
+    merger.finish(writer.into_inner())
 }
 
+/// Converts legacy PPTX comments. Their `p:text` has no font of its own and shows
+/// in the presentation's default text font, whose rule is `rule`.
+fn process_pptx_comments(contents: &[u8], rule: Option<&FontRule>, report: &mut ContainerReport) -> Vec<u8> {
+    use quick_xml::events::{BytesText, Event};
+    use quick_xml::{Reader, Writer};
+
+    let Some(rule) = rule else {
+        return contents.to_vec();
+    };
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+    let mut buf = Vec::new();
+    let mut in_text = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                in_text = e.name().as_ref() == b"p:text";
+                writer.write_event(Event::Start(e.into_owned())).ok();
+            }
+            Ok(Event::Text(e)) if in_text => {
+                report.record("comments");
+                let text = e.unescape().unwrap_or_default().to_string();
+                writer.write_event(Event::Text(BytesText::new(&rule.mapping.convert(&text)))).ok();
+            }
+            Ok(Event::End(e)) => {
+                in_text = false;
+                writer.write_event(Event::End(e.into_owned())).ok();
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -911,7 +3160,195 @@ pub fn run() {
                 .level(log::LevelFilter::Debug)
                 .build(),
         )
-        .invoke_handler(tauri::generate_handler![convert_file, convert_text])
+        .invoke_handler(tauri::generate_handler![
+            convert_file,
+            convert_text,
+            list_document_fonts,
+            get_default_target_font,
+            set_default_target_font
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn archive(entries: &[(&str, &str)]) -> zip::ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            writer.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        zip::ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn rules() -> FontRules {
+        FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap()
+    }
+
+    fn convert_docx(xml: &str, options: &ConversionOptions) -> (String, ContainerReport) {
+        let app = tauri::test::mock_app();
+        let mut report = ContainerReport::default();
+        let output = process_docx_xml(app.handle(), xml.as_bytes(), &rules(), &DocxStyles::default(), options, &mut report, None);
+        (String::from_utf8(output).unwrap(), report)
+    }
+
+    const WIN_PROPS: &str = r#"<w:rPr><w:rFonts w:ascii="Win Innwa" w:hAnsi="Win Innwa"/></w:rPr>"#;
+
+    /// Cell style 1 is set in Win Innwa, cell style 0 in Arial.
+    const XLSX_STYLES: &str = r#"<styleSheet><fonts><font><name val="Arial"/></font><font><name val="Win Innwa"/></font></fonts><cellXfs><xf fontId="0"/><xf fontId="1"/></cellXfs></styleSheet>"#;
+
+    fn convert_worksheet(worksheet: &str, rules: &FontRules, shared_plan: &SharedStringPlan) -> String {
+        let cell_styles = XlsxCellStyles::parse(XLSX_STYLES.as_bytes(), rules, &ThemeFonts::default());
+        let plans = XlsxPlans {
+            cell_styles: &cell_styles,
+            shared_plan,
+            refs: &References::default(),
+            style_clones: &StyleClones::default(),
+            digit_formats: &DigitFormats::default(),
+        };
+        let sheet = WorksheetPlan {
+            cells: &SheetCells::All,
+            conditional_fonts: ConditionalFonts::scan(worksheet.as_bytes(), &cell_styles),
+            validation_fonts: ValidationFonts::scan(worksheet.as_bytes()),
+        };
+        let output = process_xlsx_worksheet(worksheet.as_bytes(), Vec::new(), rules, &plans, sheet, &Default::default(), &mut ContainerReport::default());
+        String::from_utf8(output.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn story_parts_follow_the_main_document_relationships() {
+        let rels = r#"<Relationships><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/header" Target="header1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/footnotes" Target="/word/notes.xml"/><Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/><Relationship Id="rId4" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink" Target="http://example.com" TargetMode="External"/></Relationships>"#;
+        let mut archive = archive(&[("word/_rels/document.xml.rels", rels)]);
+        let parts = docx_story_parts(&mut archive, "word/document.xml");
+        let expected = ["word/document.xml", "word/header1.xml", "word/notes.xml"];
+        assert_eq!(parts, expected.iter().map(|part| part.to_string()).collect());
+    }
+
+    #[test]
+    fn text_boxes_equations_and_content_controls_are_converted() {
+        let xml = format!(
+            r#"<w:body><w:p><w:r>{WIN_PROPS}<w:drawing><wps:txbx><w:txbxContent><w:p><w:r>{WIN_PROPS}<w:t>u</w:t></w:r><w:r><w:t>u</w:t></w:r></w:p></w:txbxContent></wps:txbx></w:drawing><w:t>c</w:t></w:r><m:oMath><m:r>{WIN_PROPS}<m:t>u</m:t></m:r></m:oMath></w:p><w:sdt><w:sdtPr><w:rPr><w:rFonts w:ascii="Win Innwa"/></w:rPr><w:dropDownList><w:listItem w:displayText="u" w:value="u"/></w:dropDownList></w:sdtPr><w:sdtContent><w:p><w:r>{WIN_PROPS}<w:t>u</w:t></w:r></w:p></w:sdtContent></w:sdt></w:body>"#
+        );
+        let (output, report) = convert_docx(&xml, &Default::default());
+        // The text box run has its own font; the one without keeps its text.
+        assert!(output.contains("<w:t>\u{1000}</w:t></w:r><w:r><w:t>u</w:t></w:r>"), "{}", output);
+        // The run around the text box carries on in its own font after it.
+        assert!(output.contains("</w:drawing><w:t>\u{1001}</w:t>"), "{}", output);
+        assert!(output.contains("<m:t>\u{1000}</m:t>"), "{}", output);
+        assert!(output.contains("<w:listItem w:displayText=\"\u{1000}\" w:value=\"u\"/>"), "{}", output);
+        let usage: Vec<(String, usize)> = report.usage().into_iter().map(|usage| (usage.name, usage.count)).collect();
+        let expected = [("body", 1), ("content control", 2), ("equation", 1), ("text box", 1)];
+        assert_eq!(usage, expected.map(|(name, count)| (name.to_string(), count)));
+    }
+
+    #[test]
+    fn smart_art_parts_are_found_from_stories() {
+        let rels = r#"<Relationships><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/diagramData" Target="diagrams/data1.xml"/><Relationship Id="rId2" Type="http://schemas.microsoft.com/office/2007/relationships/diagramDrawing" Target="diagrams/drawing1.xml"/><Relationship Id="rId3" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/diagramLayout" Target="diagrams/layout1.xml"/></Relationships>"#;
+        let mut archive = archive(&[("word/_rels/header1.xml.rels", rels)]);
+        let stories = HashSet::from(["word/document.xml".to_string(), "word/header1.xml".to_string()]);
+        let parts = docx_diagram_parts(&mut archive, &stories);
+        assert_eq!(parts, HashSet::from(["word/diagrams/data1.xml".to_string(), "word/diagrams/drawing1.xml".to_string()]));
+    }
+
+    #[test]
+    fn converted_runs_get_every_font_slot_and_the_myanmar_language() {
+        let options: ConversionOptions = serde_json::from_str(r#"{"setRunLanguage":true}"#).unwrap();
+        let xml = r#"<w:p><w:r><w:rPr><w:rFonts w:ascii="Win Innwa"/><w:b/><w:lang w:val="en-US" w:eastAsia="zh-CN"/></w:rPr><w:t>u</w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>u</w:t></w:r></w:p>"#;
+        let (output, _) = convert_docx(xml, &options);
+        let fonts = r#"<w:rFonts w:ascii="Myanmar Text" w:hAnsi="Myanmar Text" w:eastAsia="Myanmar Text" w:cs="Myanmar Text" w:hint="cs"/>"#;
+        assert!(output.contains(&format!(r#"<w:rPr>{fonts}<w:b/><w:lang w:val="my-MM" w:eastAsia="zh-CN" w:bidi="my-MM"/></w:rPr>"#)), "{}", output);
+        // Runs that are not converted keep their properties.
+        assert!(output.contains("<w:r><w:rPr><w:b/></w:rPr><w:t>u</w:t></w:r>"), "{}", output);
+
+        let (output, _) = convert_docx(xml, &Default::default());
+        assert!(output.contains(r#"<w:rFonts w:ascii="Myanmar Text"/><w:b/><w:lang w:val="en-US" w:eastAsia="zh-CN"/>"#), "{}", output);
+    }
+
+    #[test]
+    fn deleted_text_and_revision_fonts_are_converted() {
+        let xml = format!(
+            r#"<w:p><w:del w:id="1" w:author="A"><w:r>{WIN_PROPS}<w:delText>u</w:delText></w:r></w:del><w:ins w:id="2" w:author="A"><w:r>{WIN_PROPS}<w:t>c</w:t></w:r></w:ins><w:r><w:rPr><w:rFonts w:ascii="Arial"/><w:rPrChange w:id="3" w:author="A"><w:rPr><w:rFonts w:ascii="Win Innwa"/></w:rPr></w:rPrChange></w:rPr><w:t>u</w:t></w:r></w:p>"#
+        );
+        let (output, report) = convert_docx(&xml, &Default::default());
+        assert!(output.contains("<w:delText>\u{1000}</w:delText>"), "{}", output);
+        assert!(output.contains("<w:t>\u{1001}</w:t></w:r></w:ins>"), "{}", output);
+        // Rejecting the formatting change must not bring back the source font.
+        assert!(output.contains(r#"<w:rPrChange w:id="3" w:author="A"><w:rPr><w:rFonts w:ascii="Myanmar Text"/>"#), "{}", output);
+        assert!(output.contains(r#"<w:rFonts w:ascii="Arial"/>"#) && output.contains("<w:t>u</w:t>"), "{}", output);
+        let names: Vec<String> = report.usage().into_iter().map(|usage| usage.name).collect();
+        assert_eq!(names, ["deleted text", "inserted text"]);
+    }
+
+    #[test]
+    fn field_results_are_converted_and_field_codes_kept() {
+        let xml = format!(
+            r#"<w:p><w:r>{WIN_PROPS}<w:fldChar w:fldCharType="begin"/></w:r><w:r>{WIN_PROPS}<w:instrText xml:space="preserve"> PAGE </w:instrText></w:r><w:r>{WIN_PROPS}<w:fldChar w:fldCharType="separate"/></w:r><w:r>{WIN_PROPS}<w:t>12</w:t></w:r><w:r>{WIN_PROPS}<w:fldChar w:fldCharType="end"/></w:r><w:r>{WIN_PROPS}<w:t>u</w:t></w:r></w:p>"#
+        );
+        let (output, report) = convert_docx(&xml, &Default::default());
+        assert!(output.contains(r#"<w:instrText xml:space="preserve"> PAGE </w:instrText>"#), "{}", output);
+        assert!(output.contains("<w:t>\u{1041}\u{1042}</w:t>"), "{}", output);
+        let usage: Vec<(String, usize)> = report.usage().into_iter().map(|usage| (usage.name, usage.count)).collect();
+        assert_eq!(usage, [("body".to_string(), 1), ("field result".to_string(), 1)]);
+    }
+
+    #[test]
+    fn inline_strings_and_formula_results_are_converted() {
+        let worksheet = r#"<worksheet><sheetData><row r="1"><c r="A1" s="1" t="inlineStr"><is><t>u</t></is></c><c r="B1" s="1" t="str"><f>A1</f><v>c</v></c><c r="C1" s="0" t="inlineStr"><is><t>u</t></is></c><c r="D1" s="0" t="inlineStr"><is><r><rPr><rFont val="Win Innwa"/></rPr><t>u</t></r><r><t>u</t></r></is></c><c r="E1" s="1"><v>12</v></c></row></sheetData></worksheet>"#;
+        let output = convert_worksheet(worksheet, &rules(), &SharedStringPlan::default());
+        assert!(output.contains("<c r=\"A1\" s=\"1\" t=\"inlineStr\"><is><t>\u{1000}</t></is></c>"), "{}", output);
+        assert!(output.contains("<f>A1</f><v>\u{1001}</v>"), "{}", output);
+        assert!(output.contains(r#"<c r="C1" s="0" t="inlineStr"><is><t>u</t></is></c>"#), "{}", output);
+        // A rich text run in a source font is converted; the run in the cell's font is not.
+        assert!(output.contains("<rFont val=\"Myanmar Text\"/></rPr><t>\u{1000}</t></r><r><t>u</t></r>"), "{}", output);
+        assert!(output.contains("<v>12</v>"), "{}", output);
+    }
+
+    #[test]
+    fn shared_strings_used_in_other_fonts_are_copied() {
+        let rules = rules();
+        let mut uses = SharedStringUses::default();
+        uses.record(0, rules.find("Win Innwa"));
+        uses.record(0, None);
+        uses.record(1, rules.find("Win Innwa"));
+        let plan = uses.plan(2);
+
+        let table = r#"<sst count="3" uniqueCount="2"><si><t>u</t></si><si><t>c</t></si></sst>"#;
+        let output = String::from_utf8(process_shared_strings(table.as_bytes(), &rules, &plan)).unwrap();
+        assert_eq!(output, "<sst count=\"3\" uniqueCount=\"3\"><si><t>u</t></si><si><t>\u{1001}</t></si><si><t>\u{1000}</t></si></sst>");
+
+        // The Win Innwa cell moves to the converted copy; the Arial cell keeps the original.
+        let worksheet = r#"<worksheet><sheetData><row r="1"><c r="A1" s="1" t="s"><v>0</v></c><c r="B1" s="0" t="s"><v>0</v></c><c r="C1" s="1" t="s"><v>1</v></c></row></sheetData></worksheet>"#;
+        let output = convert_worksheet(worksheet, &rules, &plan);
+        assert!(output.contains(r#"<c r="A1" s="1" t="s"><v>2</v></c><c r="B1" s="0" t="s"><v>0</v></c><c r="C1" s="1" t="s"><v>1</v></c>"#), "{}", output);
+    }
+
+    #[test]
+    fn selected_sheets_take_their_notes_and_charts() {
+        let rel = |id: &str, kind: &str, target: &str| {
+            format!(r#"<Relationship Id="{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/{}" Target="{}"/>"#, id, kind, target)
+        };
+        let workbook_rels = format!("<Relationships>{}{}</Relationships>", rel("rId1", "worksheet", "worksheets/sheet1.xml"), rel("rId2", "worksheet", "worksheets/sheet2.xml"));
+        let sheet_rels = format!("<Relationships>{}{}</Relationships>", rel("rId1", "comments", "../comments1.xml"), rel("rId2", "drawing", "../drawings/drawing1.xml"));
+        let drawing_rels = format!("<Relationships>{}</Relationships>", rel("rId1", "chart", "../charts/chart1.xml"));
+        let mut archive = archive(&[
+            ("xl/workbook.xml", r#"<workbook><sheets><sheet name="Data" r:id="rId1"/><sheet name="Other" r:id="rId2"/></sheets></workbook>"#),
+            ("xl/_rels/workbook.xml.rels", &workbook_rels),
+            ("xl/worksheets/_rels/sheet1.xml.rels", &sheet_rels),
+            ("xl/drawings/_rels/drawing1.xml.rels", &drawing_rels),
+        ]);
+        let picks = serde_json::from_str::<Vec<options::SheetSelection>>(r#"[{"sheet":" data ","ranges":["B2:C3"]},{"sheet":"Missing"}]"#).unwrap();
+        let selection = XlsxSelection::load(&mut archive, &picks);
+
+        assert!(selection.is_active());
+        assert!(selection.includes_sheet("Data") && !selection.includes_sheet("Other"));
+        assert!(selection.includes_part("xl/comments1.xml") && selection.includes_part("xl/charts/chart1.xml"));
+        let cells = selection.cells("xl/worksheets/sheet1.xml");
+        assert!(cells.contains(Some("C3")) && !cells.contains(Some("A1")) && !cells.contains(None));
+        assert!(!selection.cells("xl/worksheets/sheet2.xml").contains(Some("A1")));
+        assert!(XlsxSelection::default().cells("xl/worksheets/sheet2.xml").is_all());
+    }
+}
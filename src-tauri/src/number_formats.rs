@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use quick_xml::events::{BytesStart, Event};
+
+use crate::options::DigitDisplay;
+use crate::xlsx_styles::XlsxCellStyles;
+
+/// Locale tag that shows a number format's digits as Myanmar digits: numeral
+/// system 0x10 (Myanmar) with the Burmese locale 0x0455.
+pub const MYANMAR_DIGITS_LOCALE: &str = "[$-10000455]";
+
+/// First ID available to custom number formats; lower IDs are built in.
+const FIRST_CUSTOM_ID: u32 = 164;
+
+/// Format code of a built-in SpreadsheetML number format.
+fn builtin_format(id: u32) -> Option<&'static str> {
+    let code = match id {
+        0 => "General",
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "mm-dd-yy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mmss.0",
+        48 => "##0.0E+0",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// ASCII digits of a cell value written as Myanmar digits, the way a Win font
+/// displayed them.
+pub fn myanmar_digits(value: &str) -> String {
+    value
+        .chars()
+        .map(|ch| match ch.to_digit(10) {
+            Some(digit) => char::from_u32(0x1040 + digit).unwrap_or(ch),
+            None => ch,
+        })
+        .collect()
+}
+
+fn attr_value(elem: &BytesStart, key: &[u8]) -> Option<String> {
+    elem.attributes()
+        .flatten()
+        .find(|attr| attr.key.as_ref() == key)
+        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+}
+
+/// Number formats added to styles.xml so cells styled in a source font keep
+/// showing Myanmar digits after the font swap.
+#[derive(Default)]
+pub struct DigitFormats {
+    /// New `numFmt` entries, by ID.
+    pub formats: BTreeMap<u32, String>,
+    /// New `numFmtId` of each `cellXfs` entry, by position.
+    pub xf_formats: HashMap<usize, u32>,
+    /// Whether styles.xml already has a `numFmts` list to add them to.
+    pub has_num_fmts: bool,
+    /// `cellXfs` entries with the General format, whose values `DigitDisplay::Text`
+    /// can write as text without losing a date or number format.
+    pub general_xfs: HashSet<usize>,
+}
+
+impl DigitFormats {
+    /// Gives every `cellXfs` entry whose effective font is a source font a copy of
+    /// its number format tagged with `MYANMAR_DIGITS_LOCALE`. Text formats and
+    /// formats that already carry a locale are left alone, and so is General with
+    /// `DigitDisplay::Text`, whose cells become digit text instead.
+    pub fn plan(contents: &[u8], cell_styles: &XlsxCellStyles, display: DigitDisplay) -> Self {
+        use quick_xml::Reader;
+
+        let mut reader = Reader::from_reader(contents);
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+        let mut custom: HashMap<u32, String> = HashMap::new();
+        // numFmtId of each cellXfs entry.
+        let mut xfs: Vec<u32> = Vec::new();
+        let mut has_num_fmts = false;
+        let mut in_num_fmts = false;
+        let mut in_cell_xfs = false;
+
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    match name.as_slice() {
+                        b"numFmts" => {
+                            has_num_fmts = true;
+                            in_num_fmts = true;
+                        }
+                        b"cellXfs" => in_cell_xfs = true,
+                        b"numFmt" if in_num_fmts => {
+                            let id = attr_value(&e, b"numFmtId").and_then(|id| id.parse::<u32>().ok());
+                            if let (Some(id), Some(code)) = (id, attr_value(&e, b"formatCode")) {
+                                custom.insert(id, code);
+                            }
+                        }
+                        b"xf" if in_cell_xfs => {
+                            let num_fmt_id = attr_value(&e, b"numFmtId").and_then(|id| id.parse().ok()).unwrap_or(0);
+                            xfs.push(num_fmt_id);
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(Event::End(e)) => match e.name().as_ref() {
+                    b"numFmts" => in_num_fmts = false,
+                    b"cellXfs" => in_cell_xfs = false,
+                    _ => {}
+                },
+                Ok(Event::Eof) => break,
+                Err(_) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        let mut next_id = custom.keys().map(|id| id + 1).max().unwrap_or(0).max(FIRST_CUSTOM_ID);
+        let mut tagged: HashMap<u32, u32> = HashMap::new();
+        let mut digit_formats = DigitFormats {
+            has_num_fmts,
+            ..Default::default()
+        };
+        for (xf_index, num_fmt_id) in xfs.into_iter().enumerate() {
+            let code = custom.get(&num_fmt_id).map(String::as_str).or_else(|| builtin_format(num_fmt_id));
+            let general = code.is_some_and(|code| code.eq_ignore_ascii_case("General"));
+            if general {
+                digit_formats.general_xfs.insert(xf_index);
+            }
+            if cell_styles.rule(xf_index).is_none() || (general && display == DigitDisplay::Text) {
+                continue;
+            }
+            if let Some(new_id) = tagged.get(&num_fmt_id) {
+                digit_formats.xf_formats.insert(xf_index, *new_id);
+                continue;
+            }
+            let code = match code {
+                Some(code) if !code.contains("[$-") && code != "@" => code,
+                _ => continue,
+            };
+            digit_formats.formats.insert(next_id, format!("{}{}", MYANMAR_DIGITS_LOCALE, code));
+            digit_formats.xf_formats.insert(xf_index, next_id);
+            tagged.insert(num_fmt_id, next_id);
+            next_id += 1;
+        }
+        digit_formats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.formats.is_empty()
+    }
+
+    /// Writes the new `numFmt` entries.
+    pub fn write_formats(&self, writer: &mut quick_xml::Writer<Vec<u8>>) {
+        for (id, code) in &self.formats {
+            let mut num_fmt = BytesStart::new("numFmt");
+            num_fmt.push_attribute(("numFmtId", id.to_string().as_str()));
+            num_fmt.push_attribute(("formatCode", code.as_str()));
+            writer.write_event(Event::Empty(num_fmt)).ok();
+        }
+    }
+
+    /// Copy of the `cellXfs` entry at `xf_index` pointing at its tagged format.
+    pub fn rewrite_xf(&self, elem: &BytesStart, xf_index: usize) -> Option<BytesStart<'static>> {
+        let new_id = self.xf_formats.get(&xf_index)?.to_string();
+        let mut new_elem = BytesStart::new("xf");
+        for attr in elem.attributes().flatten() {
+            let key = attr.key.as_ref();
+            if key != b"numFmtId" && key != b"applyNumberFormat" {
+                new_elem.push_attribute(attr);
+            }
+        }
+        new_elem.push_attribute(("numFmtId", new_id.as_str()));
+        new_elem.push_attribute(("applyNumberFormat", "1"));
+        Some(new_elem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font_rules::FontRules;
+
+    const STYLES: &str = r#"<styleSheet><numFmts count="1"><numFmt numFmtId="164" formatCode="0.0"/></numFmts><fonts><font><name val="Arial"/></font><font><name val="Win Innwa"/></font></fonts><cellXfs><xf numFmtId="0" fontId="0"/><xf numFmtId="0" fontId="1"/><xf numFmtId="14" fontId="1"/><xf numFmtId="164" fontId="1"/><xf numFmtId="49" fontId="1"/><xf numFmtId="14" fontId="1"/></cellXfs></styleSheet>"#;
+
+    fn plan(display: DigitDisplay) -> DigitFormats {
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        let cell_styles = XlsxCellStyles::parse(STYLES.as_bytes(), &rules, &Default::default());
+        DigitFormats::plan(STYLES.as_bytes(), &cell_styles, display)
+    }
+
+    #[test]
+    fn source_font_styles_get_tagged_formats() {
+        let formats = plan(DigitDisplay::NumberFormat);
+        assert!(formats.has_num_fmts);
+        assert_eq!(
+            formats.formats,
+            BTreeMap::from([
+                (165, "[$-10000455]General".to_string()),
+                (166, "[$-10000455]mm-dd-yy".to_string()),
+                (167, "[$-10000455]0.0".to_string()),
+            ])
+        );
+        // Styles sharing a format share its tagged copy; the text format is left alone.
+        assert_eq!(formats.xf_formats, HashMap::from([(1, 165), (2, 166), (3, 167), (5, 166)]));
+        assert_eq!(formats.general_xfs, HashSet::from([0, 1]));
+    }
+
+    #[test]
+    fn general_cells_written_as_text_keep_their_format() {
+        let formats = plan(DigitDisplay::Text);
+        assert!(!formats.formats.values().any(|code| code.ends_with("General")));
+        assert!(!formats.xf_formats.contains_key(&1) && formats.xf_formats.contains_key(&2));
+    }
+
+    #[test]
+    fn digits_are_written_in_myanmar() {
+        assert_eq!(myanmar_digits("-12.50"), "-\u{1041}\u{1042}.\u{1045}\u{1040}");
+    }
+}
@@ -0,0 +1,244 @@
+use quick_xml::events::{BytesEnd, BytesStart, Event};
+
+use crate::docx_styles::{self, DocxStyles};
+use crate::font_rules::{FontRule, FontRules};
+use crate::options::ConversionOptions;
+use crate::{convert_attr, docx_font_slot, rewrite_font_attrs, ContainerReport};
+
+const MC_NAMESPACE: &str = "http://schemas.openxmlformats.org/markup-compatibility/2006";
+const W14_NAMESPACE: &str = "http://schemas.microsoft.com/office/word/2010/wordml";
+
+/// Word 2010 custom number format with Myanmar digits for a decimal `w:numFmt`.
+fn myanmar_format(num_fmt: &str) -> Option<&'static str> {
+    match num_fmt {
+        "decimal" => Some("\u{1041}, \u{1042}, \u{1043}, ..."),
+        "decimalZero" => Some("\u{1040}\u{1041}, \u{1040}\u{1042}, \u{1040}\u{1043}, ..."),
+        _ => None,
+    }
+}
+
+/// Rule of each `w:lvl` in document order, from the level's own run font or the
+/// style of the paragraphs it numbers.
+fn level_rules<'r>(contents: &[u8], rules: &'r FontRules, styles: &DocxStyles) -> Vec<Option<&'r FontRule>> {
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut levels = Vec::new();
+    // Paragraph style and run font of the level being read.
+    let mut level: Option<(Option<String>, Option<String>)> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                let name = e.name().as_ref().to_vec();
+                match name.as_slice() {
+                    b"w:lvl" => level = Some((None, None)),
+                    b"w:pStyle" => {
+                        if let Some((style, _)) = level.as_mut() {
+                            *style = docx_styles::val_attr(&e);
+                        }
+                    }
+                    b"w:rFonts" => {
+                        if let Some((_, font)) = level.as_mut() {
+                            *font = styles.rfonts_font(&e).or(font.take());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::End(e)) if e.name().as_ref() == b"w:lvl" => {
+                if let Some((style, font)) = level.take() {
+                    let font = font.as_deref().or_else(|| styles.inherited_font(None, style.as_deref()));
+                    levels.push(font.and_then(|font| rules.find(font)));
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    levels
+}
+
+/// Converts the literal text of a `w:lvlText`, leaving the `%1`-style level
+/// placeholders alone.
+fn convert_level_text(value: &str, rule: &FontRule) -> String {
+    let mut output = String::new();
+    let mut literal = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match chars.peek().copied() {
+            Some(level @ '1'..='9') if ch == '%' => {
+                chars.next();
+                output.push_str(&rule.mapping.convert(&literal));
+                literal.clear();
+                output.push('%');
+                output.push(level);
+            }
+            _ => literal.push(ch),
+        }
+    }
+    output.push_str(&rule.mapping.convert(&literal));
+    output
+}
+
+/// `w:numFmt` with Myanmar digits, falling back to `num_fmt` in Word versions
+/// without custom number formats.
+fn write_myanmar_num_fmt(writer: &mut quick_xml::Writer<Vec<u8>>, elem: &BytesStart, format: &str) {
+    let mut custom = BytesStart::new("w:numFmt");
+    custom.push_attribute(("w:val", "custom"));
+    custom.push_attribute(("w:format", format));
+    let mut choice = BytesStart::new("mc:Choice");
+    choice.push_attribute(("Requires", "w14"));
+
+    writer.write_event(Event::Start(BytesStart::new("mc:AlternateContent"))).ok();
+    writer.write_event(Event::Start(choice)).ok();
+    writer.write_event(Event::Empty(custom)).ok();
+    writer.write_event(Event::End(BytesEnd::new("mc:Choice"))).ok();
+    writer.write_event(Event::Start(BytesStart::new("mc:Fallback"))).ok();
+    writer.write_event(Event::Empty(elem.to_owned())).ok();
+    writer.write_event(Event::End(BytesEnd::new("mc:Fallback"))).ok();
+    writer.write_event(Event::End(BytesEnd::new("mc:AlternateContent"))).ok();
+}
+
+/// Declares the namespaces the Myanmar number formats need on the root element.
+fn with_namespaces(elem: BytesStart) -> BytesStart<'static> {
+    let mut root = elem.into_owned();
+    for (prefix, namespace) in [("xmlns:mc", MC_NAMESPACE), ("xmlns:w14", W14_NAMESPACE)] {
+        if root.try_get_attribute(prefix).ok().flatten().is_none() {
+            root.push_attribute((prefix, namespace));
+        }
+    }
+    root
+}
+
+/// Rewrites `word/numbering.xml`: list levels set in a source font get converted
+/// label text and the target font, and with `myanmar_numbering` their decimal
+/// numbers are written with Myanmar digits.
+pub fn process_numbering_xml(
+    contents: &[u8],
+    rules: &FontRules,
+    styles: &DocxStyles,
+    options: &ConversionOptions,
+    report: &mut ContainerReport,
+) -> Vec<u8> {
+    use quick_xml::{Reader, Writer};
+
+    let levels = level_rules(contents, rules, styles);
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+    let mut buf = Vec::new();
+    let mut level_count = 0usize;
+    let mut level_rule: Option<&FontRule> = None;
+    // Formats inside an mc:AlternateContent have a fallback already.
+    let mut alternate_depth = 0usize;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let name = e.name().as_ref().to_vec();
+                if name.as_slice() == b"w:numbering" && options.myanmar_numbering {
+                    writer.write_event(Event::Start(with_namespaces(e))).ok();
+                    buf.clear();
+                    continue;
+                }
+                if name.as_slice() == b"w:lvl" {
+                    level_rule = levels.get(level_count).copied().flatten();
+                    level_count += 1;
+                } else if name.as_slice() == b"mc:AlternateContent" {
+                    alternate_depth += 1;
+                }
+                if name.as_slice() == b"w:rFonts" {
+                    writer.write_event(Event::Start(rewrite_font_attrs(&e, rules, &docx_font_slot).0)).ok();
+                } else {
+                    writer.write_event(Event::Start(e.into_owned())).ok();
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                let name = e.name().as_ref().to_vec();
+                match (name.as_slice(), level_rule) {
+                    (b"w:rFonts", _) => {
+                        writer.write_event(Event::Empty(rewrite_font_attrs(&e, rules, &docx_font_slot).0)).ok();
+                    }
+                    (b"w:lvlText", Some(rule)) => {
+                        report.record("numbering");
+                        let new_elem = convert_attr(&e, b"w:val", |value| convert_level_text(value, rule));
+                        writer.write_event(Event::Empty(new_elem)).ok();
+                    }
+                    (b"w:numFmt", Some(_)) if options.myanmar_numbering && alternate_depth == 0 => {
+                        match docx_styles::val_attr(&e).as_deref().and_then(myanmar_format) {
+                            Some(format) => write_myanmar_num_fmt(&mut writer, &e, format),
+                            None => {
+                                writer.write_event(Event::Empty(e.into_owned())).ok();
+                            }
+                        }
+                    }
+                    _ => {
+                        writer.write_event(Event::Empty(e.into_owned())).ok();
+                    }
+                }
+            }
+            Ok(Event::End(e)) => {
+                if e.name().as_ref() == b"w:lvl" {
+                    level_rule = None;
+                } else if e.name().as_ref() == b"mc:AlternateContent" {
+                    alternate_depth = alternate_depth.saturating_sub(1);
+                }
+                writer.write_event(Event::End(e.into_owned())).ok();
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NUMBERING: &str = r#"<w:numbering><w:abstractNum w:abstractNumId="0"><w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/><w:lvlText w:val="u%1/"/><w:rPr><w:rFonts w:ascii="Win Innwa" w:hAnsi="Win Innwa"/></w:rPr></w:lvl><w:lvl w:ilvl="1"><w:numFmt w:val="decimal"/><w:lvlText w:val="(%2)"/><w:rPr><w:rFonts w:ascii="Arial"/></w:rPr></w:lvl></w:abstractNum></w:numbering>"#;
+
+    fn process(options: &ConversionOptions) -> (String, ContainerReport) {
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        let mut report = ContainerReport::default();
+        let output = process_numbering_xml(NUMBERING.as_bytes(), &rules, &DocxStyles::default(), options, &mut report);
+        (String::from_utf8(output).unwrap(), report)
+    }
+
+    #[test]
+    fn level_text_is_converted_around_placeholders() {
+        let (output, report) = process(&Default::default());
+        assert!(
+            output.contains("<w:lvlText w:val=\"\u{1000}%1\u{104B}\"/><w:rPr><w:rFonts w:ascii=\"Myanmar Text\" w:hAnsi=\"Myanmar Text\"/>"),
+            "{}",
+            output
+        );
+        assert!(output.contains(r#"<w:numFmt w:val="decimal"/><w:lvlText w:val="(%2)"/>"#), "{}", output);
+        assert_eq!(report.usage().len(), 1);
+    }
+
+    #[test]
+    fn myanmar_numbering_adds_a_custom_format_with_fallback() {
+        let options: ConversionOptions = serde_json::from_str(r#"{"myanmarNumbering":true}"#).unwrap();
+        let (output, _) = process(&options);
+        assert!(output.starts_with(&format!(r#"<w:numbering xmlns:mc="{MC_NAMESPACE}" xmlns:w14="{W14_NAMESPACE}">"#)), "{}", output);
+        assert!(
+            output.contains("<mc:AlternateContent><mc:Choice Requires=\"w14\"><w:numFmt w:val=\"custom\" w:format=\"\u{1041}, \u{1042}, \u{1043}, ...\"/></mc:Choice><mc:Fallback><w:numFmt w:val=\"decimal\"/></mc:Fallback></mc:AlternateContent>"),
+            "{}",
+            output
+        );
+        // Levels in other fonts keep their digits.
+        assert_eq!(output.matches("mc:AlternateContent>").count(), 2, "{}", output);
+    }
+}
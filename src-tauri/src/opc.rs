@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+use std::io::{Read, Seek};
+
+use zip::ZipArchive;
+
+/// One relationship of an Open Packaging Conventions part, with the target resolved
+/// to a part name inside the package.
+pub struct Relationship {
+    pub id: String,
+    pub rel_type: String,
+    pub target: String,
+}
+
+impl Relationship {
+    /// Last segment of the relationship type, e.g. "header" or "officeDocument".
+    /// Transitional and Strict OOXML use different type prefixes.
+    pub fn kind(&self) -> &str {
+        self.rel_type.rsplit('/').next().unwrap_or("")
+    }
+}
+
+/// "word/document.xml" -> "word/_rels/document.xml.rels"; "" is the package itself.
+pub fn rels_part_name(part: &str) -> String {
+    match part.rfind('/') {
+        Some(idx) => format!("{}/_rels/{}.rels", &part[..idx], &part[idx + 1..]),
+        None => format!("_rels/{}.rels", part),
+    }
+}
+
+/// Resolves a relationship target relative to the folder of `source_part`.
+pub fn resolve_target(source_part: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+
+    let mut segments: Vec<&str> = match source_part.rfind('/') {
+        Some(idx) => source_part[..idx].split('/').collect(),
+        None => Vec::new(),
+    };
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Reads a part of the package; `None` when it is missing or unreadable.
+pub fn read_part<R: Read + Seek>(archive: &mut ZipArchive<R>, part: &str) -> Option<Vec<u8>> {
+    let mut file = archive.by_name(part).ok()?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents).ok()?;
+    Some(contents)
+}
+
+/// Reads the internal relationships of `part` (use "" for the package relationships).
+/// A missing or unreadable .rels part yields no relationships.
+pub fn read_relationships<R: Read + Seek>(archive: &mut ZipArchive<R>, part: &str) -> Vec<Relationship> {
+    let rels_name = rels_part_name(part);
+    let mut contents = Vec::new();
+    match archive.by_name(&rels_name) {
+        Ok(mut file) => {
+            if file.read_to_end(&mut contents).is_err() {
+                return Vec::new();
+            }
+        }
+        Err(_) => return Vec::new(),
+    }
+    parse_relationships(&contents, part)
+}
+
+fn parse_relationships(contents: &[u8], part: &str) -> Vec<Relationship> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut relationships = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                if e.local_name().as_ref() != b"Relationship" {
+                    buf.clear();
+                    continue;
+                }
+                let mut id = String::new();
+                let mut rel_type = String::new();
+                let mut target = String::new();
+                let mut external = false;
+                for attr in e.attributes().flatten() {
+                    let value = attr.unescape_value().unwrap_or_default().to_string();
+                    match attr.key.as_ref() {
+                        b"Id" => id = value,
+                        b"Type" => rel_type = value,
+                        b"Target" => target = value,
+                        b"TargetMode" => external = value == "External",
+                        _ => {}
+                    }
+                }
+                if !external && !target.is_empty() {
+                    relationships.push(Relationship {
+                        id,
+                        rel_type,
+                        target: resolve_target(part, &target),
+                    });
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    relationships
+}
+
+/// Part name of the main document (word/document.xml, ppt/presentation.xml, ...).
+pub fn main_document_part<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<String> {
+    read_relationships(archive, "")
+        .into_iter()
+        .find(|rel| rel.kind() == "officeDocument")
+        .map(|rel| rel.target)
+}
+
+/// Copies a .rels part without the relationships whose Id is in `ids`.
+pub fn remove_relationships(contents: &[u8], ids: &HashSet<String>) -> Vec<u8> {
+    remove_elements(contents, |elem| is_removed_relationship(elem, ids))
+}
+
+/// Copies `[Content_Types].xml` without the overrides for the removed `parts`.
+pub fn remove_content_type_overrides(contents: &[u8], parts: &HashSet<String>) -> Vec<u8> {
+    remove_elements(contents, |elem| {
+        elem.local_name().as_ref() == b"Override"
+            && elem
+                .try_get_attribute("PartName")
+                .ok()
+                .flatten()
+                .is_some_and(|attr| parts.contains(attr.unescape_value().unwrap_or_default().trim_start_matches('/')))
+    })
+}
+
+/// Copies an XML part without the elements `is_removed` picks, along with their
+/// content.
+pub fn remove_elements(contents: &[u8], is_removed: impl Fn(&quick_xml::events::BytesStart) -> bool) -> Vec<u8> {
+    use quick_xml::events::Event;
+    use quick_xml::{Reader, Writer};
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+    let mut buf = Vec::new();
+    let mut skip_depth = 0usize;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                if skip_depth > 0 || is_removed(&e) {
+                    skip_depth += 1;
+                } else {
+                    writer.write_event(Event::Start(e.into_owned())).ok();
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                if skip_depth == 0 && !is_removed(&e) {
+                    writer.write_event(Event::Empty(e.into_owned())).ok();
+                }
+            }
+            Ok(Event::End(e)) => {
+                if skip_depth > 0 {
+                    skip_depth -= 1;
+                } else {
+                    writer.write_event(Event::End(e.into_owned())).ok();
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                if skip_depth == 0 {
+                    writer.write_event(e.into_owned()).ok();
+                }
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
+
+fn is_removed_relationship(elem: &quick_xml::events::BytesStart, ids: &HashSet<String>) -> bool {
+    elem.local_name().as_ref() == b"Relationship"
+        && elem
+            .try_get_attribute("Id")
+            .ok()
+            .flatten()
+            .is_some_and(|attr| ids.contains(attr.unescape_value().unwrap_or_default().as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_of_removed_parts_are_dropped() {
+        let types = r#"<Types><Default Extension="xml" ContentType="application/xml"/><Override PartName="/word/fonts/font1.odttf" ContentType="application/vnd.openxmlformats-officedocument.obfuscatedFont"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/></Types>"#;
+        let parts = HashSet::from(["word/fonts/font1.odttf".to_string()]);
+        let output = String::from_utf8(remove_content_type_overrides(types.as_bytes(), &parts)).unwrap();
+        assert!(!output.contains("font1.odttf"), "{}", output);
+        assert!(output.contains(r#"<Override PartName="/word/document.xml""#), "{}", output);
+    }
+}
@@ -0,0 +1,122 @@
+use serde::Deserialize;
+
+use crate::font_rules::{FontRule, FontSlot};
+
+fn default_factor() -> f64 {
+    1.0
+}
+
+/// Size compensation applied to converted runs when one font pair is swapped.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScalingRule {
+    pub source_font: String,
+    /// Target font of the pair; any target when not set.
+    #[serde(default)]
+    pub target_font: Option<String>,
+    #[serde(default = "default_factor")]
+    pub size_factor: f64,
+    /// Also scale the line spacing of paragraphs holding converted runs.
+    #[serde(default)]
+    pub line_spacing_factor: Option<f64>,
+}
+
+/// Reviewer shown on the tracked changes written for conversions.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevisionAuthor {
+    pub author: String,
+    /// ISO 8601 date of the revisions; left out when not set.
+    #[serde(default)]
+    pub date: Option<String>,
+}
+
+/// How numeric XLSX cells styled in a source font keep the Myanmar digits the
+/// source font drew for ASCII digits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DigitDisplay {
+    /// Give the cell styles a number format with a Myanmar digit locale.
+    NumberFormat,
+    /// Replace the values with Myanmar digit text. Formula cells are left alone,
+    /// and formulas that use the values will no longer see numbers. Cells in a
+    /// date or other non-General format get a number format as with `NumberFormat`.
+    Text,
+}
+
+/// A worksheet, and optionally ranges in it, picked for XLSX conversion.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetSelection {
+    pub sheet: String,
+    /// A1 ranges such as `A1:C20`, `D:D` or `3:3`; the whole sheet when empty.
+    #[serde(default)]
+    pub ranges: Vec<String>,
+}
+
+/// Per-job switches that are not tied to a single source font.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConversionOptions {
+    pub scaling: Vec<ScalingRule>,
+    /// Drop font files embedded for source fonts in DOCX output.
+    pub remove_embedded_fonts: bool,
+    /// Give converted DOCX runs the target font in every slot, a complex-script hint
+    /// and the Myanmar language tag.
+    pub set_run_language: bool,
+    /// Write each converted DOCX run as a tracked deletion of the original run and an
+    /// insertion of the converted one.
+    pub tracked_revisions: Option<RevisionAuthor>,
+    /// Highlight DOCX and PPTX runs whose conversion involved a guess or left
+    /// characters unmapped.
+    pub flag_uncertain: bool,
+    /// Number converted DOCX list levels with Myanmar digits instead of decimal ones.
+    pub myanmar_numbering: bool,
+    /// Keep Myanmar digits in XLSX numeric cells that were set in a source font.
+    pub myanmar_digits: Option<DigitDisplay>,
+    /// Convert only these XLSX sheets and ranges; every cell when empty.
+    pub xlsx_selection: Vec<SheetSelection>,
+}
+
+fn same_font(a: &str, b: &str) -> bool {
+    a.trim().eq_ignore_ascii_case(b.trim())
+}
+
+impl ConversionOptions {
+    /// Scaling for text converted by `rule`, if its font pair has one.
+    pub fn scaling_for(&self, rule: &FontRule) -> Option<&ScalingRule> {
+        let target = rule.target(FontSlot::Ascii);
+        self.scaling.iter().find(|scaling| {
+            same_font(&scaling.source_font, &rule.source_font)
+                && scaling
+                    .target_font
+                    .as_deref()
+                    .is_none_or(|font| same_font(font, target))
+        })
+    }
+}
+
+impl ScalingRule {
+    /// Scales an integer size such as DOCX half-points or DrawingML hundredths of a point.
+    pub fn scale_size(&self, value: &str) -> Option<String> {
+        let size = value.trim().parse::<f64>().ok()?;
+        Some(((size * self.size_factor).round().max(1.0) as i64).to_string())
+    }
+
+    /// Scales a fractional point size such as SpreadsheetML `sz`.
+    pub fn scale_points(&self, value: &str) -> Option<String> {
+        let size = value.trim().parse::<f64>().ok()?;
+        let scaled = ((size * self.size_factor) * 2.0).round().max(1.0) / 2.0;
+        Some(if scaled.fract() == 0.0 {
+            format!("{}", scaled as i64)
+        } else {
+            format!("{}", scaled)
+        })
+    }
+
+    /// Scales a line-spacing value; `None` when the rule leaves spacing alone.
+    pub fn scale_line(&self, value: f64) -> Option<i64> {
+        self.line_spacing_factor
+            .map(|factor| (value * factor).round().max(1.0) as i64)
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use quick_xml::events::BytesStart;
+use zip::ZipArchive;
+
+use crate::docx_styles::ThemeFonts;
+use crate::font_rules::{FontRule, FontRules};
+use crate::{opc, tag_matches};
+
+/// Guards the layout and master walk against relationship cycles in damaged files.
+const MAX_STYLE_DEPTH: usize = 8;
+
+/// Folders of the parts that hold slide-like text, with the relationship type of the
+/// part each inherits placeholder formatting from. Layouts and masters are read up
+/// front, since the parts below them inherit from their placeholders and text styles.
+const SLIDE_FOLDERS: &[(&str, Option<&str>, bool)] = &[
+    ("ppt/slides/", Some("slideLayout"), false),
+    ("ppt/slideLayouts/", Some("slideMaster"), true),
+    ("ppt/slideMasters/", None, true),
+    ("ppt/notesSlides/", Some("notesMaster"), false),
+    ("ppt/notesMasters/", None, true),
+    ("ppt/handoutMasters/", None, true),
+];
+
+fn slide_folder(part: &str) -> Option<&'static (&'static str, Option<&'static str>, bool)> {
+    SLIDE_FOLDERS.iter().find(|(folder, _, _)| {
+        part.strip_prefix(folder)
+            .is_some_and(|file| !file.contains('/') && file.ends_with(".xml"))
+    })
+}
+
+/// Comments with rich text, which are converted like slide text.
+fn is_modern_comments(part: &str) -> bool {
+    part.starts_with("ppt/comments/modernComment") && part.ends_with(".xml")
+}
+
+/// Comments whose text is plain `p:text`, with no font of its own.
+pub fn is_legacy_comments(part: &str) -> bool {
+    part.starts_with("ppt/comments/comment") && part.ends_with(".xml")
+}
+
+/// "+mn-lt" -> "minorAscii": the theme font a DrawingML typeface refers to.
+fn theme_font_name(typeface: &str) -> Option<String> {
+    let (collection, slot) = typeface.strip_prefix('+')?.split_once('-')?;
+    let collection = match collection {
+        "mj" => "major",
+        "mn" => "minor",
+        _ => return None,
+    };
+    let slot = match slot {
+        "lt" => "Ascii",
+        "ea" => "EastAsia",
+        "cs" => "Bidi",
+        _ => return None,
+    };
+    Some(format!("{}{}", collection, slot))
+}
+
+/// Latin font and size (`sz`, in hundredths of a point) that the `a:defRPr` of one
+/// list style level sets.
+#[derive(Default, Clone)]
+struct LevelStyle {
+    font: Option<String>,
+    size: Option<String>,
+}
+
+fn level_font(level: &LevelStyle) -> Option<&str> {
+    level.font.as_deref()
+}
+
+fn level_size(level: &LevelStyle) -> Option<&str> {
+    level.size.as_deref()
+}
+
+/// Run formatting of each level of a list style: index 0 is `a:defPPr`, 1 to 9 are
+/// `a:lvl1pPr` to `a:lvl9pPr`.
+#[derive(Default, Clone)]
+pub struct ListStyle {
+    levels: [LevelStyle; 10],
+}
+
+impl ListStyle {
+    /// `field` for paragraphs at outline level `level` (the 0-based `a:pPr lvl`).
+    fn value(&self, level: usize, field: fn(&LevelStyle) -> Option<&str>) -> Option<&str> {
+        self.levels
+            .get(level + 1)
+            .and_then(field)
+            .or_else(|| field(&self.levels[0]))
+    }
+}
+
+/// Slot of a list style child: `a:defPPr` or `a:lvlNpPr`.
+fn list_level(name: &[u8]) -> Option<usize> {
+    if name == b"a:defPPr" {
+        return Some(0);
+    }
+    let digit = name.strip_prefix(b"a:lvl")?.strip_suffix(b"pPr")?;
+    match digit {
+        [digit @ b'1'..=b'9'] => Some((digit - b'0') as usize),
+        _ => None,
+    }
+}
+
+/// Elements holding a list style: a shape's own, a master's text styles and the
+/// presentation defaults.
+fn is_list_style(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"a:lstStyle" | b"p:titleStyle" | b"p:bodyStyle" | b"p:otherStyle" | b"p:notesStyle" | b"p:defaultTextStyle"
+    )
+}
+
+/// `type` and `idx` of a `p:ph`. A placeholder without a type is an "obj".
+#[derive(Default, Clone)]
+pub struct Placeholder {
+    kind: Option<String>,
+    idx: Option<String>,
+}
+
+impl Placeholder {
+    fn from_elem(elem: &BytesStart) -> Self {
+        let mut placeholder = Placeholder::default();
+        for attr in elem.attributes().flatten() {
+            let value = attr.unescape_value().unwrap_or_default().to_string();
+            match attr.key.as_ref() {
+                b"type" => placeholder.kind = Some(value),
+                b"idx" => placeholder.idx = Some(value),
+                _ => {}
+            }
+        }
+        placeholder
+    }
+
+    /// Kind used to match placeholders across layouts and masters, where centred
+    /// titles follow the title and content placeholders follow the body.
+    fn class(&self) -> &str {
+        match self.kind.as_deref() {
+            Some("title" | "ctrTitle") => "title",
+            None | Some("body" | "subTitle" | "obj") => "body",
+            Some(kind) => kind,
+        }
+    }
+}
+
+/// Follows the placeholder, list style and paragraph level of the shape being read,
+/// which decide the font its runs inherit.
+#[derive(Default)]
+pub struct ShapeStyle {
+    placeholder: Option<Placeholder>,
+    list_style: ListStyle,
+    /// Outline level of the current paragraph.
+    level: usize,
+    in_list_style: bool,
+    list_level: Option<usize>,
+}
+
+impl ShapeStyle {
+    /// Takes note of a start element, before it is written.
+    pub fn open(&mut self, elem: &BytesStart) {
+        let name = elem.name();
+        let name = name.as_ref();
+        if name == b"p:sp" {
+            *self = ShapeStyle::default();
+        } else if name == b"p:ph" {
+            self.placeholder = Some(Placeholder::from_elem(elem));
+        } else if is_list_style(name) || tag_matches(name, b"txBody") {
+            self.list_style = ListStyle::default();
+            self.in_list_style = is_list_style(name);
+        } else if name == b"a:p" {
+            self.level = 0;
+        } else if name == b"a:pPr" {
+            self.level = elem
+                .try_get_attribute("lvl")
+                .ok()
+                .flatten()
+                .and_then(|attr| attr.unescape_value().ok()?.parse().ok())
+                .unwrap_or(0);
+        } else if self.in_list_style {
+            if let Some(level) = list_level(name) {
+                self.list_level = Some(level);
+            } else if let Some(level) = self.list_level {
+                let (key, value) = match name {
+                    b"a:latin" => (b"typeface".as_slice(), &mut self.list_style.levels[level].font),
+                    b"a:defRPr" => (b"sz".as_slice(), &mut self.list_style.levels[level].size),
+                    _ => return,
+                };
+                if let Some(attr) = elem.attributes().flatten().find(|attr| attr.key.as_ref() == key) {
+                    *value = Some(attr.unescape_value().unwrap_or_default().to_string());
+                }
+            }
+        }
+    }
+
+    /// Takes note of an end element.
+    pub fn close(&mut self, name: &[u8]) {
+        if name == b"p:sp" {
+            *self = ShapeStyle::default();
+        } else if is_list_style(name) {
+            self.in_list_style = false;
+        } else if list_level(name).is_some() {
+            self.list_level = None;
+        }
+    }
+
+    /// Takes note of an empty element, which opens and closes at once.
+    pub fn empty(&mut self, elem: &BytesStart) {
+        self.open(elem);
+        self.close(elem.name().as_ref());
+    }
+}
+
+/// Placeholders and text styles of a layout or master, and the parts it links to.
+#[derive(Default)]
+struct PartStyles {
+    placeholders: Vec<(Placeholder, ListStyle)>,
+    title_style: ListStyle,
+    /// `p:bodyStyle`, or the `p:notesStyle` of a notes master.
+    body_style: ListStyle,
+    /// `p:otherStyle`, or the `p:defaultTextStyle` of the presentation.
+    other_style: ListStyle,
+    parent: Option<String>,
+    theme: Option<String>,
+}
+
+impl PartStyles {
+    fn parse(contents: &[u8]) -> Self {
+        use quick_xml::events::Event;
+        use quick_xml::Reader;
+
+        let mut reader = Reader::from_reader(contents);
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+        let mut styles = PartStyles::default();
+        let mut shape = ShapeStyle::default();
+
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(e)) => shape.open(&e),
+                Ok(Event::Empty(e)) => shape.empty(&e),
+                Ok(Event::End(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    let list_style = || shape.list_style.clone();
+                    match name.as_slice() {
+                        b"p:sp" => {
+                            if let Some(placeholder) = shape.placeholder.clone() {
+                                styles.placeholders.push((placeholder, list_style()));
+                            }
+                        }
+                        b"p:titleStyle" => styles.title_style = list_style(),
+                        b"p:bodyStyle" | b"p:notesStyle" => styles.body_style = list_style(),
+                        b"p:otherStyle" | b"p:defaultTextStyle" => styles.other_style = list_style(),
+                        _ => {}
+                    }
+                    shape.close(&name);
+                }
+                Ok(Event::Eof) => break,
+                Err(_) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        styles
+    }
+
+    /// The placeholder `placeholder` inherits from here: the one with the same `idx`,
+    /// else the first of the same kind.
+    fn placeholder(&self, placeholder: &Placeholder) -> Option<&(Placeholder, ListStyle)> {
+        self.placeholders
+            .iter()
+            .find(|(candidate, _)| candidate.idx.is_some() && candidate.idx == placeholder.idx)
+            .or_else(|| {
+                self.placeholders
+                    .iter()
+                    .find(|(candidate, _)| candidate.class() == placeholder.class())
+            })
+    }
+}
+
+/// Text formatting a presentation's slides inherit, used to find the font of runs
+/// without an `a:latin` of their own.
+///
+/// Fonts are resolved the way PowerPoint does: the shape's list style, then the
+/// matching placeholder on the layout and on the master, then the master's title,
+/// body or other text style and finally the presentation's default text style.
+/// Theme fonts such as "+mn-lt" resolve through the master's theme.
+#[derive(Default)]
+pub struct PptxStyles {
+    parts: HashMap<String, PartStyles>,
+    themes: HashMap<String, ThemeFonts>,
+    presentation: PartStyles,
+    /// Theme parts, which hold font definitions themselves.
+    pub theme_parts: Vec<String>,
+    pub presentation_part: Option<String>,
+}
+
+impl PptxStyles {
+    /// Loads the layouts, masters and themes of the presentation `main_part`.
+    pub fn load<R: Read + Seek>(archive: &mut ZipArchive<R>, main_part: &str) -> Self {
+        let mut styles = PptxStyles::default();
+        let slide_parts: Vec<String> = archive
+            .file_names()
+            .filter(|name| slide_folder(name).is_some())
+            .map(str::to_string)
+            .collect();
+
+        for name in slide_parts {
+            let Some((_, parent_kind, inherited)) = slide_folder(&name) else {
+                continue;
+            };
+            let relationships = opc::read_relationships(archive, &name);
+            let mut part = match opc::read_part(archive, &name) {
+                Some(contents) if *inherited => PartStyles::parse(&contents),
+                _ => PartStyles::default(),
+            };
+            part.parent = relationships
+                .iter()
+                .find(|rel| Some(rel.kind()) == *parent_kind)
+                .map(|rel| rel.target.clone());
+            part.theme = relationships.iter().find(|rel| rel.kind() == "theme").map(|rel| rel.target.clone());
+            styles.parts.insert(name, part);
+        }
+
+        let relationships = opc::read_relationships(archive, main_part);
+        if let Some(contents) = opc::read_part(archive, main_part) {
+            styles.presentation = PartStyles::parse(&contents);
+            styles.presentation_part = Some(main_part.to_string());
+        }
+        styles.presentation.theme = relationships.iter().find(|rel| rel.kind() == "theme").map(|rel| rel.target.clone());
+
+        let theme_parts: Vec<String> = styles
+            .parts
+            .values()
+            .chain(std::iter::once(&styles.presentation))
+            .filter_map(|part| part.theme.clone())
+            .collect();
+        for theme_part in theme_parts {
+            if styles.themes.contains_key(&theme_part) {
+                continue;
+            }
+            let theme = opc::read_part(archive, &theme_part)
+                .map(|contents| ThemeFonts::parse(&contents))
+                .unwrap_or_default();
+            styles.themes.insert(theme_part.clone(), theme);
+            styles.theme_parts.push(theme_part);
+        }
+
+        log::debug!(
+            "PPTX styles: {} slide parts, themes {:?}",
+            styles.parts.len(),
+            styles.theme_parts
+        );
+        styles
+    }
+
+    /// Inheritance chain of a slide, layout, master or notes part, or of the
+    /// presentation's comments; `None` for parts without slide text.
+    pub fn slide_styles(&self, part: &str) -> Option<SlideStyles<'_>> {
+        if is_modern_comments(part) {
+            return Some(self.presentation_styles());
+        }
+        let mut part_styles = self.parts.get(part)?;
+        let mut chain = vec![part_styles];
+        for _ in 0..MAX_STYLE_DEPTH {
+            let Some(parent) = part_styles.parent.as_deref().and_then(|parent| self.parts.get(parent)) else {
+                break;
+            };
+            chain.push(parent);
+            part_styles = parent;
+        }
+        let theme = chain
+            .iter()
+            .chain(std::iter::once(&&self.presentation))
+            .find_map(|part| part.theme.as_deref())
+            .and_then(|theme| self.themes.get(theme));
+        Some(SlideStyles {
+            chain,
+            theme,
+            default_style: Some(&self.presentation.other_style),
+        })
+    }
+
+    /// Formatting of text outside slides, such as comments: the presentation's
+    /// default text style and theme.
+    pub fn presentation_styles(&self) -> SlideStyles<'_> {
+        SlideStyles {
+            chain: Vec::new(),
+            theme: self.presentation.theme.as_deref().and_then(|theme| self.themes.get(theme)),
+            default_style: Some(&self.presentation.other_style),
+        }
+    }
+}
+
+/// What the text of one part inherits; the default inherits nothing, for DrawingML
+/// outside presentations such as charts and SmartArt.
+#[derive(Default)]
+pub struct SlideStyles<'a> {
+    /// The part itself, its layout and its master, nearest first.
+    chain: Vec<&'a PartStyles>,
+    theme: Option<&'a ThemeFonts>,
+    default_style: Option<&'a ListStyle>,
+}
+
+impl SlideStyles<'_> {
+    /// Resolves a theme font reference such as "+mj-lt"; other typefaces are
+    /// returned as they are.
+    pub fn typeface<'t>(&'t self, typeface: &'t str) -> Option<&'t str> {
+        match theme_font_name(typeface) {
+            Some(theme_font) => self.theme?.font(&theme_font),
+            None => Some(typeface),
+        }
+    }
+
+    /// Font an `a:latin` names, with theme fonts resolved.
+    pub fn latin_typeface(&self, elem: &BytesStart) -> Option<String> {
+        let typeface = elem.try_get_attribute("typeface").ok()??.unescape_value().ok()?;
+        self.typeface(&typeface).map(str::to_string)
+    }
+
+    /// Rule for the typeface of an `a:latin`, which may name a theme font.
+    pub fn latin_rule<'r>(&self, rules: &'r FontRules, elem: &BytesStart) -> Option<&'r FontRule> {
+        rules.find(&self.latin_typeface(elem)?)
+    }
+
+    /// Formatting `field` of the shape's current paragraph level, through the shape,
+    /// layout and master placeholders and text styles.
+    fn inherited<'t>(&'t self, shape: &'t ShapeStyle, field: fn(&LevelStyle) -> Option<&str>) -> Option<&'t str> {
+        let level = shape.level;
+        if let Some(value) = shape.list_style.value(level, field) {
+            return Some(value);
+        }
+        let value = match shape.placeholder.as_ref() {
+            Some(placeholder) => {
+                let mut placeholder = placeholder;
+                let mut is_title = placeholder.class() == "title";
+                let mut value = None;
+                for part in &self.chain {
+                    let Some((inherited, list_style)) = part.placeholder(placeholder) else {
+                        continue;
+                    };
+                    value = list_style.value(level, field);
+                    if value.is_some() {
+                        break;
+                    }
+                    if placeholder.kind.is_none() {
+                        is_title = inherited.class() == "title";
+                    }
+                    placeholder = inherited;
+                }
+                value.or_else(|| {
+                    self.chain.iter().find_map(|part| {
+                        let text_style = if is_title { &part.title_style } else { &part.body_style };
+                        text_style.value(level, field)
+                    })
+                })
+            }
+            None => self.chain.iter().find_map(|part| part.other_style.value(level, field)),
+        };
+        value.or_else(|| self.default_style?.value(level, field))
+    }
+
+    /// Rule for a run without a Latin font of its own in the shape `shape`.
+    pub fn inherited_rule<'r>(&self, rules: &'r FontRules, shape: &ShapeStyle) -> Option<&'r FontRule> {
+        let typeface = self.inherited(shape, level_font)?;
+        rules.find(self.typeface(typeface)?)
+    }
+
+    /// Size, in hundredths of a point, of a run without an `sz` of its own.
+    pub fn inherited_size<'t>(&'t self, shape: &'t ShapeStyle) -> Option<&'t str> {
+        self.inherited(shape, level_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THEME: &str = r#"<a:theme><a:themeElements><a:fontScheme><a:majorFont><a:latin typeface="Win Innwa"/></a:majorFont><a:minorFont><a:latin typeface="Arial"/></a:minorFont></a:fontScheme></a:themeElements></a:theme>"#;
+
+    const MASTER: &str = r#"<p:sldMaster><p:cSld><p:spTree><p:sp><p:nvSpPr><p:nvPr><p:ph type="title"/></p:nvPr></p:nvSpPr><p:txBody><a:lstStyle/></p:txBody></p:sp></p:spTree></p:cSld><p:txStyles><p:titleStyle><a:lvl1pPr><a:defRPr sz="4400"><a:latin typeface="+mj-lt"/></a:defRPr></a:lvl1pPr></p:titleStyle><p:bodyStyle><a:lvl1pPr><a:defRPr sz="2800"><a:latin typeface="Arial"/></a:defRPr></a:lvl1pPr><a:lvl2pPr><a:defRPr><a:latin typeface="Win Innwa"/></a:defRPr></a:lvl2pPr></p:bodyStyle><p:otherStyle><a:defPPr><a:defRPr><a:latin typeface="+mn-lt"/></a:defRPr></a:defPPr></p:otherStyle></p:txStyles></p:sldMaster>"#;
+
+    const LAYOUT: &str = r#"<p:sldLayout><p:cSld><p:spTree><p:sp><p:nvSpPr><p:nvPr><p:ph type="body" idx="1"/></p:nvPr></p:nvSpPr><p:txBody><a:lstStyle><a:lvl1pPr><a:defRPr><a:latin typeface="Win Kalaw"/></a:defRPr></a:lvl1pPr></a:lstStyle></p:txBody></p:sp></p:spTree></p:cSld></p:sldLayout>"#;
+
+    fn styles() -> PptxStyles {
+        let mut layout = PartStyles::parse(LAYOUT.as_bytes());
+        layout.parent = Some("ppt/slideMasters/slideMaster1.xml".to_string());
+        let mut master = PartStyles::parse(MASTER.as_bytes());
+        master.theme = Some("ppt/theme/theme1.xml".to_string());
+        let slide = PartStyles {
+            parent: Some("ppt/slideLayouts/slideLayout1.xml".to_string()),
+            ..Default::default()
+        };
+        PptxStyles {
+            parts: HashMap::from([
+                ("ppt/slides/slide1.xml".to_string(), slide),
+                ("ppt/slideLayouts/slideLayout1.xml".to_string(), layout),
+                ("ppt/slideMasters/slideMaster1.xml".to_string(), master),
+            ]),
+            themes: HashMap::from([("ppt/theme/theme1.xml".to_string(), ThemeFonts::parse(THEME.as_bytes()))]),
+            ..Default::default()
+        }
+    }
+
+    /// Shape state after opening each of `elements`, given as tag contents.
+    fn shape(elements: &[&str]) -> ShapeStyle {
+        let mut shape = ShapeStyle::default();
+        for content in elements {
+            shape.open(&BytesStart::from_content(*content, content.find(' ').unwrap_or(content.len())));
+        }
+        shape
+    }
+
+    fn inherited_font(styles: &SlideStyles, shape: &ShapeStyle) -> Option<String> {
+        styles.typeface(styles.inherited(shape, level_font)?).map(str::to_string)
+    }
+
+    #[test]
+    fn placeholders_inherit_through_layout_and_master() {
+        let styles = styles();
+        let slide = styles.slide_styles("ppt/slides/slide1.xml").unwrap();
+
+        // The layout's placeholder sets level 1; level 2 falls through to the master's body style.
+        let body = shape(&["p:sp", r#"p:ph idx="1""#, "a:p"]);
+        assert_eq!(inherited_font(&slide, &body).as_deref(), Some("Win Kalaw"));
+        assert_eq!(slide.inherited_size(&body), Some("2800"));
+        let body = shape(&["p:sp", r#"p:ph idx="1""#, "a:p", r#"a:pPr lvl="1""#]);
+        assert_eq!(inherited_font(&slide, &body).as_deref(), Some("Win Innwa"));
+
+        // Centred titles follow the title style, whose theme font is resolved.
+        let title = shape(&["p:sp", r#"p:ph type="ctrTitle""#, "a:p"]);
+        assert_eq!(inherited_font(&slide, &title).as_deref(), Some("Win Innwa"));
+        assert_eq!(slide.inherited_size(&title), Some("4400"));
+
+        // Other shapes use the other style, unless their own list style sets a font.
+        assert_eq!(inherited_font(&slide, &shape(&["p:sp", "a:p"])).as_deref(), Some("Arial"));
+        let own = shape(&["p:sp", "p:txBody", "a:lstStyle", "a:lvl1pPr", "a:defRPr", r#"a:latin typeface="Win Kalaw""#, "a:p"]);
+        assert_eq!(inherited_font(&slide, &own).as_deref(), Some("Win Kalaw"));
+    }
+
+    #[test]
+    fn only_slide_like_parts_have_styles() {
+        let styles = styles();
+        assert!(styles.slide_styles("ppt/slideMasters/slideMaster1.xml").is_some());
+        assert!(styles.slide_styles("ppt/comments/modernComment_100_0.xml").is_some());
+        assert!(styles.slide_styles("ppt/charts/chart1.xml").is_none());
+        assert!(slide_folder("ppt/notesSlides/notesSlide1.xml").is_some() && slide_folder("ppt/slides/_rels/slide1.xml.rels").is_none());
+        assert_eq!(theme_font_name("+mn-cs").as_deref(), Some("minorBidi"));
+        assert_eq!(theme_font_name("Arial"), None);
+    }
+}
@@ -0,0 +1,225 @@
+use quick_xml::events::{BytesStart, BytesText};
+
+use crate::font_rules::{FontRule, Mapping};
+
+/// Appends the name and attributes of a run-property element to a run's format key,
+/// leaving out `ignored_attrs` (bookkeeping such as PowerPoint's spell-check `err`).
+pub fn record_format(format: &mut Vec<u8>, elem: &BytesStart, ignored_attrs: &[&[u8]]) {
+    format.push(b'<');
+    format.extend_from_slice(elem.name().as_ref());
+    for attr in elem.attributes().flatten() {
+        if ignored_attrs.contains(&attr.key.as_ref()) {
+            continue;
+        }
+        format.push(b' ');
+        format.extend_from_slice(attr.key.as_ref());
+        format.push(b'=');
+        format.extend_from_slice(&attr.value);
+    }
+    format.push(b'>');
+}
+
+pub fn record_format_end(format: &mut Vec<u8>, name: &[u8]) {
+    format.extend_from_slice(b"</");
+    format.extend_from_slice(name);
+    format.push(b'>');
+}
+
+/// Markup flagging a run whose conversion is uncertain, and where it goes in the output.
+pub type RunMark = (usize, &'static [u8]);
+
+/// Attribute keeping the edge spaces of a text element that gains them.
+const PRESERVE_SPACE: &[u8] = br#" xml:space="preserve""#;
+
+/// Text nodes of consecutive runs that share a source font and formatting.
+struct RunGroup<'r> {
+    rule: &'r FontRule,
+    format: Vec<u8>,
+    /// Output offset where each text node goes, with its original text and where
+    /// `xml:space="preserve"` goes if its element lacks it.
+    pieces: Vec<(usize, String, Option<usize>)>,
+    /// Flags for the runs of the group, used when the conversion is uncertain.
+    marks: Vec<RunMark>,
+}
+
+/// Collects run text while a part is rewritten, so that text Word split across runs
+/// (spell-check marks, revision IDs, bookmarks) is converted as one string.
+///
+/// Text nodes are left empty in the output and filled in by `finish` once every
+/// group is converted.
+#[derive(Default)]
+pub struct RunMerger<'r> {
+    group: Option<RunGroup<'r>>,
+    insertions: Vec<(usize, Vec<u8>)>,
+    /// Groups whose runs were flagged as uncertain.
+    pub flagged: usize,
+}
+
+impl<'r> RunMerger<'r> {
+    /// Adds the text of a run to the current group, starting a new group when the
+    /// rule or formatting differs. `mark` flags the run if the group's conversion
+    /// turns out to be uncertain. `space_at` is the end of a text element's start tag
+    /// without `xml:space="preserve"`, which it gets if its converted text starts or
+    /// ends with a space, such as one moved in from another run.
+    pub fn push(
+        &mut self,
+        rule: &'r FontRule,
+        format: &[u8],
+        offset: usize,
+        text: String,
+        mark: Option<RunMark>,
+        space_at: Option<usize>,
+    ) {
+        let same_group = self
+            .group
+            .as_ref()
+            .is_some_and(|group| std::ptr::eq(group.rule, rule) && group.format == format);
+        if !same_group {
+            self.flush();
+            self.group = Some(RunGroup {
+                rule,
+                format: format.to_vec(),
+                pieces: Vec::new(),
+                marks: Vec::new(),
+            });
+        }
+        if let Some(group) = self.group.as_mut() {
+            group.pieces.push((offset, text, space_at));
+            // A run with several text nodes is flagged once.
+            if let Some(mark) = mark.filter(|mark| !group.marks.iter().any(|(at, _)| *at == mark.0)) {
+                group.marks.push(mark);
+            }
+        }
+    }
+
+    /// Ends the current group; call on anything that separates runs.
+    pub fn flush(&mut self) {
+        let Some(group) = self.group.take() else {
+            return;
+        };
+        let texts: Vec<&str> = group.pieces.iter().map(|(_, text, _)| text.as_str()).collect();
+        let converted = convert_pieces(group.rule.mapping, &texts);
+        if group.pieces.len() > 1 {
+            log::trace!("Converted {} runs as one: {:?}", group.pieces.len(), texts);
+        }
+        if !group.marks.is_empty() {
+            if let Some(uncertainty) = group.rule.mapping.review(&texts.concat(), &converted.concat()) {
+                log::debug!("Flagging uncertain conversion ({:?}): {:?}", uncertainty, texts);
+                self.flagged += 1;
+                self.insertions.extend(group.marks.iter().map(|(offset, markup)| (*offset, markup.to_vec())));
+            }
+        }
+        for ((offset, _, space_at), text) in group.pieces.iter().zip(converted) {
+            if let Some(space_at) = space_at.filter(|_| text.starts_with(char::is_whitespace) || text.ends_with(char::is_whitespace)) {
+                self.insertions.push((space_at, PRESERVE_SPACE.to_vec()));
+            }
+            let escaped = BytesText::new(&text).into_inner().into_owned();
+            self.insertions.push((*offset, escaped));
+        }
+    }
+
+    /// Inserts markup at an output offset along with the converted text.
+    pub fn insert(&mut self, offset: usize, markup: Vec<u8>) {
+        self.insertions.push((offset, markup));
+    }
+
+    /// Writes the converted text into `output` at the recorded offsets.
+    pub fn finish(mut self, output: Vec<u8>) -> Vec<u8> {
+        self.flush();
+        if self.insertions.is_empty() {
+            return output;
+        }
+        // Stable, so insertions at one offset keep the order they were made in.
+        self.insertions.sort_by_key(|(offset, _)| *offset);
+        let extra: usize = self.insertions.iter().map(|(_, text)| text.len()).sum();
+        let mut merged = Vec::with_capacity(output.len() + extra);
+        let mut copied = 0;
+        for (offset, text) in &self.insertions {
+            merged.extend_from_slice(&output[copied..*offset]);
+            merged.extend_from_slice(text);
+            copied = *offset;
+        }
+        merged.extend_from_slice(&output[copied..]);
+        merged
+    }
+}
+
+/// Converts `pieces` as one string and splits the result back over them.
+///
+/// A piece keeps its own text only where the split is clean: the converted text so far
+/// is followed by the conversion of the next piece on its own. Otherwise its converted
+/// text moves to the piece before it, whose formatting is identical.
+fn convert_pieces(mapping: Mapping, pieces: &[&str]) -> Vec<String> {
+    if pieces.len() == 1 {
+        return vec![mapping.convert(pieces[0])];
+    }
+
+    let full = pieces.concat();
+    let converted = mapping.convert(&full);
+    let mut output = vec![String::new(); pieces.len()];
+    let mut remaining = converted.as_str();
+    let mut segment_start = 0;
+    let mut owner = 0;
+    let mut boundary = 0;
+    // Conversion of the piece after the last clean split, which is the next head.
+    let mut next_head: Option<String> = None;
+
+    for (idx, piece) in pieces.iter().enumerate() {
+        let end = boundary + piece.len();
+        if idx > 0 && !piece.is_empty() {
+            let head = next_head
+                .take()
+                .unwrap_or_else(|| mapping.convert(&full[segment_start..boundary]));
+            let next = mapping.convert(&full[boundary..end]);
+            let clean = remaining.strip_prefix(head.as_str()).is_some_and(|tail| {
+                if end == full.len() {
+                    tail == next
+                } else {
+                    tail.starts_with(next.as_str())
+                }
+            });
+            if clean {
+                remaining = &remaining[head.len()..];
+                output[owner] = head;
+                segment_start = boundary;
+                owner = idx;
+                next_head = Some(next);
+            }
+        }
+        boundary = end;
+    }
+    output[owner] = remaining.to_string();
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_split_keeps_each_piece() {
+        let pieces = convert_pieces(Mapping::WinInnwa, &["u", "c", "*"]);
+        assert_eq!(pieces, ["\u{1000}", "\u{1001}", "\u{1002}"]);
+    }
+
+    #[test]
+    fn pre_base_vowel_moves_to_earlier_piece() {
+        // "a" is typed before the consonant but stored after it.
+        let pieces = convert_pieces(Mapping::WinInnwa, &["u", "a", "c"]);
+        assert_eq!(pieces, ["\u{1000}\u{1001}\u{1031}", "", ""]);
+    }
+
+    #[test]
+    fn moved_edge_space_is_preserved() {
+        use crate::font_rules::FontRules;
+
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        let rule = rules.find("Win Innwa").unwrap();
+        let mut merger = RunMerger::default();
+        // "a" is stored after the consonant, so "ကေ " all goes to the first text node.
+        merger.push(rule, b"", 5, "a".to_string(), None, Some(4));
+        merger.push(rule, b"", 16, "u ".to_string(), None, Some(15));
+        let output = merger.finish(b"<w:t></w:t><w:t></w:t>".to_vec());
+        assert_eq!(String::from_utf8(output).unwrap(), "<w:t xml:space=\"preserve\">\u{1000}\u{1031} </w:t><w:t></w:t>");
+    }
+}
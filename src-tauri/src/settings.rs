@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::font_rules::TargetFont;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// User preferences kept between runs in the app config directory.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub target_font: TargetFont,
+}
+
+fn settings_path(handle: &AppHandle) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(handle.path().app_config_dir()?.join(SETTINGS_FILE))
+}
+
+/// Loads the saved settings, falling back to the defaults when none are saved
+/// or the file cannot be read.
+pub fn load(handle: &AppHandle) -> Settings {
+    let path = match settings_path(handle) {
+        Ok(path) => path,
+        Err(e) => {
+            log::warn!("Failed to resolve settings path: {}", e);
+            return Settings::default();
+        }
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::warn!("Ignoring invalid settings file {:?}: {}", path, e);
+            Settings::default()
+        }),
+        Err(_) => Settings::default(),
+    }
+}
+
+pub fn save(handle: &AppHandle, settings: &Settings) -> Result<(), Box<dyn std::error::Error>> {
+    let path = settings_path(handle)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    log::debug!("Saving settings to: {:?}", path);
+    std::fs::write(path, serde_json::to_string_pretty(settings)?)?;
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::BufRead;
+
+use quick_xml::events::Event;
+
+use crate::font_rules::FontRule;
+
+/// Source fonts of the cells that reference each shared string, in the order the
+/// worksheets use them. `None` is a cell whose style is not a source font.
+#[derive(Default)]
+pub struct SharedStringUses<'r> {
+    uses: BTreeMap<usize, Vec<Option<&'r FontRule>>>,
+    picked: HashSet<usize>,
+    unpicked: HashSet<usize>,
+}
+
+impl<'r> SharedStringUses<'r> {
+    pub fn record(&mut self, index: usize, rule: Option<&'r FontRule>) {
+        self.add_use(index, rule);
+        self.picked.insert(index);
+    }
+
+    /// Records a cell outside the selected cells, which keeps the string as it is.
+    pub fn record_unpicked(&mut self, index: usize) {
+        self.add_use(index, None);
+        self.unpicked.insert(index);
+    }
+
+    fn add_use(&mut self, index: usize, rule: Option<&'r FontRule>) {
+        let uses = self.uses.entry(index).or_default();
+        if !uses.iter().any(|seen| source_font(*seen) == source_font(rule)) {
+            uses.push(rule);
+        }
+    }
+
+    /// Decides how each string is converted. A string whose cells disagree keeps
+    /// its first use (unconverted when any cell is not in a source font), and every
+    /// other source font gets its own copy appended after the `unique_count`
+    /// strings already in the table.
+    pub fn plan(self, unique_count: usize) -> SharedStringPlan<'r> {
+        let mut plan = SharedStringPlan {
+            unique_count,
+            kept: self.unpicked.difference(&self.picked).copied().collect(),
+            ..Default::default()
+        };
+        for (index, mut uses) in self.uses {
+            if let Some(pos) = uses.iter().position(Option::is_none) {
+                uses.swap(0, pos);
+            }
+            let mut uses = uses.into_iter();
+            if let Some(Some(rule)) = uses.next() {
+                plan.convert.insert(index, rule);
+            }
+            for rule in uses.flatten() {
+                let new_index = unique_count + plan.splits.len();
+                plan.remap.insert((index, rule.source_font.as_str()), new_index);
+                plan.split.insert(index);
+                plan.splits.push((index, rule));
+            }
+        }
+        plan
+    }
+}
+
+fn source_font(rule: Option<&FontRule>) -> Option<&str> {
+    rule.map(|rule| rule.source_font.as_str())
+}
+
+/// How `xl/sharedStrings.xml` and the `t="s"` cells referencing it are rewritten.
+#[derive(Default)]
+pub struct SharedStringPlan<'r> {
+    /// Strings converted in place.
+    pub convert: HashMap<usize, &'r FontRule>,
+    /// Converted copies appended to the table, as (original index, rule), in the
+    /// order of their new indices.
+    pub splits: Vec<(usize, &'r FontRule)>,
+    /// Strings in the table before the copies.
+    pub unique_count: usize,
+    /// Strings only cells outside the selected cells use; their rich text runs
+    /// keep their fonts.
+    pub kept: HashSet<usize>,
+    /// Strings that have copies.
+    split: HashSet<usize>,
+    remap: HashMap<(usize, &'r str), usize>,
+}
+
+impl SharedStringPlan<'_> {
+    /// New index for a cell in `rule`'s font that references the split string `index`.
+    pub fn split_index(&self, index: usize, rule: &FontRule) -> Option<usize> {
+        self.remap.get(&(index, rule.source_font.as_str())).copied()
+    }
+
+    pub fn is_split(&self, index: usize) -> bool {
+        self.split.contains(&index)
+    }
+}
+
+/// Number of `si` entries in a shared strings part. The `uniqueCount` attribute is
+/// optional and not always accurate, so the entries are counted.
+pub fn count_shared_strings<R: BufRead>(source: R) -> usize {
+    use quick_xml::Reader;
+
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut count = 0;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"si" => count += 1,
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font_rules::FontRules;
+
+    #[test]
+    fn strings_used_in_several_fonts_are_copied() {
+        let rules = FontRules::new(
+            serde_json::from_str(r#"[{"sourceFont":"Win Innwa"},{"sourceFont":"Win Kalaw"}]"#).unwrap(),
+            &Default::default(),
+        )
+        .unwrap();
+        let innwa = rules.find("Win Innwa");
+        let kalaw = rules.find("Win Kalaw");
+        let mut uses = SharedStringUses::default();
+        uses.record(0, innwa);
+        uses.record(0, innwa);
+        uses.record(1, innwa);
+        uses.record(1, None);
+        uses.record(1, kalaw);
+        uses.record(2, kalaw);
+        uses.record_unpicked(3);
+
+        let plan = uses.plan(4);
+        assert!(std::ptr::eq(plan.convert[&0], innwa.unwrap()) && std::ptr::eq(plan.convert[&2], kalaw.unwrap()));
+        // A cell in another font keeps string 1 unconverted; each source font gets a copy.
+        assert!(!plan.convert.contains_key(&1));
+        assert!(plan.is_split(1) && !plan.is_split(0));
+        assert_eq!(plan.split_index(1, innwa.unwrap()), Some(4));
+        assert_eq!(plan.split_index(1, kalaw.unwrap()), Some(5));
+        assert_eq!(plan.kept, HashSet::from([3]));
+    }
+
+    #[test]
+    fn entries_are_counted() {
+        let table = r#"<sst uniqueCount="1"><si><t>a</t></si><si/><si><r><t>b</t></r></si></sst>"#;
+        assert_eq!(count_shared_strings(table.as_bytes()), 3);
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::OnceLock;
+
 use regex::Regex;
 
 const FONT_MAPPING_ENTRIES: &[(&str, &str)] = &[
@@ -193,6 +195,69 @@ fn childdeldul(match_str: &str) -> &str {
     }
 }
 
+/// Regular expressions used by the conversion, compiled on first use.
+/// Digits that `correction1` reads as the letter they look like when a sign follows
+/// them: zero as wa, seven as ra, eight as ga.
+const DIGIT_LETTERS: [(char, char); 3] = [('\u{1040}', '\u{101D}'), ('\u{1047}', '\u{101B}'), ('\u{1048}', '\u{1002}')];
+
+struct Patterns {
+    duplicate_marks: Regex,
+    medials_ra: Regex,
+    medials_ya: Regex,
+    /// Zero, seven and eight next to a vowel, medial or final, with the letter each stands for.
+    digit_letters: Vec<(Regex, String)>,
+    upper_medial: Regex,
+    tone_lower: Regex,
+    kinzi: [Regex; 4],
+    medial_ra: Regex,
+    storage_order: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        let compile = |pattern: &str| Regex::new(pattern).unwrap();
+        // Medial orders that all normalize to ra (or ya) + wa + ha.
+        let medials = |first: &str| {
+            let (w, h) = ("\u{103D}", "\u{103E}");
+            compile(&format!(
+                "{h}{w}{first}|{h}{first}{w}|{w}{h}{first}|{w}{first}{h}|{first}{h}{w}"
+            ))
+        };
+        let followers = [
+            ("vowel", "[\u{102B}-\u{1036}]"),
+            ("medial", "[\u{103B}-\u{103E}]"),
+            ("finale", "([\u{1000}-\u{1031}][\u{1039}-\u{103A}])"),
+        ];
+        let digit_letters = followers
+            .iter()
+            .flat_map(|(name, follower)| {
+                DIGIT_LETTERS.map(|(digit, letter)| {
+                    (compile(&format!("{}(?P<{}>{})", digit, name, follower)), format!("{}${}", letter, name))
+                })
+            })
+            .collect();
+        let con_pattern = "(?:u|c|\\*|C|i|p|q|Z|n|\u{00F1}|\u{00CD}|\u{00DA}|#|X|!|\u{00A1}|P|w|x|'|\"|e|E|\u{00BD}|y|z|A|b|r|,|&|v|0|o|\\[|V|t|\\||\u{00F3})";
+        let kinzi = |sign: &str| compile(&format!("(?P<E>a)?(?P<R>j)?(?P<con>{}){}", con_pattern, sign));
+        Patterns {
+            duplicate_marks: compile("\u{102D}+|\u{102E}+|\u{103D}+|\u{103E}+|\u{1032}+|\u{1037}+|\u{1036}+|\u{103A}+"),
+            medials_ra: medials("\u{103C}"),
+            medials_ya: medials("\u{103B}"),
+            digit_letters,
+            upper_medial: compile("(?P<upper>[\u{102D}\u{102E}\u{1036}\u{1032}])(?P<M>[\u{103B}-\u{103E}]+)"),
+            tone_lower: compile("(?P<DVs>[\u{1036}\u{1037}\u{1038}]+)(?P<lower>[\u{102F}\u{1030}])"),
+            kinzi: [
+                kinzi("\u{1004}\u{103A}\u{1039}"),
+                kinzi("\u{00D8}"),
+                kinzi("\u{00D0}"),
+                kinzi("\u{00F8}"),
+            ],
+            medial_ra: compile("(?P<R>\u{103C})(?P<Wa>\u{103D})?(?P<Ha>\u{103E})?(?P<U>\u{102F})?(?P<con>[\u{1000}-\u{1021}])(?P<scon>\u{1039}[\u{1000}-\u{1021}])?"),
+            storage_order: compile("(?P<E>\u{1031})?(?P<con>[\u{1000}-\u{1021}])(?P<scon>\u{1039}[\u{1000}-\u{1021}])?(?P<upper>[\u{102D}\u{102E}\u{1032}\u{1036}])?(?P<DVs>[\u{1037}\u{1038}]){0,2}(?P<M>[\u{103B}-\u{103E}]*)(?P<lower>[\u{102F}\u{1030}])?(?P<upper2>[\u{102D}\u{102E}\u{1032}])?"),
+        }
+    })
+}
+
 fn correction1(input: &str) -> String {
     let c = [
         "\u{1000}", "\u{1001}", "\u{1002}", "\u{1003}", "\u{1004}", "\u{1005}", "\u{1006}",
@@ -213,8 +278,9 @@ fn correction1(input: &str) -> String {
     let mut unistr = input.to_string();
 
     // Remove duplicate diacritical marks
-    let dup_re = Regex::new("\u{102D}+|\u{102E}+|\u{103D}+|\u{103E}+|\u{1032}+|\u{1037}+|\u{1036}+|\u{103A}+").unwrap();
-    unistr = dup_re
+    let patterns = patterns();
+    unistr = patterns
+        .duplicate_marks
         .replace_all(&unistr, |caps: &regex::Captures| {
             childdeldul(caps.get(0).unwrap().as_str()).to_string()
         })
@@ -239,28 +305,14 @@ fn correction1(input: &str) -> String {
     unistr = unistr.replace(&format!("{}{}", m[3], m[2]), &format!("{}{}", m[2], m[3]));
     unistr = unistr.replace(&format!("{}{}", m[2], m[0]), &format!("{}{}", m[0], m[2]));
     unistr = unistr.replace(&format!("{}{}", m[2], m[1]), &format!("{}{}", m[1], m[2]));
-    unistr = Regex::new(&format!(
-        "{}{}{}|{}{}{}|{}{}{}|{}{}{}|{}{}{}",
-        m[3], m[2], m[1],
-        m[3], m[1], m[2],
-        m[2], m[3], m[1],
-        m[2], m[1], m[3],
-        m[1], m[3], m[2],
-    ))
-    .unwrap()
-    .replace_all(&unistr, &format!("{}{}{}", m[1], m[2], m[3]))
-    .to_string();
-    unistr = Regex::new(&format!(
-        "{}{}{}|{}{}{}|{}{}{}|{}{}{}|{}{}{}",
-        m[3], m[2], m[0],
-        m[3], m[0], m[2],
-        m[2], m[3], m[0],
-        m[2], m[0], m[3],
-        m[0], m[3], m[2],
-    ))
-    .unwrap()
-    .replace_all(&unistr, &format!("{}{}{}", m[0], m[2], m[3]))
-    .to_string();
+    unistr = patterns
+        .medials_ra
+        .replace_all(&unistr, &format!("{}{}{}", m[1], m[2], m[3]))
+        .to_string();
+    unistr = patterns
+        .medials_ya
+        .replace_all(&unistr, &format!("{}{}{}", m[0], m[2], m[3]))
+        .to_string();
 
     // Vowel reordering
     unistr = unistr.replace(&format!("{}{}", v[8], v[4]), &format!("{}{}", v[4], v[8]));
@@ -281,67 +333,20 @@ fn correction1(input: &str) -> String {
     unistr = unistr.replace(&format!("{}{}", t[2], t[2]), t[2]);
 
     // Recognition of digit as consonant
-    unistr = unistr.replace(&format!("{}{}", d[0], t[2]), &format!("{}{}", c[29], t[2]));
-    unistr = unistr.replace(&format!("{}{}", d[7], t[2]), &format!("{}{}", c[27], t[2]));
-    unistr = unistr.replace(&format!("{}{}", d[8], t[2]), &format!("{}{}", c[2], t[2]));
-
-    unistr = unistr.replace(&format!("{}{}", d[0], t[3]), &format!("{}{}", c[29], t[3]));
-    unistr = unistr.replace(&format!("{}{}", d[7], t[3]), &format!("{}{}", c[27], t[3]));
-    unistr = unistr.replace(&format!("{}{}", d[8], t[3]), &format!("{}{}", c[2], t[3]));
-
-    // Digit + vowel combinations
-    let vowel_range = format!("{}-{}", v[0], v[8]);
-    unistr = Regex::new(&format!("{}(?P<vowel>[{}])", d[0], vowel_range))
-        .unwrap()
-        .replace_all(&unistr, &format!("{}$vowel", c[29]))
-        .to_string();
-    unistr = Regex::new(&format!("{}(?P<vowel>[{}])", d[7], vowel_range))
-        .unwrap()
-        .replace_all(&unistr, &format!("{}$vowel", c[27]))
-        .to_string();
-    unistr = Regex::new(&format!("{}(?P<vowel>[{}])", d[8], vowel_range))
-        .unwrap()
-        .replace_all(&unistr, &format!("{}$vowel", c[2]))
-        .to_string();
-
-    // Digit + medial combinations
-    let medial_range = format!("{}-{}", m[0], m[3]);
-    unistr = Regex::new(&format!("{}(?P<medial>[{}])", d[0], medial_range))
-        .unwrap()
-        .replace_all(&unistr, &format!("{}$medial", c[29]))
-        .to_string();
-    unistr = Regex::new(&format!("{}(?P<medial>[{}])", d[7], medial_range))
-        .unwrap()
-        .replace_all(&unistr, &format!("{}$medial", c[27]))
-        .to_string();
-    unistr = Regex::new(&format!("{}(?P<medial>[{}])", d[8], medial_range))
-        .unwrap()
-        .replace_all(&unistr, &format!("{}$medial", c[2]))
-        .to_string();
+    for sign in [t[2], t[3]] {
+        for (digit, letter) in DIGIT_LETTERS {
+            unistr = unistr.replace(&format!("{}{}", digit, sign), &format!("{}{}", letter, sign));
+        }
+    }
 
-    // Digit + final combinations
-    unistr = Regex::new(&format!("{}(?P<finale>([{}-{}][{}-{}]))", d[0], "\u{1000}", "\u{1031}", "\u{1039}", "\u{103A}"))
-        .unwrap()
-        .replace_all(&unistr, &format!("{}$finale", c[29]))
-        .to_string();
-    unistr = Regex::new(&format!("{}(?P<finale>([{}-{}][{}-{}]))", d[7], "\u{1000}", "\u{1031}", "\u{1039}", "\u{103A}"))
-        .unwrap()
-        .replace_all(&unistr, &format!("{}$finale", c[27]))
-        .to_string();
-    unistr = Regex::new(&format!("{}(?P<finale>([{}-{}][{}-{}]))", d[8], "\u{1000}", "\u{1031}", "\u{1039}", "\u{103A}"))
-        .unwrap()
-        .replace_all(&unistr, &format!("{}$finale", c[2]))
-        .to_string();
+    // Digit + vowel, medial and final combinations
+    for (re, letter) in &patterns.digit_letters {
+        unistr = re.replace_all(&unistr, letter.as_str()).to_string();
+    }
 
     // Final reordering
-    unistr = Regex::new("(?P<upper>[\u{102D}\u{102E}\u{1036}\u{1032}])(?P<M>[\u{103B}-\u{103E}]+)")
-        .unwrap()
-        .replace_all(&unistr, "$M$upper")
-        .to_string();
-    unistr = Regex::new("(?P<DVs>[\u{1036}\u{1037}\u{1038}]+)(?P<lower>[\u{102F}\u{1030}])")
-        .unwrap()
-        .replace_all(&unistr, "$lower$DVs")
-        .to_string();
+    unistr = patterns.upper_medial.replace_all(&unistr, "$M$upper").to_string();
+    unistr = patterns.tone_lower.replace_all(&unistr, "$lower$DVs").to_string();
 
     // Original JS: unistr = unistr.replace("့်", "့်");
     unistr = unistr.replace("့်", "့်");
@@ -415,30 +420,40 @@ fn cleanup_after_convert(input: &str) -> String {
     result
 }
 
+/// Word stores text in a symbol font as private-use code points U+F020-U+F0FF;
+/// fold them back onto the Latin-1 code points the mapping expects.
+fn fold_symbol_code_points(input: &str) -> String {
+    input
+        .chars()
+        .map(|ch| match ch as u32 {
+            code @ 0xF020..=0xF0FF => char::from_u32(code - 0xF000).unwrap_or(ch),
+            _ => ch,
+        })
+        .collect()
+}
+
 pub fn win_to_myanmar3(input: &str) -> String {
+    let folded = fold_symbol_code_points(input);
+
     // Cleanup before font mapping
-    let cleaned = cleanup_before_convert(input);
+    let cleaned = cleanup_before_convert(&folded);
 
     // Apply font mapping
     let mut unistr = apply_font_mapping(&cleaned);
 
     // Reordering kinzi
-    let con_pattern = "(?:u|c|\\*|C|i|p|q|Z|n|\u{00F1}|\u{00CD}|\u{00DA}|#|X|!|\u{00A1}|P|w|x|'|\"|e|E|\u{00BD}|y|z|A|b|r|,|&|v|0|o|\\[|V|t|\\||\u{00F3})";
-    let re = Regex::new(&format!("(?P<E>a)?(?P<R>j)?(?P<con>{})\u{1004}\u{103A}\u{1039}", con_pattern)).unwrap();
-    unistr = re
+    let patterns = patterns();
+    let [kinzi, kinzi_i, kinzi_ii, kinzi_anusvara] = &patterns.kinzi;
+    unistr = kinzi
         .replace_all(&unistr, "\u{1004}\u{103A}\u{1039}${E}${R}${con}")
         .to_string();
-    let re = Regex::new(&format!("(?P<E>a)?(?P<R>j)?(?P<con>{})\u{00D8}", con_pattern)).unwrap();
-    unistr = re.replace_all(&unistr, "F${E}${R}${con}d").to_string();
-    let re = Regex::new(&format!("(?P<E>a)?(?P<R>j)?(?P<con>{})\u{00D0}", con_pattern)).unwrap();
-    unistr = re.replace_all(&unistr, "F${E}${R}${con}D").to_string();
-    let re = Regex::new(&format!("(?P<E>a)?(?P<R>j)?(?P<con>{})\u{00F8}", con_pattern)).unwrap();
-    unistr = re.replace_all(&unistr, "F${E}${R}${con}H").to_string();
+    unistr = kinzi_i.replace_all(&unistr, "F${E}${R}${con}d").to_string();
+    unistr = kinzi_ii.replace_all(&unistr, "F${E}${R}${con}D").to_string();
+    unistr = kinzi_anusvara.replace_all(&unistr, "F${E}${R}${con}H").to_string();
 
     // Reordering Ra
-    let re = Regex::new("(?P<R>\u{103C})(?P<Wa>\u{103D})?(?P<Ha>\u{103E})?(?P<U>\u{102F})?(?P<con>[\u{1000}-\u{1021}])(?P<scon>\u{1039}[\u{1000}-\u{1021}])?")
-        .unwrap();
-    unistr = re
+    unistr = patterns
+        .medial_ra
         .replace_all(&unistr, "${con}${scon}${R}${Wa}${Ha}${U}")
         .to_string();
 
@@ -446,9 +461,8 @@ pub fn win_to_myanmar3(input: &str) -> String {
     unistr = replace_zero_wa(&unistr);
 
     // Final reordering for storage order
-    let re = Regex::new("(?P<E>\u{1031})?(?P<con>[\u{1000}-\u{1021}])(?P<scon>\u{1039}[\u{1000}-\u{1021}])?(?P<upper>[\u{102D}\u{102E}\u{1032}\u{1036}])?(?P<DVs>[\u{1037}\u{1038}]){0,2}(?P<M>[\u{103B}-\u{103E}]*)(?P<lower>[\u{102F}\u{1030}])?(?P<upper2>[\u{102D}\u{102E}\u{1032}])?")
-        .unwrap();
-    unistr = re
+    unistr = patterns
+        .storage_order
         .replace_all(&unistr, "${con}${scon}${M}${E}${upper}${lower}${DVs}${upper2}")
         .to_string();
 
@@ -458,3 +472,94 @@ pub fn win_to_myanmar3(input: &str) -> String {
     // Cleanup after conversion (avoid conflicts during Win Innwa mapping)
     cleanup_after_convert(&unistr)
 }
+
+/// Why a conversion may be wrong and is worth a proofreader's look.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Uncertainty {
+    /// "0" or "7" taken for wa or ra because of the letters around it.
+    DigitOrLetter,
+    /// Characters the mapping has no entry for, left as they were.
+    Unmapped,
+    /// A sign with no consonant to attach to, or the same sign twice.
+    InvalidSyllable,
+}
+
+fn is_dependent_sign(ch: char) -> bool {
+    ('\u{102B}'..='\u{103E}').contains(&ch)
+}
+
+/// Latin-1 punctuation that legacy-font text uses as punctuation.
+const LATIN1_PUNCTUATION: &[char] = &['\u{00A1}', '\u{00A7}', '\u{00AB}', '\u{00B6}', '\u{00B7}', '\u{00BB}', '\u{00BF}'];
+
+/// A character the mapping left as it was although the source font draws it as a
+/// Myanmar glyph: a Latin letter, a Latin-1 sign or a symbol-font code point. Spaces,
+/// the no-break space among them, and punctuation look the same in either font.
+fn is_unmapped(ch: char) -> bool {
+    if ch.is_whitespace() || ch.is_ascii_punctuation() || LATIN1_PUNCTUATION.contains(&ch) {
+        return false;
+    }
+    ch.is_ascii_alphabetic() || ('\u{00A0}'..='\u{00FF}').contains(&ch) || ('\u{F000}'..='\u{F0FF}').contains(&ch)
+}
+
+/// Looks for the spots in a conversion that `win_to_myanmar3` had to guess at or
+/// could not map.
+pub fn review_conversion(input: &str, output: &str) -> Option<Uncertainty> {
+    let input = fold_symbol_code_points(input);
+    // Keys the mapping turns into exactly `target`.
+    let maps_to = |ch: char, target: char| {
+        FONT_MAPPING_ENTRIES
+            .iter()
+            .any(|(win, unicode)| win.chars().eq([ch]) && unicode.chars().eq([target]))
+    };
+    // A digit `correction1` may read as a letter was typed, and the output has more of
+    // that letter than was typed as one.
+    for (digit, letter) in DIGIT_LETTERS {
+        let digit_typed = input.chars().any(|ch| maps_to(ch, digit));
+        let typed = input.chars().filter(|ch| maps_to(*ch, letter)).count();
+        if digit_typed && output.chars().filter(|ch| *ch == letter).count() > typed {
+            return Some(Uncertainty::DigitOrLetter);
+        }
+    }
+
+    if output.chars().any(is_unmapped) {
+        return Some(Uncertainty::Unmapped);
+    }
+
+    let mut previous: Option<char> = None;
+    for ch in output.chars() {
+        if is_dependent_sign(ch) {
+            let attached = previous.is_some_and(|prev| ('\u{1000}'..='\u{109F}').contains(&prev));
+            if !attached || previous == Some(ch) {
+                return Some(Uncertainty::InvalidSyllable);
+            }
+        }
+        previous = Some(ch);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(input: &str) -> Option<Uncertainty> {
+        review_conversion(input, &win_to_myanmar3(input))
+    }
+
+    #[test]
+    fn spaces_and_punctuation_are_not_unmapped() {
+        assert_eq!(review("u\u{00A0}c"), None);
+        assert_eq!(review("u, c."), None);
+        assert_eq!(review("u\u{00C0}"), Some(Uncertainty::Unmapped));
+    }
+
+    #[test]
+    fn digit_read_as_letter_is_flagged() {
+        // "8" before a vowel sign is read as ga, "*" is typed as ga.
+        assert_eq!(win_to_myanmar3("8m"), win_to_myanmar3("*m"));
+        assert_eq!(review("8m"), Some(Uncertainty::DigitOrLetter));
+        assert_eq!(review("*m"), None);
+        assert_eq!(review("0f"), Some(Uncertainty::DigitOrLetter));
+        assert_eq!(review("u8"), None);
+    }
+}
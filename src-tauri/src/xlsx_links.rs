@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use zip::ZipArchive;
+
+use crate::font_rules::FontRule;
+use crate::opc;
+use crate::xlsx_selection::workbook_sheets;
+use crate::xlsx_styles::cell_position;
+
+const WORKBOOK_PART: &str = "xl/workbook.xml";
+
+/// Rule each linked cell of one worksheet was converted with, by column and row;
+/// `None` while the cell is not found or its text is not converted.
+pub type LinkedRules<'r> = HashMap<(u32, u32), Option<&'r FontRule>>;
+
+/// Header row of a table: the worksheet it is on, its first header cell and the
+/// names of its columns from left to right.
+struct TableHeader {
+    sheet: String,
+    first: (u32, u32),
+    columns: Vec<String>,
+}
+
+/// Cells whose text other parts repeat: the header row of each table, which names
+/// its columns, and the cells chart series read, whose text the chart caches. The
+/// first pass over each worksheet records the rule the text of these cells is
+/// converted with, so the copies convert the same way.
+#[derive(Default)]
+pub struct LinkedCells<'r> {
+    /// Worksheet part of each sheet, by name.
+    sheet_parts: HashMap<String, String>,
+    /// Linked cells of each worksheet part.
+    cells: HashMap<String, LinkedRules<'r>>,
+    tables: Vec<TableHeader>,
+}
+
+impl<'r> LinkedCells<'r> {
+    /// Reads the tables of every worksheet and the series of the `charts` parts.
+    pub fn load<R: Read + Seek>(archive: &mut ZipArchive<R>, charts: &[&str]) -> Self {
+        let targets: HashMap<String, String> = opc::read_relationships(archive, WORKBOOK_PART)
+            .into_iter()
+            .map(|rel| (rel.id, rel.target))
+            .collect();
+        let workbook = opc::read_part(archive, WORKBOOK_PART).unwrap_or_default();
+
+        let mut linked = LinkedCells::default();
+        for (name, id) in workbook_sheets(&workbook) {
+            if let Some(part) = targets.get(&id) {
+                linked.sheet_parts.insert(name, part.clone());
+            }
+        }
+
+        let sheets: Vec<String> = linked.sheet_parts.values().cloned().collect();
+        for sheet in sheets {
+            for rel in opc::read_relationships(archive, &sheet).into_iter().filter(|rel| rel.kind() == "table") {
+                let Some(table) = opc::read_part(archive, &rel.target).and_then(|table| table_header(&table, &sheet)) else {
+                    continue;
+                };
+                let cells = linked.cells.entry(sheet.clone()).or_default();
+                for column in 0..table.columns.len() as u32 {
+                    cells.insert((table.first.0 + column, table.first.1), None);
+                }
+                linked.tables.push(table);
+            }
+        }
+
+        for chart in charts {
+            let Some(contents) = opc::read_part(archive, chart) else {
+                continue;
+            };
+            for formula in series_text_formulas(&contents) {
+                let cells: Vec<(String, (u32, u32))> = linked
+                    .resolve(&formula)
+                    .into_iter()
+                    .map(|(sheet, position)| (sheet.to_string(), position))
+                    .collect();
+                for (sheet, position) in cells {
+                    linked.cells.entry(sheet).or_default().insert(position, None);
+                }
+            }
+        }
+        linked
+    }
+
+    /// Linked cells of the worksheet `part`, for the first pass to fill in.
+    pub fn sheet_cells(&mut self, part: &str) -> Option<&mut LinkedRules<'r>> {
+        self.cells.get_mut(part)
+    }
+
+    fn rule(&self, sheet: &str, position: (u32, u32)) -> Option<&'r FontRule> {
+        self.cells.get(sheet)?.get(&position).copied().flatten()
+    }
+
+    /// Table columns whose header cell text is converted, renamed the same way so
+    /// each table keeps matching its header row.
+    pub fn column_renames(&self) -> Vec<(String, String)> {
+        let mut renames = Vec::new();
+        for table in &self.tables {
+            for (column, name) in (0..).zip(&table.columns) {
+                let Some(rule) = self.rule(&table.sheet, (table.first.0 + column, table.first.1)) else {
+                    continue;
+                };
+                let new = rule.mapping.convert(name);
+                if new != *name && !renames.iter().any(|(old, _)| old == name) {
+                    renames.push((name.clone(), new));
+                }
+            }
+        }
+        renames
+    }
+
+    /// Rule of each point of a series reading the cells of `formula`, in point
+    /// order.
+    pub fn series_rules(&self, formula: &str) -> Vec<Option<&'r FontRule>> {
+        self.resolve(formula)
+            .into_iter()
+            .map(|(sheet, position)| self.rule(sheet, position))
+            .collect()
+    }
+
+    /// Worksheet part, column and row of each cell a series formula such as
+    /// `Sheet1!$A$2:$A$5` or `('Sheet 1'!$A$2,'Sheet 1'!$A$4)` reads, in order.
+    /// Areas spanning more than one row and column are not a series and are skipped.
+    fn resolve(&self, formula: &str) -> Vec<(&str, (u32, u32))> {
+        let formula = formula.trim();
+        let formula = formula.strip_prefix('(').and_then(|inner| inner.strip_suffix(')')).unwrap_or(formula);
+
+        let mut cells = Vec::new();
+        for area in split_areas(formula) {
+            let Some((sheet, range)) = area.rsplit_once('!') else {
+                continue;
+            };
+            let Some(part) = self.sheet_parts.get(&unquote(sheet.trim())) else {
+                continue;
+            };
+            let (first, last) = range.split_once(':').unwrap_or((range, range));
+            let (Some(first), Some(last)) = (cell_position(first.trim()), cell_position(last.trim())) else {
+                continue;
+            };
+            if first.0 != last.0 && first.1 != last.1 {
+                continue;
+            }
+            for row in first.1..=last.1 {
+                for column in first.0..=last.0 {
+                    cells.push((part.as_str(), (column, row)));
+                }
+            }
+        }
+        cells
+    }
+}
+
+/// Splits a reference list at the commas outside quoted sheet names.
+fn split_areas(formula: &str) -> Vec<&str> {
+    let mut areas = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (idx, ch) in formula.char_indices() {
+        match ch {
+            '\'' => quoted = !quoted,
+            ',' if !quoted => {
+                areas.push(&formula[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    areas.push(&formula[start..]);
+    areas
+}
+
+fn unquote(quoted: &str) -> String {
+    let inner = quoted.strip_prefix('\'').unwrap_or(quoted);
+    let inner = inner.strip_suffix('\'').unwrap_or(inner);
+    inner.replace("''", "'")
+}
+
+fn attr_value(elem: &BytesStart, key: &[u8]) -> Option<String> {
+    elem.attributes()
+        .flatten()
+        .find(|attr| attr.key.as_ref() == key)
+        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+}
+
+/// Header row of a table part on `sheet`; `None` for a table without one.
+fn table_header(table: &[u8], sheet: &str) -> Option<TableHeader> {
+    let mut reader = Reader::from_reader(table);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut first = None;
+    let mut columns = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.name().as_ref() {
+                b"table" => {
+                    if attr_value(&e, b"headerRowCount").as_deref() == Some("0") {
+                        return None;
+                    }
+                    let reference = attr_value(&e, b"ref")?;
+                    first = cell_position(reference.split(':').next().unwrap_or_default());
+                }
+                b"tableColumn" => columns.push(attr_value(&e, b"name").unwrap_or_default()),
+                _ => {}
+            },
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Some(TableHeader {
+        sheet: sheet.to_string(),
+        first: first?,
+        columns,
+    })
+}
+
+/// Formulas of the text references (`c:strRef`) of a chart's series.
+fn series_text_formulas(chart: &[u8]) -> Vec<String> {
+    let mut reader = Reader::from_reader(chart);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut formulas = Vec::new();
+    let mut in_str_ref = false;
+    let mut in_formula = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => match e.name().as_ref() {
+                b"c:strRef" => in_str_ref = true,
+                b"c:f" => in_formula = in_str_ref,
+                _ => {}
+            },
+            Ok(Event::Text(e)) if in_formula => {
+                formulas.push(e.unescape().unwrap_or_default().to_string());
+            }
+            Ok(Event::End(e)) => match e.name().as_ref() {
+                b"c:strRef" => in_str_ref = false,
+                b"c:f" => in_formula = false,
+                _ => {}
+            },
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    formulas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font_rules::FontRules;
+
+    fn linked<'r>() -> LinkedCells<'r> {
+        LinkedCells {
+            sheet_parts: HashMap::from([
+                ("Sheet1".to_string(), "xl/worksheets/sheet1.xml".to_string()),
+                ("Tom's, list".to_string(), "xl/worksheets/sheet2.xml".to_string()),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn series_formulas_resolve_to_cells() {
+        let linked = linked();
+        assert_eq!(
+            linked.resolve("Sheet1!$A$2:$A$4"),
+            [("xl/worksheets/sheet1.xml", (1, 2)), ("xl/worksheets/sheet1.xml", (1, 3)), ("xl/worksheets/sheet1.xml", (1, 4))]
+        );
+        assert_eq!(
+            linked.resolve("('Tom''s, list'!$B$1,Sheet1!$C$1:$D$1)"),
+            [("xl/worksheets/sheet2.xml", (2, 1)), ("xl/worksheets/sheet1.xml", (3, 1)), ("xl/worksheets/sheet1.xml", (4, 1))]
+        );
+        // Blocks, unknown sheets and references without a sheet are not series.
+        assert!(linked.resolve("Sheet1!$A$1:$B$2").is_empty());
+        assert!(linked.resolve("Sheet9!$A$1").is_empty());
+        assert!(linked.resolve("$A$1").is_empty());
+    }
+
+    #[test]
+    fn table_columns_follow_their_header_cells() {
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        let table = r#"<table ref="B2:C5"><tableColumns count="2"><tableColumn id="1" name="u"/><tableColumn id="2" name="c"/></tableColumns></table>"#;
+        let mut linked = linked();
+        linked.tables.extend(table_header(table.as_bytes(), "xl/worksheets/sheet1.xml"));
+        linked.cells.insert("xl/worksheets/sheet1.xml".to_string(), HashMap::from([((2, 2), rules.find("Win Innwa")), ((3, 2), None)]));
+        assert_eq!(linked.column_renames(), [("u".to_string(), "\u{1000}".to_string())]);
+        assert!(table_header(br#"<table ref="A1:B2" headerRowCount="0"/>"#, "xl/worksheets/sheet1.xml").is_none());
+    }
+
+    #[test]
+    fn only_text_references_are_read_from_charts() {
+        let chart = r#"<c:ser><c:tx><c:strRef><c:f>Sheet1!$B$1</c:f></c:strRef></c:tx><c:cat><c:strRef><c:f>Sheet1!$A$2:$A$4</c:f></c:strRef></c:cat><c:val><c:numRef><c:f>Sheet1!$B$2:$B$4</c:f></c:numRef></c:val></c:ser>"#;
+        assert_eq!(series_text_formulas(chart.as_bytes()), ["Sheet1!$B$1", "Sheet1!$A$2:$A$4"]);
+    }
+}
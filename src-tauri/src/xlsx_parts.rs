@@ -0,0 +1,922 @@
+use std::collections::HashSet;
+use std::io::BufRead;
+
+use quick_xml::events::{BytesStart, BytesText, Event};
+
+use crate::font_rules::{FontRule, FontRules, FontSlot};
+use crate::xlsx_links::LinkedCells;
+use crate::xlsx_styles::{parse_sqref, CellRange};
+use crate::{convert_attr, ContainerReport};
+
+/// Characters Excel does not allow in a sheet name.
+const INVALID_SHEET_CHARS: &[char] = &['[', ']', ':', '*', '?', '/', '\\'];
+const MAX_SHEET_NAME_CHARS: usize = 31;
+
+/// `headerFooter` children holding header and footer format strings.
+const HEADER_FOOTER_ELEMENTS: &[&[u8]] = &[
+    b"oddHeader",
+    b"oddFooter",
+    b"evenHeader",
+    b"evenFooter",
+    b"firstHeader",
+    b"firstFooter",
+];
+
+/// Sheets and table columns renamed by the conversion. Every formula and
+/// reference in the workbook goes through `rename_formula` so it keeps pointing
+/// at the renamed sheet or column.
+#[derive(Default)]
+pub struct References {
+    pub sheets: Vec<(String, String)>,
+    pub columns: Vec<(String, String)>,
+}
+
+impl References {
+    pub fn is_empty(&self) -> bool {
+        self.sheets.is_empty() && self.columns.is_empty()
+    }
+
+    pub fn sheet(&self, name: &str) -> Option<&str> {
+        self.sheets.iter().find(|(old, _)| old == name).map(|(_, new)| new.as_str())
+    }
+
+    fn column(&self, name: &str) -> Option<&str> {
+        self.columns.iter().find(|(old, _)| old == name).map(|(_, new)| new.as_str())
+    }
+
+    /// Rewrites the sheet names (`'Sheet'!A1`, `Sheet!A1`, `'A:B'!A1`, `A:B!A1`) and
+    /// structured reference columns (`Table[Col]`, `[@Col]`, `[[#Headers],[Col]]`)
+    /// of a formula. String literals and sheets of other workbooks (`[1]Sheet!A1`)
+    /// are left alone.
+    pub fn rename_formula(&self, formula: &str) -> String {
+        if self.is_empty() {
+            return formula.to_string();
+        }
+
+        let chars: Vec<char> = formula.chars().collect();
+        let mut output = String::with_capacity(formula.len());
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '"' => {
+                    let end = quoted_end(&chars, i);
+                    output.extend(&chars[i..end]);
+                    i = end;
+                }
+                '\'' => {
+                    let end = quoted_end(&chars, i);
+                    let quoted: String = chars[i..end].iter().collect();
+                    let renamed = (chars.get(end) == Some(&'!'))
+                        .then(|| self.rename_sheet_range(&unquote(&quoted)))
+                        .flatten();
+                    match renamed {
+                        Some(name) => output.push_str(&quote(&name)),
+                        None => output.push_str(&quoted),
+                    }
+                    i = end;
+                }
+                '[' => {
+                    let end = bracket_end(&chars, i);
+                    if chars.get(end).is_some_and(|ch| is_name_char(*ch) || *ch == '\'') {
+                        // A workbook index: the sheet after it is in another workbook.
+                        let sheet_end = match chars[end] {
+                            '\'' => quoted_end(&chars, end),
+                            _ => name_end(&chars, end, true),
+                        };
+                        output.extend(&chars[i..sheet_end]);
+                        i = sheet_end;
+                    } else {
+                        self.rename_columns(&chars[i..end], &mut output);
+                        i = end;
+                    }
+                }
+                ch if is_name_char(ch) => {
+                    let start = i;
+                    i = name_end(&chars, i, false);
+                    // `First:Last!A1` spans sheets; `A1:B2` is a plain range.
+                    let range_end = name_end(&chars, i, true);
+                    if chars.get(i) == Some(&':') && chars.get(range_end) == Some(&'!') {
+                        i = range_end;
+                    }
+                    let name: String = chars[start..i].iter().collect();
+                    let renamed = (chars.get(i) == Some(&'!')).then(|| self.rename_sheet_range(&name)).flatten();
+                    match renamed {
+                        Some(new) => output.push_str(&quote(&new)),
+                        None => output.push_str(&name),
+                    }
+                }
+                ch => {
+                    output.push(ch);
+                    i += 1;
+                }
+            }
+        }
+        output
+    }
+
+    /// A sheet name or `First:Last` sheet range with its sheets renamed; `None` when
+    /// nothing in it changed or it points into another workbook.
+    fn rename_sheet_range(&self, name: &str) -> Option<String> {
+        if name.starts_with('[') {
+            return None;
+        }
+        let mut changed = false;
+        let parts: Vec<&str> = name
+            .split(':')
+            .map(|part| match self.sheet(part) {
+                Some(new) => {
+                    changed = true;
+                    new
+                }
+                None => part,
+            })
+            .collect();
+        changed.then(|| parts.join(":"))
+    }
+
+    /// Copies a bracketed structured reference, renaming the columns in it.
+    fn rename_columns(&self, group: &[char], output: &mut String) {
+        if group.len() < 2 || group[group.len() - 1] != ']' {
+            output.extend(group);
+            return;
+        }
+        let inner = &group[1..group.len() - 1];
+        output.push('[');
+        if has_nested_group(inner) {
+            let mut i = 0;
+            while i < inner.len() {
+                if inner[i] == '[' {
+                    let end = bracket_end(inner, i);
+                    self.rename_columns(&inner[i..end], output);
+                    i = end;
+                } else {
+                    output.push(inner[i]);
+                    i += 1;
+                }
+            }
+        } else {
+            let (prefix, name) = match inner.first() {
+                Some('@') => ("@", &inner[1..]),
+                _ => ("", inner),
+            };
+            output.push_str(prefix);
+            match self.column(&unescape_column(name)) {
+                Some(new) => output.push_str(&escape_column(new)),
+                None => output.extend(name),
+            }
+        }
+        output.push(']');
+    }
+}
+
+fn is_name_char(ch: char) -> bool {
+    ch.is_alphanumeric() || matches!(ch, '_' | '.' | '\\')
+}
+
+/// End of the unquoted name starting at `start`, taking in `:` between names when
+/// `range` is set.
+fn name_end(chars: &[char], start: usize, range: bool) -> usize {
+    let mut i = start;
+    while i < chars.len() && (is_name_char(chars[i]) || (range && chars[i] == ':')) {
+        i += 1;
+    }
+    i
+}
+
+/// End of a `"` or `'` quoted span starting at `start`; doubled quotes are escapes.
+fn quoted_end(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// End of the bracket group starting at `start`; `'` escapes the next character.
+fn bracket_end(chars: &[char], start: usize) -> usize {
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\'' => {
+                i += 2;
+                continue;
+            }
+            '[' => depth += 1,
+            ']' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+fn has_nested_group(inner: &[char]) -> bool {
+    let mut i = 0;
+    while i < inner.len() {
+        match inner[i] {
+            '\'' => i += 2,
+            '[' => return true,
+            _ => i += 1,
+        }
+    }
+    false
+}
+
+fn quote(name: &str) -> String {
+    format!("'{}'", name.replace('\'', "''"))
+}
+
+fn unquote(quoted: &str) -> String {
+    let inner = quoted.strip_prefix('\'').unwrap_or(quoted);
+    let inner = inner.strip_suffix('\'').unwrap_or(inner);
+    inner.replace("''", "'")
+}
+
+fn unescape_column(name: &[char]) -> String {
+    let mut column = String::new();
+    let mut chars = name.iter();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' => column.extend(chars.next()),
+            ch => column.push(*ch),
+        }
+    }
+    column
+}
+
+fn escape_column(name: &str) -> String {
+    let mut escaped = String::new();
+    for ch in name.chars() {
+        if matches!(ch, '[' | ']' | '#' | '\'') {
+            escaped.push('\'');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+fn attr_value(elem: &BytesStart, key: &[u8]) -> Option<String> {
+    elem.attributes()
+        .flatten()
+        .find(|attr| attr.key.as_ref() == key)
+        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+}
+
+/// Converted name for a sheet, or `None` when it should keep its name: the name
+/// would not be a valid sheet name, or converting it looks like a guess. Sheet tabs
+/// are not drawn in the cell font, so many sheet names were typed in plain Latin.
+fn convert_sheet_name(name: &str, rule: &FontRule) -> Option<String> {
+    let converted = rule.mapping.convert(name);
+    let valid = !converted.trim().is_empty()
+        && converted.chars().count() <= MAX_SHEET_NAME_CHARS
+        && !converted.contains(INVALID_SHEET_CHARS)
+        && !converted.starts_with('\'')
+        && !converted.ends_with('\'');
+    if converted == name || !valid || rule.mapping.review(name, &converted).is_some() {
+        return None;
+    }
+    Some(converted)
+}
+
+/// Sheet names in `xl/workbook.xml` to convert with the workbook's default rule.
+pub fn sheet_renames(workbook: &[u8], rule: Option<&FontRule>) -> Vec<(String, String)> {
+    use quick_xml::Reader;
+
+    let Some(rule) = rule else {
+        return Vec::new();
+    };
+
+    let mut reader = Reader::from_reader(workbook);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut names = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"sheet" => {
+                names.extend(attr_value(&e, b"name"));
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    let mut taken: HashSet<String> = names.iter().map(|name| name.to_lowercase()).collect();
+    let mut renames = Vec::new();
+    for name in names {
+        let Some(new) = convert_sheet_name(&name, rule) else {
+            continue;
+        };
+        if taken.insert(new.to_lowercase()) {
+            renames.push((name, new));
+        } else {
+            log::warn!("Keeping sheet name {:?}: converted name {:?} is taken", name, new);
+        }
+    }
+    renames
+}
+
+/// Converts the string literals of a formula typed in `rule`'s font, so it keeps
+/// comparing against the converted cell text. Quoted sheet names, structured
+/// references and everything outside quotes are kept.
+pub fn convert_formula_strings(formula: &str, rule: &FontRule) -> String {
+    let chars: Vec<char> = formula.chars().collect();
+    let mut output = String::with_capacity(formula.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '"' => {
+                let end = quoted_end(&chars, i);
+                let literal: String = chars[i..end].iter().collect();
+                output.push_str(&quote_literal(&rule.mapping.convert(&unquote_literal(&literal))));
+                i = end;
+            }
+            '\'' => {
+                let end = quoted_end(&chars, i);
+                output.extend(&chars[i..end]);
+                i = end;
+            }
+            '[' => {
+                let end = bracket_end(&chars, i);
+                output.extend(&chars[i..end]);
+                i = end;
+            }
+            ch => {
+                output.push(ch);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+/// Converts a data validation formula. A literal drop-down list (`"a,b,c"`) has
+/// each item converted on its own, since the converter maps the `,` separator to
+/// a letter; any other formula has its string literals converted.
+pub fn convert_validation_formula(formula: &str, rule: &FontRule) -> String {
+    let chars: Vec<char> = formula.chars().collect();
+    if chars.first() != Some(&'"') || quoted_end(&chars, 0) != chars.len() {
+        return convert_formula_strings(formula, rule);
+    }
+    let items: Vec<String> = unquote_literal(formula)
+        .split(',')
+        .map(|item| rule.mapping.convert(item))
+        .collect();
+    quote_literal(&items.join(","))
+}
+
+fn quote_literal(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+fn unquote_literal(literal: &str) -> String {
+    let inner = literal.strip_prefix('"').unwrap_or(literal);
+    let inner = inner.strip_suffix('"').unwrap_or(inner);
+    inner.replace("\"\"", "\"")
+}
+
+/// Font of the cells each `dataValidation` of a worksheet applies to. The
+/// validations follow `sheetData`, so their ranges are read first and each cell
+/// is checked against them as the sheet is rewritten.
+#[derive(Default)]
+pub struct ValidationFonts<'r> {
+    /// Ranges of each validation, the rule of the first source font cell in them,
+    /// and whether any cell in them was seen.
+    validations: Vec<(Vec<CellRange>, Option<&'r FontRule>, bool)>,
+}
+
+impl<'r> ValidationFonts<'r> {
+    pub fn scan<R: BufRead>(source: R) -> Self {
+        use quick_xml::Reader;
+
+        let mut reader = Reader::from_reader(source);
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+        let mut fonts = ValidationFonts::default();
+
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"dataValidation" => {
+                    let sqref = e
+                        .try_get_attribute("sqref")
+                        .ok()
+                        .flatten()
+                        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+                        .unwrap_or_default();
+                    fonts.validations.push((parse_sqref(&sqref), None, false));
+                }
+                Ok(Event::Eof) => break,
+                Err(_) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        fonts
+    }
+
+    /// Notes the font of the cell at `cell` for the validations covering it.
+    pub fn record(&mut self, cell: &str, rule: Option<&'r FontRule>) {
+        for (ranges, validation_rule, seen) in &mut self.validations {
+            if ranges.iter().any(|range| range.contains_cell(cell)) {
+                *seen = true;
+                *validation_rule = validation_rule.or(rule);
+            }
+        }
+    }
+
+    /// Rule for the formulas of the validation at `index`. A validation over cells
+    /// the sheet does not write out follows the default cell style.
+    pub fn rule(&self, index: usize, default_rule: Option<&'r FontRule>) -> Option<&'r FontRule> {
+        let (_, rule, seen) = self.validations.get(index)?;
+        if *seen {
+            *rule
+        } else {
+            default_rule
+        }
+    }
+}
+
+/// Converts the text of a header or footer format string. `&"Font,Style"` codes
+/// switch the font of the text after them, and each `&L`, `&C`, `&R` section
+/// starts over in the default font.
+pub fn convert_header_footer(value: &str, rules: &FontRules, default_rule: Option<&FontRule>) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut text = String::new();
+    let mut rule = default_rule;
+    let mut chars = value.chars().peekable();
+
+    let flush = |text: &mut String, output: &mut String, rule: Option<&FontRule>| {
+        match rule {
+            // A literal ampersand is written doubled.
+            Some(rule) => output.push_str(&rule.mapping.convert(text).replace('&', "&&")),
+            None => output.push_str(&text.replace('&', "&&")),
+        }
+        text.clear();
+    };
+
+    while let Some(ch) = chars.next() {
+        if ch != '&' {
+            text.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('&') => text.push('&'),
+            Some('"') => {
+                flush(&mut text, &mut output, rule);
+                let mut spec = String::new();
+                for ch in chars.by_ref() {
+                    if ch == '"' {
+                        break;
+                    }
+                    spec.push(ch);
+                }
+                let (font, style) = spec.split_once(',').unwrap_or((spec.as_str(), ""));
+                output.push_str("&\"");
+                if font == "-" {
+                    output.push_str(font);
+                } else {
+                    rule = rules.find(font);
+                    output.push_str(rule.map(|rule| rule.target(FontSlot::Ascii)).unwrap_or(font));
+                }
+                if spec.contains(',') {
+                    output.push(',');
+                    output.push_str(style);
+                }
+                output.push('"');
+            }
+            Some(code @ ('L' | 'C' | 'R')) => {
+                flush(&mut text, &mut output, rule);
+                rule = default_rule;
+                output.push('&');
+                output.push(code);
+            }
+            Some(digit) if digit.is_ascii_digit() => {
+                flush(&mut text, &mut output, rule);
+                output.push('&');
+                output.push(digit);
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    output.push(digit);
+                }
+            }
+            Some('K') => {
+                flush(&mut text, &mut output, rule);
+                output.push_str("&K");
+                output.extend(chars.by_ref().take(6));
+            }
+            Some(code) => {
+                flush(&mut text, &mut output, rule);
+                output.push('&');
+                output.push(code);
+            }
+            None => text.push('&'),
+        }
+    }
+    flush(&mut text, &mut output, rule);
+    output
+}
+
+pub fn is_header_footer(name: &[u8]) -> bool {
+    HEADER_FOOTER_ELEMENTS.contains(&name)
+}
+
+/// Rewrites `xl/workbook.xml`: renamed sheets and the references in defined names.
+pub fn process_workbook(contents: &[u8], refs: &References, report: &mut ContainerReport) -> Vec<u8> {
+    use quick_xml::{Reader, Writer};
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+    let mut buf = Vec::new();
+    let mut in_defined_name = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                if e.name().as_ref() == b"definedName" {
+                    in_defined_name = true;
+                }
+                writer.write_event(Event::Start(e.into_owned())).ok();
+            }
+            Ok(Event::Empty(e)) => {
+                let renamed = (e.name().as_ref() == b"sheet")
+                    .then(|| attr_value(&e, b"name"))
+                    .flatten()
+                    .and_then(|name| refs.sheet(&name));
+                match renamed {
+                    Some(new) => {
+                        report.record("sheet name");
+                        writer.write_event(Event::Empty(convert_attr(&e, b"name", |_| new.to_string()))).ok();
+                    }
+                    None => {
+                        writer.write_event(Event::Empty(e.into_owned())).ok();
+                    }
+                }
+            }
+            Ok(Event::Text(e)) if in_defined_name => {
+                let text = e.unescape().unwrap_or_default().to_string();
+                let renamed = refs.rename_formula(&text);
+                if renamed == text {
+                    writer.write_event(Event::Text(e.into_owned())).ok();
+                } else {
+                    writer.write_event(Event::Text(BytesText::new(&renamed))).ok();
+                }
+            }
+            Ok(Event::End(e)) => {
+                if e.name().as_ref() == b"definedName" {
+                    in_defined_name = false;
+                }
+                writer.write_event(Event::End(e.into_owned())).ok();
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
+
+/// Rewrites a table part: renamed columns and the references in column formulas.
+pub fn process_table(contents: &[u8], refs: &References, report: &mut ContainerReport) -> Vec<u8> {
+    use quick_xml::{Reader, Writer};
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+    let mut buf = Vec::new();
+    let mut in_formula = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let name = e.name().as_ref().to_vec();
+                if matches!(name.as_slice(), b"calculatedColumnFormula" | b"totalsRowFormula") {
+                    in_formula = true;
+                }
+                let renamed = (name.as_slice() == b"tableColumn")
+                    .then(|| attr_value(&e, b"name"))
+                    .flatten()
+                    .and_then(|name| refs.column(&name));
+                match renamed {
+                    Some(new) => {
+                        report.record("table column");
+                        writer.write_event(Event::Start(convert_attr(&e, b"name", |_| new.to_string()))).ok();
+                    }
+                    None => {
+                        writer.write_event(Event::Start(e.into_owned())).ok();
+                    }
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                let renamed = (e.name().as_ref() == b"tableColumn")
+                    .then(|| attr_value(&e, b"name"))
+                    .flatten()
+                    .and_then(|name| refs.column(&name));
+                match renamed {
+                    Some(new) => {
+                        report.record("table column");
+                        writer.write_event(Event::Empty(convert_attr(&e, b"name", |_| new.to_string()))).ok();
+                    }
+                    None => {
+                        writer.write_event(Event::Empty(e.into_owned())).ok();
+                    }
+                }
+            }
+            Ok(Event::Text(e)) if in_formula => {
+                let text = e.unescape().unwrap_or_default().to_string();
+                let renamed = refs.rename_formula(&text);
+                if renamed == text {
+                    writer.write_event(Event::Text(e.into_owned())).ok();
+                } else {
+                    writer.write_event(Event::Text(BytesText::new(&renamed))).ok();
+                }
+            }
+            Ok(Event::End(e)) => {
+                if matches!(e.name().as_ref(), b"calculatedColumnFormula" | b"totalsRowFormula") {
+                    in_formula = false;
+                }
+                writer.write_event(Event::End(e.into_owned())).ok();
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
+
+/// Rewrites the references of a chart's series and converts the cached text of
+/// each point whose cell text was converted, with the rule of that cell.
+pub fn process_chart(contents: &[u8], refs: &References, linked: &LinkedCells, report: &mut ContainerReport) -> Vec<u8> {
+    use quick_xml::{Reader, Writer};
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+    let mut buf = Vec::new();
+    let mut in_str_ref = false;
+    let mut in_formula = false;
+    let mut in_cache_value = false;
+    let mut cache_depth = 0usize;
+    // Rule of each point of the current text reference, and the point being read.
+    let mut point_rules: Vec<Option<&FontRule>> = Vec::new();
+    let mut point: Option<usize> = None;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                match e.name().as_ref() {
+                    b"c:strRef" => in_str_ref = true,
+                    b"c:f" => in_formula = true,
+                    b"c:strCache" => cache_depth += 1,
+                    b"c:pt" => point = attr_value(&e, b"idx").and_then(|idx| idx.parse().ok()),
+                    b"c:v" => in_cache_value = cache_depth > 0,
+                    _ => {}
+                }
+                writer.write_event(Event::Start(e.into_owned())).ok();
+            }
+            Ok(Event::Text(e)) if in_formula || in_cache_value => {
+                let text = e.unescape().unwrap_or_default().to_string();
+                let new_text = if in_formula {
+                    if in_str_ref {
+                        point_rules = linked.series_rules(&text);
+                    }
+                    refs.rename_formula(&text)
+                } else {
+                    match point.and_then(|point| point_rules.get(point).copied().flatten()) {
+                        Some(rule) => {
+                            report.record("chart");
+                            rule.mapping.convert(&text)
+                        }
+                        None => text.clone(),
+                    }
+                };
+                if new_text == text {
+                    writer.write_event(Event::Text(e.into_owned())).ok();
+                } else {
+                    writer.write_event(Event::Text(BytesText::new(&new_text))).ok();
+                }
+            }
+            Ok(Event::End(e)) => {
+                match e.name().as_ref() {
+                    b"c:strRef" => {
+                        in_str_ref = false;
+                        point_rules.clear();
+                    }
+                    b"c:f" => in_formula = false,
+                    b"c:strCache" => cache_depth = cache_depth.saturating_sub(1),
+                    b"c:pt" => point = None,
+                    b"c:v" => in_cache_value = false,
+                    _ => {}
+                }
+                writer.write_event(Event::End(e.into_owned())).ok();
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
+
+/// Rewrites the sheet a pivot cache reads from.
+pub fn process_pivot_cache(contents: &[u8], refs: &References) -> Vec<u8> {
+    use quick_xml::{Reader, Writer};
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+    let mut buf = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Empty(e)) => {
+                let renamed = (e.name().as_ref() == b"worksheetSource")
+                    .then(|| attr_value(&e, b"sheet"))
+                    .flatten()
+                    .and_then(|name| refs.sheet(&name));
+                match renamed {
+                    Some(new) => {
+                        writer.write_event(Event::Empty(convert_attr(&e, b"sheet", |_| new.to_string()))).ok();
+                    }
+                    None => {
+                        writer.write_event(Event::Empty(e.into_owned())).ok();
+                    }
+                }
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
+
+/// Rewrites the sheet names listed in `docProps/app.xml`.
+pub fn process_app_properties(contents: &[u8], refs: &References) -> Vec<u8> {
+    use quick_xml::{Reader, Writer};
+
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(contents.len()));
+    let mut buf = Vec::new();
+    let mut in_titles = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                if e.name().as_ref() == b"TitlesOfParts" {
+                    in_titles = true;
+                }
+                writer.write_event(Event::Start(e.into_owned())).ok();
+            }
+            Ok(Event::Text(e)) if in_titles => {
+                let text = e.unescape().unwrap_or_default().to_string();
+                match refs.sheet(&text) {
+                    Some(new) => {
+                        writer.write_event(Event::Text(BytesText::new(new))).ok();
+                    }
+                    None => {
+                        writer.write_event(Event::Text(e.into_owned())).ok();
+                    }
+                }
+            }
+            Ok(Event::End(e)) => {
+                if e.name().as_ref() == b"TitlesOfParts" {
+                    in_titles = false;
+                }
+                writer.write_event(Event::End(e.into_owned())).ok();
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn references() -> References {
+        References {
+            sheets: vec![("Old".to_string(), "New Name".to_string()), ("Last".to_string(), "End".to_string())],
+            columns: vec![("Col".to_string(), "Kol".to_string())],
+        }
+    }
+
+    #[test]
+    fn renames_sheets() {
+        let refs = references();
+        assert_eq!(refs.rename_formula("Old!A1+'Old'!B2"), "'New Name'!A1+'New Name'!B2");
+        assert_eq!(refs.rename_formula("SUM(Old!A1:B2)"), "SUM('New Name'!A1:B2)");
+        assert_eq!(refs.rename_formula("SUM(Old:Last!A1)"), "SUM('New Name:End'!A1)");
+        assert_eq!(refs.rename_formula("SUM('Old:Last'!A1)"), "SUM('New Name:End'!A1)");
+        assert_eq!(refs.rename_formula("Other!A1&\"Old!A1\""), "Other!A1&\"Old!A1\"");
+    }
+
+    #[test]
+    fn keeps_sheets_of_other_workbooks() {
+        let refs = references();
+        assert_eq!(refs.rename_formula("[1]Old!A1"), "[1]Old!A1");
+        assert_eq!(refs.rename_formula("'[1]Old'!A1"), "'[1]Old'!A1");
+        assert_eq!(refs.rename_formula("SUM([1]Old:Last!A1)+Old!A1"), "SUM([1]Old:Last!A1)+'New Name'!A1");
+    }
+
+    #[test]
+    fn renames_table_columns() {
+        let refs = references();
+        assert_eq!(refs.rename_formula("SUM(Table1[Col])"), "SUM(Table1[Kol])");
+        assert_eq!(refs.rename_formula("[@Col]*2"), "[@Kol]*2");
+        assert_eq!(refs.rename_formula("Table1[[#Headers],[Col]]"), "Table1[[#Headers],[Kol]]");
+        assert_eq!(refs.rename_formula("Table1[[#This Row],[Other]]"), "Table1[[#This Row],[Other]]");
+    }
+
+    #[test]
+    fn converts_sheet_names_that_stay_unique() {
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        let workbook = "<workbook><sheets><sheet name=\"u\" r:id=\"rId1\"/><sheet name=\"c\" r:id=\"rId2\"/><sheet name=\"\u{1001}\" r:id=\"rId3\"/></sheets></workbook>";
+        // "c" would become the name of the third sheet.
+        assert_eq!(sheet_renames(workbook.as_bytes(), rules.find("Win Innwa")), [("u".to_string(), "\u{1000}".to_string())]);
+        assert!(sheet_renames(workbook.as_bytes(), None).is_empty());
+    }
+
+    #[test]
+    fn converts_formula_string_literals() {
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        let rule = rules.find("Win Innwa").unwrap();
+        assert_eq!(
+            convert_formula_strings(r#"IF(A1="u",'u'!B1&Table1[u],"c")"#, rule),
+            "IF(A1=\"\u{1000}\",'u'!B1&Table1[u],\"\u{1001}\")"
+        );
+        // Each item of a literal list is converted on its own; other formulas keep their separators.
+        assert_eq!(convert_validation_formula(r#""u,c""#, rule), "\"\u{1000},\u{1001}\"");
+        assert_eq!(convert_validation_formula("$A$1:$A$5", rule), "$A$1:$A$5");
+    }
+
+    #[test]
+    fn validations_follow_the_font_of_their_cells() {
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        let rule = rules.find("Win Innwa");
+        let worksheet = r#"<worksheet><dataValidations count="2"><dataValidation sqref="A1:A5"/><dataValidation sqref="C1"/></dataValidations></worksheet>"#;
+        let mut fonts = ValidationFonts::scan(worksheet.as_bytes());
+        fonts.record("A1", None);
+        fonts.record("A2", rule);
+        assert!(fonts.rule(0, None).is_some());
+        // No cell of the second validation was written, so it takes the default style's font.
+        assert!(fonts.rule(1, None).is_none() && fonts.rule(1, rule).is_some());
+    }
+
+    #[test]
+    fn converts_header_footer_text() {
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        let mapping = rules.find("Win Innwa").unwrap().mapping;
+        assert_eq!(
+            convert_header_footer("&L&\"Win Innwa,Bold\"uGef;&CPage &P&R&\"-,Italic\"u", &rules, None),
+            format!("&L&\"Myanmar Text,Bold\"{}&CPage &P&R&\"-,Italic\"u", mapping.convert("uGef;"))
+        );
+        assert_eq!(convert_header_footer("A&&B&12u", &rules, None), "A&&B&12u");
+        // A doubled ampersand in a source font is a letter, still written doubled.
+        assert_eq!(
+            convert_header_footer("u&&c", &rules, rules.find("Win Innwa")),
+            mapping.convert("u&c").replace('&', "&&")
+        );
+    }
+}
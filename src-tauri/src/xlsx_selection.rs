@@ -0,0 +1,438 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{Read, Seek};
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer};
+use zip::ZipArchive;
+
+use crate::opc;
+use crate::options::SheetSelection;
+use crate::tag_matches;
+use crate::xlsx_styles::{parse_sqref, CellRange, XlsxCellStyles};
+
+const WORKBOOK_PART: &str = "xl/workbook.xml";
+
+/// Cells of one worksheet picked for conversion.
+pub enum SheetCells {
+    All,
+    Ranges(Vec<CellRange>),
+    Nothing,
+}
+
+impl SheetCells {
+    /// Whether the cell at `cell` is picked. A cell without an `r` reference is
+    /// only picked with the whole sheet.
+    pub fn contains(&self, cell: Option<&str>) -> bool {
+        match self {
+            SheetCells::All => true,
+            SheetCells::Ranges(ranges) => cell.is_some_and(|cell| ranges.iter().any(|range| range.contains_cell(cell))),
+            SheetCells::Nothing => false,
+        }
+    }
+
+    pub fn is_all(&self) -> bool {
+        matches!(self, SheetCells::All)
+    }
+}
+
+/// Worksheets and ranges `ConversionOptions::xlsx_selection` limits an XLSX
+/// conversion to, keyed by worksheet part, with the notes and charts those
+/// worksheets own. Without a selection every cell and part is picked.
+#[derive(Default)]
+pub struct XlsxSelection {
+    active: bool,
+    sheets: HashMap<String, SheetCells>,
+    sheet_names: HashSet<String>,
+    parts: HashSet<String>,
+}
+
+impl XlsxSelection {
+    pub fn load<R: Read + Seek>(archive: &mut ZipArchive<R>, selection: &[SheetSelection]) -> Self {
+        if selection.is_empty() {
+            return XlsxSelection::default();
+        }
+
+        let targets: HashMap<String, String> = opc::read_relationships(archive, WORKBOOK_PART)
+            .into_iter()
+            .map(|rel| (rel.id, rel.target))
+            .collect();
+        let workbook = opc::read_part(archive, WORKBOOK_PART).unwrap_or_default();
+
+        let mut picked = XlsxSelection {
+            active: true,
+            ..Default::default()
+        };
+        for (name, id) in workbook_sheets(&workbook) {
+            let picks: Vec<&SheetSelection> = selection
+                .iter()
+                .filter(|pick| pick.sheet.trim().to_lowercase() == name.to_lowercase())
+                .collect();
+            let Some(part) = targets.get(&id) else {
+                continue;
+            };
+            if picks.is_empty() {
+                picked.sheets.insert(part.clone(), SheetCells::Nothing);
+                continue;
+            }
+
+            let cells = if picks.iter().any(|pick| pick.ranges.is_empty()) {
+                SheetCells::All
+            } else {
+                SheetCells::Ranges(picks.iter().flat_map(|pick| pick.ranges.iter()).flat_map(|range| parse_sqref(range)).collect())
+            };
+            picked.sheets.insert(part.clone(), cells);
+            picked.sheet_names.insert(name);
+
+            // Notes and charts of a picked sheet go with it.
+            for rel in opc::read_relationships(archive, part) {
+                match rel.kind() {
+                    "comments" => {
+                        picked.parts.insert(rel.target);
+                    }
+                    "drawing" => {
+                        let charts = opc::read_relationships(archive, &rel.target).into_iter().filter(|rel| rel.kind() == "chart");
+                        picked.parts.extend(charts.map(|rel| rel.target));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for pick in selection {
+            if !picked.sheet_names.iter().any(|name| name.to_lowercase() == pick.sheet.trim().to_lowercase()) {
+                log::warn!("Selected sheet {:?} is not in the workbook", pick.sheet);
+            }
+        }
+        picked
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Cells picked in the worksheet `part`.
+    pub fn cells(&self, part: &str) -> &SheetCells {
+        if !self.active {
+            return &SheetCells::All;
+        }
+        self.sheets.get(part).unwrap_or(&SheetCells::Nothing)
+    }
+
+    /// Whether the sheet called `name` is picked, in whole or in part.
+    pub fn includes_sheet(&self, name: &str) -> bool {
+        !self.active || self.sheet_names.contains(name)
+    }
+
+    /// Whether a notes or chart part belongs to a picked sheet.
+    pub fn includes_part(&self, part: &str) -> bool {
+        !self.active || self.parts.contains(part)
+    }
+}
+
+/// Name and relationship ID of each `sheet` of the workbook.
+pub fn workbook_sheets(workbook: &[u8]) -> Vec<(String, String)> {
+    let mut reader = Reader::from_reader(workbook);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut sheets = Vec::new();
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"sheet" => {
+                let mut name = None;
+                let mut id = None;
+                for attr in e.attributes().flatten() {
+                    let value = attr.unescape_value().unwrap_or_default().to_string();
+                    if attr.key.as_ref() == b"name" {
+                        name = Some(value);
+                    } else if attr.key.local_name().as_ref() == b"id" {
+                        id = Some(value);
+                    }
+                }
+                if let (Some(name), Some(id)) = (name, id) {
+                    sheets.push((name, id));
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    sheets
+}
+
+/// Copies of the cell formats in a source font, appended to styles.xml for cells
+/// outside the selection. The copies point at copies of their fonts, so they keep
+/// the original font when the `fonts` list is converted.
+#[derive(Default)]
+pub struct StyleClones {
+    /// Index of the copy of each `cellXfs` entry.
+    xf_clones: BTreeMap<usize, usize>,
+    /// Index of the copy of each `fonts` entry.
+    font_clones: BTreeMap<usize, usize>,
+    /// XML of the font copies, in index order.
+    fonts: Vec<u8>,
+    /// XML of the cell format copies, in index order.
+    xfs: Vec<u8>,
+}
+
+impl StyleClones {
+    pub fn plan(contents: &[u8], cell_styles: &XlsxCellStyles) -> Self {
+        let mut clones = StyleClones::default();
+        let mut font_ids = BTreeSet::new();
+        for (xf_index, font_id) in cell_styles.xf_font_ids.iter().enumerate() {
+            if cell_styles.rule(xf_index).is_some() {
+                clones.xf_clones.insert(xf_index, cell_styles.xf_font_ids.len() + clones.xf_clones.len());
+                font_ids.insert(*font_id);
+            }
+        }
+        let font_count = count_fonts(contents);
+        clones.font_clones = font_ids.into_iter().enumerate().map(|(n, font_id)| (font_id, font_count + n)).collect();
+        if clones.is_empty() {
+            return clones;
+        }
+
+        let mut reader = Reader::from_reader(contents);
+        reader.trim_text(false);
+        let mut buf = Vec::new();
+        let mut font_sources: BTreeMap<usize, &[u8]> = BTreeMap::new();
+        let mut xf_sources: BTreeMap<usize, &[u8]> = BTreeMap::new();
+        let mut section: Option<Vec<u8>> = None;
+        let mut font_count = 0usize;
+        let mut xf_count = 0usize;
+        let mut element_start = 0usize;
+
+        loop {
+            let event_start = reader.buffer_position();
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    match section.as_deref() {
+                        None if name.as_slice() == b"fonts" || name.as_slice() == b"cellXfs" => section = Some(name),
+                        Some(b"fonts") if tag_matches(&name, b"font") => element_start = event_start,
+                        Some(b"cellXfs") if name.as_slice() == b"xf" => element_start = event_start,
+                        _ => {}
+                    }
+                }
+                Ok(Event::Empty(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    let source = &contents[event_start..reader.buffer_position()];
+                    match section.as_deref() {
+                        Some(b"fonts") if tag_matches(&name, b"font") => {
+                            font_sources.insert(font_count, source);
+                            font_count += 1;
+                        }
+                        Some(b"cellXfs") if name.as_slice() == b"xf" => {
+                            xf_sources.insert(xf_count, source);
+                            xf_count += 1;
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(Event::End(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    let source = &contents[element_start..reader.buffer_position()];
+                    match section.as_deref() {
+                        Some(section_name) if section_name == name.as_slice() => section = None,
+                        Some(b"fonts") if tag_matches(&name, b"font") => {
+                            font_sources.insert(font_count, source);
+                            font_count += 1;
+                        }
+                        Some(b"cellXfs") if name.as_slice() == b"xf" => {
+                            xf_sources.insert(xf_count, source);
+                            xf_count += 1;
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(Event::Eof) => break,
+                Err(_) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        for font_id in clones.font_clones.keys() {
+            if let Some(source) = font_sources.get(font_id) {
+                clones.fonts.extend(clone_element(source, |_| None, true));
+            }
+        }
+        for (xf_index, font_id) in cell_styles.xf_font_ids.iter().enumerate() {
+            let (Some(source), Some(clone_font)) = (xf_sources.get(&xf_index), clones.font_clones.get(font_id)) else {
+                continue;
+            };
+            if !clones.xf_clones.contains_key(&xf_index) {
+                continue;
+            }
+            let clone_font = clone_font.to_string();
+            clones.xfs.extend(clone_element(
+                source,
+                |elem| {
+                    let mut new_elem = BytesStart::new("xf");
+                    new_elem.extend_attributes(
+                        elem.attributes()
+                            .flatten()
+                            .filter(|attr| !matches!(attr.key.as_ref(), b"fontId" | b"applyFont")),
+                    );
+                    new_elem.push_attribute(("fontId", clone_font.as_str()));
+                    new_elem.push_attribute(("applyFont", "1"));
+                    Some(new_elem)
+                },
+                false,
+            ));
+        }
+        clones
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.xf_clones.is_empty()
+    }
+
+    /// Index of the copy of cell format `style_idx`, for a cell outside the selection.
+    pub fn style(&self, style_idx: usize) -> Option<usize> {
+        self.xf_clones.get(&style_idx).copied()
+    }
+
+    /// Copy of `elem` with its style attribute `key` (`s` on cells and rows,
+    /// `style` on columns) moved to the copy of the cell format it names; cells
+    /// without the attribute use format 0. `None` when that format has no copy.
+    pub fn restyle(&self, elem: &BytesStart, key: &[u8]) -> Option<BytesStart<'static>> {
+        let style = elem
+            .attributes()
+            .flatten()
+            .find(|attr| attr.key.as_ref() == key)
+            .map(|attr| attr.unescape_value().unwrap_or_default().to_string());
+        let style_idx = match &style {
+            Some(style) => style.parse::<usize>().ok()?,
+            None if key == b"s" && elem.name().as_ref() == b"c" => 0,
+            None => return None,
+        };
+        let clone = self.style(style_idx)?.to_string();
+
+        let mut new_elem = BytesStart::new(String::from_utf8_lossy(elem.name().as_ref()).to_string());
+        for attr in elem.attributes().flatten() {
+            if attr.key.as_ref() == key {
+                new_elem.push_attribute((key, clone.as_bytes()));
+            } else {
+                new_elem.push_attribute(attr);
+            }
+        }
+        if style.is_none() {
+            new_elem.push_attribute((key, clone.as_bytes()));
+        }
+        Some(new_elem)
+    }
+
+    pub fn font_count(&self) -> usize {
+        self.font_clones.len()
+    }
+
+    pub fn xf_count(&self) -> usize {
+        self.xf_clones.len()
+    }
+
+    /// Writes the font copies, at the end of the `fonts` list.
+    pub fn write_fonts(&self, writer: &mut Writer<Vec<u8>>) {
+        writer.get_mut().extend_from_slice(&self.fonts);
+    }
+
+    /// Writes the cell format copies, at the end of the `cellXfs` list.
+    pub fn write_xfs(&self, writer: &mut Writer<Vec<u8>>) {
+        writer.get_mut().extend_from_slice(&self.xfs);
+    }
+}
+
+fn count_fonts(contents: &[u8]) -> usize {
+    let mut reader = Reader::from_reader(contents);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut in_fonts = false;
+    let mut count = 0;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"fonts" => in_fonts = true,
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if in_fonts && tag_matches(e.name().as_ref(), b"font") => count += 1,
+            Ok(Event::End(e)) if e.name().as_ref() == b"fonts" => break,
+            Ok(Event::Eof) => break,
+            Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    count
+}
+
+/// Copies an element's XML, passing its own tag through `rewrite`. With
+/// `drop_scheme`, a font loses its `scheme` so it no longer follows the theme
+/// fonts, which are converted.
+fn clone_element(source: &[u8], rewrite: impl Fn(&BytesStart) -> Option<BytesStart<'static>>, drop_scheme: bool) -> Vec<u8> {
+    let mut reader = Reader::from_reader(source);
+    reader.trim_text(false);
+    let mut writer = Writer::new(Vec::with_capacity(source.len()));
+    let mut buf = Vec::new();
+    let mut depth = 0usize;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let elem = e.into_owned();
+                let new_elem = if depth == 0 { rewrite(&elem).unwrap_or(elem) } else { elem };
+                depth += 1;
+                writer.write_event(Event::Start(new_elem)).ok();
+            }
+            Ok(Event::Empty(e)) => {
+                let elem = e.into_owned();
+                if drop_scheme && tag_matches(elem.name().as_ref(), b"scheme") {
+                    buf.clear();
+                    continue;
+                }
+                let new_elem = if depth == 0 { rewrite(&elem).unwrap_or(elem) } else { elem };
+                writer.write_event(Event::Empty(new_elem)).ok();
+            }
+            Ok(Event::End(e)) => {
+                depth = depth.saturating_sub(1);
+                writer.write_event(Event::End(e.into_owned())).ok();
+            }
+            Ok(Event::Eof) => break,
+            Ok(e) => {
+                writer.write_event(e.into_owned()).ok();
+            }
+            Err(_) => break,
+        }
+        buf.clear();
+    }
+
+    writer.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font_rules::FontRules;
+
+    #[test]
+    fn cells_outside_the_selection_get_copies_of_their_styles() {
+        let rules = FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap();
+        let styles = r#"<styleSheet><fonts count="2"><font><name val="Arial"/></font><font><b/><name val="Win Innwa"/><scheme val="minor"/></font></fonts><cellXfs count="3"><xf fontId="1"/><xf fontId="0"/><xf fontId="1" applyFont="0"><alignment wrapText="1"/></xf></cellXfs></styleSheet>"#;
+        let cell_styles = XlsxCellStyles::parse(styles.as_bytes(), &rules, &Default::default());
+        let clones = StyleClones::plan(styles.as_bytes(), &cell_styles);
+
+        assert_eq!((clones.font_count(), clones.xf_count()), (1, 2));
+        assert_eq!(String::from_utf8_lossy(&clones.fonts), "<font><b/><name val=\"Win Innwa\"/></font>");
+        assert_eq!(
+            String::from_utf8_lossy(&clones.xfs),
+            "<xf fontId=\"2\" applyFont=\"1\"/><xf fontId=\"2\" applyFont=\"1\"><alignment wrapText=\"1\"/></xf>"
+        );
+        assert_eq!((clones.style(0), clones.style(1), clones.style(2)), (Some(3), None, Some(4)));
+
+        // A cell without a style uses format 0; columns without one are left alone.
+        let restyled = clones.restyle(&BytesStart::from_content(r#"c r="A1" t="s""#, 1), b"s").unwrap();
+        assert_eq!(String::from_utf8_lossy(&restyled), r#"c r="A1" t="s" s="3""#);
+        assert!(clones.restyle(&BytesStart::from_content(r#"c r="A1" s="1""#, 1), b"s").is_none());
+        assert!(clones.restyle(&BytesStart::from_content(r#"col min="1""#, 3), b"style").is_none());
+    }
+}
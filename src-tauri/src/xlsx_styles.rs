@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use quick_xml::events::{BytesStart, Event};
+
+use crate::docx_styles::ThemeFonts;
+use crate::font_rules::{FontRule, FontRules};
+use crate::{is_val_attr, tag_matches};
+
+/// Largest column and row numbers of a worksheet, for whole-row and whole-column
+/// ranges.
+const MAX_COLUMN: u32 = 16_384;
+const MAX_ROW: u32 = 1_048_576;
+
+fn attr_value(elem: &BytesStart, key: &[u8]) -> Option<String> {
+    elem.attributes()
+        .flatten()
+        .find(|attr| attr.key.as_ref() == key)
+        .map(|attr| attr.unescape_value().unwrap_or_default().to_string())
+}
+
+fn is_off(value: &str) -> bool {
+    matches!(value, "0" | "false")
+}
+
+/// Name and theme scheme of one `font` entry.
+#[derive(Default)]
+struct FontEntry {
+    name: Option<String>,
+    scheme: Option<String>,
+}
+
+impl FontEntry {
+    /// The font Excel draws with: a `scheme` font follows the theme, whatever its
+    /// `name` says.
+    fn effective_name<'a>(&'a self, theme: &'a ThemeFonts) -> Option<&'a str> {
+        let theme_font = match self.scheme.as_deref() {
+            Some("minor") => theme.font("minorAscii"),
+            Some("major") => theme.font("majorAscii"),
+            _ => None,
+        };
+        theme_font.or(self.name.as_deref())
+    }
+}
+
+/// `fontId`, `xfId` and `applyFont` of one `xf` entry.
+#[derive(Default)]
+struct XfEntry {
+    font_id: usize,
+    xf_id: Option<usize>,
+    apply_font: Option<String>,
+}
+
+fn parse_xf(elem: &BytesStart) -> XfEntry {
+    XfEntry {
+        font_id: attr_value(elem, b"fontId").and_then(|id| id.parse().ok()).unwrap_or(0),
+        xf_id: attr_value(elem, b"xfId").and_then(|id| id.parse().ok()),
+        apply_font: attr_value(elem, b"applyFont"),
+    }
+}
+
+/// Font of each cell style in styles.xml, as far as conversion cares.
+#[derive(Default)]
+pub struct XlsxCellStyles<'r> {
+    /// Rule of each `fonts` entry whose effective font is a source font.
+    pub source_font_ids: HashMap<usize, &'r FontRule>,
+    /// Effective `fontId` of each `cellXfs` entry.
+    pub xf_font_ids: Vec<usize>,
+    /// Rule of each `dxfs` entry whose font is a source font.
+    pub dxf_rules: HashMap<usize, &'r FontRule>,
+}
+
+impl<'r> XlsxCellStyles<'r> {
+    /// Resolves the font of every cell format. A format takes its font from the cell
+    /// style it is based on (`xfId` into `cellStyleXfs`) when `applyFont` turns its
+    /// own font off, and a font with a `scheme` uses the theme's major or minor font.
+    pub fn parse(contents: &[u8], rules: &'r FontRules, theme: &ThemeFonts) -> Self {
+        use quick_xml::Reader;
+
+        let mut reader = Reader::from_reader(contents);
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+
+        let mut fonts: Vec<FontEntry> = Vec::new();
+        let mut style_xfs: Vec<XfEntry> = Vec::new();
+        let mut cell_xfs: Vec<XfEntry> = Vec::new();
+        let mut dxf_fonts: Vec<Option<String>> = Vec::new();
+        let mut section: Option<Vec<u8>> = None;
+        let mut in_font = false;
+
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    match (section.as_deref(), name.as_slice()) {
+                        (None, b"fonts" | b"cellStyleXfs" | b"cellXfs" | b"dxfs") => section = Some(name),
+                        (Some(b"fonts"), _) if tag_matches(&name, b"font") => {
+                            fonts.push(FontEntry::default());
+                            in_font = true;
+                        }
+                        (Some(b"dxfs"), b"dxf") => dxf_fonts.push(None),
+                        (Some(b"dxfs"), _) if tag_matches(&name, b"font") => in_font = true,
+                        (Some(b"cellStyleXfs"), b"xf") => style_xfs.push(parse_xf(&e)),
+                        (Some(b"cellXfs"), b"xf") => cell_xfs.push(parse_xf(&e)),
+                        _ => {}
+                    }
+                }
+                Ok(Event::Empty(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    match (section.as_deref(), name.as_slice()) {
+                        (Some(b"fonts"), _) if tag_matches(&name, b"font") => fonts.push(FontEntry::default()),
+                        (Some(b"dxfs"), b"dxf") => dxf_fonts.push(None),
+                        (Some(b"cellStyleXfs"), b"xf") => style_xfs.push(parse_xf(&e)),
+                        (Some(b"cellXfs"), b"xf") => cell_xfs.push(parse_xf(&e)),
+                        (Some(section), _) if in_font => {
+                            let value = e
+                                .attributes()
+                                .flatten()
+                                .find(|attr| is_val_attr(attr.key.as_ref()))
+                                .map(|attr| attr.unescape_value().unwrap_or_default().to_string());
+                            let is_name = tag_matches(&name, b"name") || tag_matches(&name, b"rFont");
+                            if section == b"fonts" {
+                                if let Some(font) = fonts.last_mut() {
+                                    if is_name {
+                                        font.name = value;
+                                    } else if tag_matches(&name, b"scheme") {
+                                        font.scheme = value;
+                                    }
+                                }
+                            } else if is_name {
+                                if let Some(font) = dxf_fonts.last_mut() {
+                                    *font = value;
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(Event::End(e)) => {
+                    let name = e.name().as_ref().to_vec();
+                    if section.as_deref() == Some(name.as_slice()) {
+                        section = None;
+                    } else if tag_matches(&name, b"font") {
+                        in_font = false;
+                    }
+                }
+                Ok(Event::Eof) => break,
+                Err(_) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        let source_font_ids = fonts
+            .iter()
+            .enumerate()
+            .filter_map(|(font_id, font)| Some((font_id, rules.find(font.effective_name(theme)?)?)))
+            .collect();
+        let xf_font_ids = cell_xfs
+            .iter()
+            .map(|xf| {
+                let style_font = xf.xf_id.and_then(|xf_id| style_xfs.get(xf_id)).map(|style| style.font_id);
+                match style_font {
+                    Some(font_id) if xf.apply_font.as_deref().is_some_and(is_off) => font_id,
+                    _ => xf.font_id,
+                }
+            })
+            .collect();
+        let dxf_rules = dxf_fonts
+            .iter()
+            .enumerate()
+            .filter_map(|(dxf_id, font)| Some((dxf_id, rules.find(font.as_deref()?)?)))
+            .collect();
+
+        XlsxCellStyles {
+            source_font_ids,
+            xf_font_ids,
+            dxf_rules,
+        }
+    }
+
+    /// Rule for cells with style index `style_idx`, when its font is a source font.
+    pub fn rule(&self, style_idx: usize) -> Option<&'r FontRule> {
+        self.xf_font_ids
+            .get(style_idx)
+            .and_then(|font_id| self.source_font_ids.get(font_id))
+            .copied()
+    }
+
+    /// Rule of the workbook's default cell style, which also sets the font of text
+    /// without a style of its own, such as print headers.
+    pub fn default_rule(&self) -> Option<&'r FontRule> {
+        self.rule(0)
+    }
+}
+
+/// One rectangle of an `sqref` list, in 1-based column and row numbers.
+pub struct CellRange {
+    first: (u32, u32),
+    last: (u32, u32),
+}
+
+impl CellRange {
+    fn contains(&self, (column, row): (u32, u32)) -> bool {
+        (self.first.0..=self.last.0).contains(&column) && (self.first.1..=self.last.1).contains(&row)
+    }
+
+    /// Whether the A1 reference `cell` lies in the range.
+    pub fn contains_cell(&self, cell: &str) -> bool {
+        match parse_cell_ref(cell) {
+            (Some(column), Some(row)) => self.contains((column, row)),
+            _ => false,
+        }
+    }
+}
+
+/// Ranges of a space-separated `sqref` list such as `A1:B5 D:D 3:3`.
+pub fn parse_sqref(sqref: &str) -> Vec<CellRange> {
+    sqref.split_whitespace().filter_map(parse_range).collect()
+}
+
+/// Column and row of an A1 reference; either part may be missing in whole-row
+/// and whole-column ranges. `$` markers are ignored.
+fn parse_cell_ref(reference: &str) -> (Option<u32>, Option<u32>) {
+    let reference = reference.replace('$', "");
+    let split = reference.find(|ch: char| !ch.is_ascii_alphabetic()).unwrap_or(reference.len());
+    let (letters, digits) = reference.split_at(split);
+    let column = (!letters.is_empty()).then(|| {
+        letters
+            .chars()
+            .fold(0u32, |column, ch| column * 26 + (ch.to_ascii_uppercase() as u32 - 'A' as u32 + 1))
+    });
+    (column, digits.parse().ok())
+}
+
+/// Column and row of a single-cell A1 reference such as `$B$3`.
+pub fn cell_position(reference: &str) -> Option<(u32, u32)> {
+    match parse_cell_ref(reference) {
+        (Some(column), Some(row)) => Some((column, row)),
+        _ => None,
+    }
+}
+
+fn parse_range(range: &str) -> Option<CellRange> {
+    let (first, last) = range.split_once(':').unwrap_or((range, range));
+    let (first_column, first_row) = parse_cell_ref(first);
+    let (last_column, last_row) = parse_cell_ref(last);
+    if first_column.is_none() && first_row.is_none() {
+        return None;
+    }
+    Some(CellRange {
+        first: (first_column.unwrap_or(1), first_row.unwrap_or(1)),
+        last: (last_column.unwrap_or(MAX_COLUMN), last_row.unwrap_or(MAX_ROW)),
+    })
+}
+
+/// Cells of a worksheet that conditional formatting can show in a source font
+/// through a differential format.
+#[derive(Default)]
+pub struct ConditionalFonts<'r> {
+    ranges: Vec<(CellRange, &'r FontRule)>,
+}
+
+impl<'r> ConditionalFonts<'r> {
+    /// Reads the `conditionalFormatting` ranges whose rules apply a `dxfs` entry with
+    /// a source font. They follow `sheetData`, so this is a pass of its own.
+    pub fn scan<R: BufRead>(source: R, cell_styles: &XlsxCellStyles<'r>) -> Self {
+        use quick_xml::Reader;
+
+        let mut fonts = ConditionalFonts::default();
+        if cell_styles.dxf_rules.is_empty() {
+            return fonts;
+        }
+
+        let mut reader = Reader::from_reader(source);
+        reader.trim_text(true);
+        let mut buf = Vec::new();
+        let mut sqref: Option<String> = None;
+
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(e)) if e.name().as_ref() == b"conditionalFormatting" => {
+                    sqref = attr_value(&e, b"sqref");
+                }
+                Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"cfRule" => {
+                    let rule = attr_value(&e, b"dxfId")
+                        .and_then(|id| id.parse::<usize>().ok())
+                        .and_then(|dxf_id| cell_styles.dxf_rules.get(&dxf_id));
+                    if let (Some(rule), Some(sqref)) = (rule, sqref.as_deref()) {
+                        fonts.ranges.extend(parse_sqref(sqref).into_iter().map(|range| (range, *rule)));
+                    }
+                }
+                Ok(Event::End(e)) if e.name().as_ref() == b"conditionalFormatting" => sqref = None,
+                Ok(Event::Eof) => break,
+                Err(_) => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+
+        fonts
+    }
+
+    /// Rule of the first conditional format with a source font covering `cell`.
+    pub fn rule_at(&self, cell: &str) -> Option<&'r FontRule> {
+        if self.ranges.is_empty() {
+            return None;
+        }
+        let (Some(column), Some(row)) = parse_cell_ref(cell) else {
+            return None;
+        };
+        self.ranges
+            .iter()
+            .find(|(range, _)| range.contains((column, row)))
+            .map(|(_, rule)| *rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THEME: &str = r#"<a:theme><a:themeElements><a:fontScheme><a:majorFont><a:latin typeface="Calibri Light"/></a:majorFont><a:minorFont><a:latin typeface="Win Innwa"/></a:minorFont></a:fontScheme></a:themeElements></a:theme>"#;
+
+    const STYLES: &str = r#"<styleSheet><fonts count="4"><font><name val="Arial"/></font><font><name val="Win Innwa"/></font><font><name val="Arial"/><scheme val="minor"/></font><font><name val="Win Innwa"/><scheme val="major"/></font></fonts><cellStyleXfs count="2"><xf fontId="1"/><xf fontId="0"/></cellStyleXfs><cellXfs count="5"><xf fontId="0" xfId="0" applyFont="0"/><xf fontId="1" xfId="1" applyFont="1"/><xf fontId="0" xfId="0"/><xf fontId="2" xfId="1"/><xf fontId="3" xfId="0"/></cellXfs><dxfs count="2"><dxf><font><name val="Arial"/></font></dxf><dxf><font><b/><name val="Win Innwa"/></font></dxf></dxfs></styleSheet>"#;
+
+    fn rules() -> FontRules {
+        FontRules::new(serde_json::from_str(r#"[{"sourceFont":"Win Innwa"}]"#).unwrap(), &Default::default()).unwrap()
+    }
+
+    #[test]
+    fn cell_formats_resolve_their_effective_font() {
+        let rules = rules();
+        let cell_styles = XlsxCellStyles::parse(STYLES.as_bytes(), &rules, &ThemeFonts::parse(THEME.as_bytes()));
+        // Format 0 turns its own font off and takes the cell style's; format 2 keeps its own.
+        assert_eq!(cell_styles.xf_font_ids, [1, 1, 0, 2, 3]);
+        // Scheme fonts follow the theme, whatever their name says.
+        let converted: Vec<bool> = (0..5).map(|xf| cell_styles.rule(xf).is_some()).collect();
+        assert_eq!(converted, [true, true, false, true, false]);
+        assert!(cell_styles.default_rule().is_some());
+        assert_eq!(cell_styles.dxf_rules.keys().collect::<Vec<_>>(), [&1]);
+    }
+
+    #[test]
+    fn conditional_formats_with_source_fonts_cover_their_ranges() {
+        let rules = rules();
+        let cell_styles = XlsxCellStyles::parse(STYLES.as_bytes(), &rules, &ThemeFonts::default());
+        let worksheet = r#"<worksheet><sheetData/><conditionalFormatting sqref="A1:B2 D:D"><cfRule type="expression" dxfId="1"/></conditionalFormatting><conditionalFormatting sqref="C1"><cfRule type="expression" dxfId="0"/></conditionalFormatting></worksheet>"#;
+        let fonts = ConditionalFonts::scan(worksheet.as_bytes(), &cell_styles);
+        let covered: Vec<bool> = ["A1", "B2", "C1", "C3", "D9"].iter().map(|cell| fonts.rule_at(cell).is_some()).collect();
+        assert_eq!(covered, [true, true, false, false, true]);
+    }
+
+    #[test]
+    fn sqref_lists_cover_cells_rows_and_columns() {
+        let ranges = parse_sqref("$A$1:B2 3:3 ZZ:ZZ");
+        assert_eq!(ranges.len(), 3);
+        assert!(ranges[0].contains_cell("B1") && !ranges[0].contains_cell("C1"));
+        assert!(ranges[1].contains_cell("XFD3") && !ranges[1].contains_cell("A4"));
+        assert!(ranges[2].contains_cell("ZZ1048576") && !ranges[2].contains_cell("ZY1"));
+        assert_eq!(cell_position("$AB$12"), Some((28, 12)));
+        assert_eq!(cell_position("AB"), None);
+    }
+}
@@ -5,6 +5,7 @@ import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import "./App.css";
 
 const SUPPORTED_EXTENSIONS = ["txt", "docx", "xlsx", "pptx"];
+const UNICODE_FONTS = ["Myanmar Text", "Pyidaungsu", "Noto Sans Myanmar", "Padauk"];
 
 interface ConversionProgress {
   current: number;
@@ -13,9 +14,74 @@ interface ConversionProgress {
   message: string;
 }
 
+interface FontUsage {
+  name: string;
+  count: number;
+}
+
+interface TargetFont {
+  ascii: string;
+  complexScript?: string | null;
+  eastAsian?: string | null;
+}
+
+interface FontPattern {
+  kind: "glob" | "regex";
+  value: string;
+}
+
+interface FontRule {
+  sourceFont: string;
+  mapping: "win_innwa";
+  patterns?: FontPattern[];
+}
+
+interface ScalingRule {
+  sourceFont: string;
+  targetFont?: string;
+  sizeFactor: number;
+  lineSpacingFactor?: number | null;
+}
+
+interface SheetSelection {
+  sheet: string;
+  ranges: string[];
+}
+
+interface MatchedFont {
+  font: string;
+  rule: string;
+  matchedBy: "exact" | "normalized" | "alias" | "pattern";
+}
+
+interface ContainerUsage {
+  name: string;
+  count: number;
+}
+
+interface ConversionReport {
+  matchedFonts: MatchedFont[];
+  containers: ContainerUsage[];
+  flaggedRuns: number;
+  numericCells: number;
+}
+
 function App() {
-  const [sourceFont, setSourceFont] = useState("Win Innwa");
+  const [sourceFonts, setSourceFonts] = useState<string[]>(["Win Innwa"]);
   const [selectedFile, setSelectedFile] = useState<string | null>(null);
+  const [documentFonts, setDocumentFonts] = useState<FontUsage[]>([]);
+  const [targetFont, setTargetFont] = useState<TargetFont>({ ascii: "Myanmar Text" });
+  const [sizeScale, setSizeScale] = useState(100);
+  const [lineSpacingScale, setLineSpacingScale] = useState(100);
+  const [removeEmbeddedFonts, setRemoveEmbeddedFonts] = useState(false);
+  const [setRunLanguage, setSetRunLanguage] = useState(false);
+  const [trackRevisions, setTrackRevisions] = useState(false);
+  const [revisionAuthor, setRevisionAuthor] = useState("");
+  const [flagUncertain, setFlagUncertain] = useState(false);
+  const [myanmarNumbering, setMyanmarNumbering] = useState(false);
+  const [keepMyanmarDigits, setKeepMyanmarDigits] = useState(false);
+  const [digitsAsText, setDigitsAsText] = useState(false);
+  const [xlsxSelection, setXlsxSelection] = useState("");
   const [status, setStatus] = useState<string | null>(null);
   const [busy, setBusy] = useState(false);
   const [progress, setProgress] = useState<ConversionProgress | null>(null);
@@ -50,6 +116,13 @@ function App() {
     };
   }, []);
 
+  // Load the saved default target font
+  useEffect(() => {
+    invoke<TargetFont>("get_default_target_font")
+      .then(setTargetFont)
+      .catch((err) => console.error("Failed to load default target font", err));
+  }, []);
+
   // Reset progress when not busy
   useEffect(() => {
     if (!busy) {
@@ -57,6 +130,29 @@ function App() {
     }
   }, [busy]);
 
+  function updateSourceFont(index: number, value: string) {
+    setSourceFonts((fonts) => fonts.map((font, i) => (i === index ? value : font)));
+  }
+
+  function removeSourceFont(index: number) {
+    setSourceFonts((fonts) => fonts.filter((_, i) => i !== index));
+  }
+
+  function addSourceFont(value = "") {
+    setSourceFonts((fonts) =>
+      value && fonts.includes(value) ? fonts : [...fonts, value],
+    );
+  }
+
+  async function saveDefaultTargetFont() {
+    try {
+      await invoke("set_default_target_font", { targetFont });
+      setStatus(`Default target font set to ${targetFont.ascii}.`);
+    } catch (err) {
+      setStatus(err instanceof Error ? err.message : String(err));
+    }
+  }
+
   async function pickFile() {
     setStatus(null);
     const result = await open({
@@ -71,6 +167,15 @@ function App() {
 
     if (typeof result === "string") {
       setSelectedFile(result);
+      setDocumentFonts([]);
+      try {
+        const fonts = await invoke<FontUsage[]>("list_document_fonts", {
+          sourcePath: result,
+        });
+        setDocumentFonts(fonts);
+      } catch (err) {
+        console.error("Failed to list document fonts", err);
+      }
     }
   }
 
@@ -102,13 +207,78 @@ function App() {
 
       setProgress({ current: 0, total: 1, percentage: 0, message: "Starting conversion..." });
 
-      await invoke("convert_file", {
+      const rules: FontRule[] = sourceFonts
+        .map((font) => font.trim())
+        .filter((font) => font.length > 0)
+        .map((font) =>
+          /[*?]/.test(font)
+            ? { sourceFont: font, mapping: "win_innwa", patterns: [{ kind: "glob", value: font }] }
+            : { sourceFont: font, mapping: "win_innwa" },
+        );
+
+      const scaling: ScalingRule[] =
+        sizeScale === 100 && lineSpacingScale === 100
+          ? []
+          : rules.map((rule) => ({
+              sourceFont: rule.sourceFont,
+              sizeFactor: sizeScale / 100,
+              lineSpacingFactor: lineSpacingScale === 100 ? null : lineSpacingScale / 100,
+            }));
+
+      // "Sheet1!A1:C20 E:E; Sheet2" -> one entry per sheet; no ranges means the whole sheet.
+      const selection: SheetSelection[] = xlsxSelection
+        .split(/[;\n]/)
+        .map((entry) => entry.trim())
+        .filter((entry) => entry.length > 0)
+        .map((entry) => {
+          const bang = entry.lastIndexOf("!");
+          const sheet = (bang < 0 ? entry : entry.slice(0, bang)).trim().replace(/^'(.*)'$/, "$1");
+          const ranges = bang < 0 ? [] : entry.slice(bang + 1).split(/[\s,]+/).filter((range) => range.length > 0);
+          return { sheet, ranges };
+        });
+
+      const report = await invoke<ConversionReport>("convert_file", {
         sourcePath: selectedFile,
         targetPath: target,
-        sourceFont,
+        rules,
+        targetFont: {
+          ascii: targetFont.ascii.trim() || "Myanmar Text",
+          complexScript: targetFont.complexScript?.trim() || null,
+          eastAsian: targetFont.eastAsian?.trim() || null,
+        },
+        options: {
+          scaling,
+          removeEmbeddedFonts,
+          setRunLanguage,
+          trackedRevisions: trackRevisions
+            ? {
+                author: revisionAuthor.trim() || "win2unicode",
+                date: new Date().toISOString().replace(/\.\d{3}Z$/, "Z"),
+              }
+            : null,
+          flagUncertain,
+          myanmarNumbering,
+          myanmarDigits: keepMyanmarDigits ? (digitsAsText ? "text" : "numberFormat") : null,
+          xlsxSelection: selection,
+        },
       });
 
-      setStatus("Conversion completed successfully!");
+      const matched = report.matchedFonts
+        .map((m) => (m.font === m.rule ? m.font : `${m.font} (${m.rule}, ${m.matchedBy})`))
+        .join(", ");
+      const containers = report.containers.map((c) => `${c.name} (${c.count})`).join(", ");
+      const numericCells = report.numericCells
+        ? keepMyanmarDigits
+          ? `. Kept Myanmar digits in ${report.numericCells} numeric cells`
+          : `. ${report.numericCells} numeric cells now show Latin digits`
+        : "";
+      setStatus(
+        matched || ext === "txt"
+          ? `Conversion completed successfully!${matched ? ` Converted fonts: ${matched}` : ""}${
+              containers ? `. Converted text found in: ${containers}` : ""
+            }${report.flaggedRuns ? `. Highlighted ${report.flaggedRuns} uncertain conversions for review` : ""}${numericCells}`
+          : "Conversion completed, but no text used the selected fonts.",
+      );
       setProgress({ current: 1, total: 1, percentage: 100, message: "Done!" });
     } catch (err) {
       setStatus(err instanceof Error ? err.message : "Conversion failed.");
@@ -174,14 +344,191 @@ function App() {
 
       {page === "file" ? (
         <section className="panel">
-          <label className="field">
-            <span>Font Family To Replace</span>
+          <div className="field">
+            <span>Font Families To Replace</span>
+            {sourceFonts.map((font, index) => (
+              <div className="font-row" key={index}>
+                <input
+                  value={font}
+                  onChange={(e) => updateSourceFont(index, e.currentTarget.value)}
+                  placeholder="Win Innwa"
+                  list="document-fonts"
+                />
+                <button
+                  type="button"
+                  className="ghost"
+                  onClick={() => removeSourceFont(index)}
+                  disabled={sourceFonts.length === 1}
+                >
+                  Remove
+                </button>
+              </div>
+            ))}
+            <p className="hint">
+              Names ignore case and spaces. Use * or ? to match several fonts, e.g. Win*.
+            </p>
+            <datalist id="document-fonts">
+              {documentFonts.map((font) => (
+                <option key={font.name} value={font.name}>
+                  {`${font.count} uses`}
+                </option>
+              ))}
+            </datalist>
+            <div className="font-chips">
+              <button type="button" className="ghost" onClick={() => addSourceFont()}>
+                Add Font
+              </button>
+              {documentFonts
+                .filter((font) => !sourceFonts.includes(font.name))
+                .map((font) => (
+                  <button
+                    type="button"
+                    className="chip"
+                    key={font.name}
+                    onClick={() => addSourceFont(font.name)}
+                  >
+                    {font.name} ({font.count})
+                  </button>
+                ))}
+            </div>
+          </div>
+
+          <div className="field">
+            <span>Unicode Font</span>
+            <div className="font-row">
+              <input
+                value={targetFont.ascii}
+                onChange={(e) => setTargetFont({ ...targetFont, ascii: e.currentTarget.value })}
+                placeholder="Myanmar Text"
+                list="unicode-fonts"
+              />
+              <button type="button" className="ghost" onClick={saveDefaultTargetFont}>
+                Save as Default
+              </button>
+            </div>
+            <div className="font-row">
+              <input
+                value={targetFont.complexScript ?? ""}
+                onChange={(e) =>
+                  setTargetFont({ ...targetFont, complexScript: e.currentTarget.value })
+                }
+                placeholder="Complex script font (optional)"
+                list="unicode-fonts"
+              />
+              <input
+                value={targetFont.eastAsian ?? ""}
+                onChange={(e) => setTargetFont({ ...targetFont, eastAsian: e.currentTarget.value })}
+                placeholder="East Asian font (optional)"
+                list="unicode-fonts"
+              />
+            </div>
+            <datalist id="unicode-fonts">
+              {UNICODE_FONTS.map((font) => (
+                <option key={font} value={font} />
+              ))}
+            </datalist>
+          </div>
+
+          <div className="field">
+            <span>Size Compensation</span>
+            <div className="font-row">
+              <label className="inline-field">
+                Font size %
+                <input
+                  type="number"
+                  min={10}
+                  max={400}
+                  value={sizeScale}
+                  onChange={(e) => setSizeScale(Number(e.currentTarget.value) || 100)}
+                />
+              </label>
+              <label className="inline-field">
+                Line spacing %
+                <input
+                  type="number"
+                  min={10}
+                  max={400}
+                  value={lineSpacingScale}
+                  onChange={(e) => setLineSpacingScale(Number(e.currentTarget.value) || 100)}
+                />
+              </label>
+            </div>
+            <p className="hint">Applied to converted text only. 100% leaves sizes unchanged.</p>
+          </div>
+
+          <div className="field">
+            <span>Document Options</span>
+            <label className="checkbox-field">
+              <input
+                type="checkbox"
+                checked={removeEmbeddedFonts}
+                onChange={(e) => setRemoveEmbeddedFonts(e.currentTarget.checked)}
+              />
+              Remove embedded source fonts (docx)
+            </label>
+            <label className="checkbox-field">
+              <input
+                type="checkbox"
+                checked={setRunLanguage}
+                onChange={(e) => setSetRunLanguage(e.currentTarget.checked)}
+              />
+              Set Myanmar language on converted runs (docx)
+            </label>
+            <label className="checkbox-field">
+              <input
+                type="checkbox"
+                checked={myanmarNumbering}
+                onChange={(e) => setMyanmarNumbering(e.currentTarget.checked)}
+              />
+              Number converted lists with Myanmar digits (docx)
+            </label>
+            <label className="checkbox-field">
+              <input
+                type="checkbox"
+                checked={trackRevisions}
+                onChange={(e) => setTrackRevisions(e.currentTarget.checked)}
+              />
+              Write conversions as tracked changes (docx)
+            </label>
+            {trackRevisions && (
+              <input
+                value={revisionAuthor}
+                onChange={(e) => setRevisionAuthor(e.currentTarget.value)}
+                placeholder="Revision author"
+              />
+            )}
+            <label className="checkbox-field">
+              <input
+                type="checkbox"
+                checked={flagUncertain}
+                onChange={(e) => setFlagUncertain(e.currentTarget.checked)}
+              />
+              Highlight uncertain conversions (docx, pptx)
+            </label>
+            <label className="checkbox-field">
+              <input
+                type="checkbox"
+                checked={keepMyanmarDigits}
+                onChange={(e) => setKeepMyanmarDigits(e.currentTarget.checked)}
+              />
+              Keep Myanmar digits in numeric cells (xlsx)
+            </label>
+            {keepMyanmarDigits && (
+              <label className="checkbox-field">
+                <input
+                  type="checkbox"
+                  checked={digitsAsText}
+                  onChange={(e) => setDigitsAsText(e.currentTarget.checked)}
+                />
+                Store the numbers as text instead of a number format
+              </label>
+            )}
             <input
-              value={sourceFont}
-              onChange={(e) => setSourceFont(e.currentTarget.value)}
-              placeholder="Win Innwa"
+              value={xlsxSelection}
+              onChange={(e) => setXlsxSelection(e.currentTarget.value)}
+              placeholder="Only convert sheets/ranges (xlsx), e.g. Sheet1!A1:C20; Sheet2"
             />
-          </label>
+          </div>
 
           <div className="field">
             <span>Selected File</span>